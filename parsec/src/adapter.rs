@@ -1,4 +1,12 @@
-use crate::parser::Parser;
+use std::any::Any;
+use std::cell::{Cell, OnceCell, RefCell};
+use std::marker::PhantomData;
+
+use crate::error::{IntoError, JoinError, Merge};
+use crate::grammar::{Description, Grammar};
+use crate::parser::{Parser, ParserFn};
+use crate::trace::RuleHooks;
+use crate::trampoline::{Continuation, resume, StackParser, Step};
 
 pub struct Map<P, F> {
     parser: P,
@@ -22,8 +30,16 @@ impl<T, P: Parser, F> Parser for Map<P, F>
         let function = &self.f;
         self.parser.do_pars(state).map(|(new_state, t1)| (new_state, function(t1)))
     }
+
+    fn describe(&self, grammar: &mut Grammar) -> Description {
+        self.parser.describe(grammar)
+    }
 }
 
+pub type Keep<P1, P2, T> = Map2<P1, P2, fn(<P1 as Parser>::Value, <P2 as Parser>::Value) -> T>;
+
+pub type Ignore<P1, P2> = Map2<P1, P2, fn(<P1 as Parser>::Value, <P2 as Parser>::Value) -> <P1 as Parser>::Value>;
+
 pub struct Map2<P1, P2, F> {
     parser1: P1,
     parser2: P2,
@@ -32,7 +48,8 @@ pub struct Map2<P1, P2, F> {
 
 impl<T, P1: Parser, P2, F> Map2<P1, P2, F>
     where F: Fn(P1::Value, P2::Value) -> T,
-          P2: Parser<State=P1::State, Error=P1::Error>
+          P2: Parser<State=P1::State>,
+          P1::Error: JoinError<P2::Error>
 {
     pub(in crate) fn new(parser1: P1, parser2: P2, f: F) -> Self {
         Self { parser1, parser2, f }
@@ -41,18 +58,23 @@ impl<T, P1: Parser, P2, F> Map2<P1, P2, F>
 
 impl<T, P1: Parser, P2, F> Parser for Map2<P1, P2, F>
     where F: Fn(P1::Value, P2::Value) -> T,
-          P2: Parser<State=P1::State, Error=P1::Error>
+          P2: Parser<State=P1::State>,
+          P1::Error: JoinError<P2::Error>
 {
     type Value = T;
     type State = P1::State;
-    type Error = P1::Error;
+    type Error = <P1::Error as JoinError<P2::Error>>::Joined;
 
     fn do_pars(&self, state: Self::State) -> Result<(Self::State, T), Self::Error> {
-        self.parser1.do_pars(state).and_then(|(state2, v1)| {
-            self.parser2.do_pars(state2).map(|(new_state, v2)| {
-                (new_state, (&self.f)(v1, v2))
-            })
-        })
+        let (state2, v1) = self.parser1.do_pars(state).map_err(JoinError::join_first)?;
+        match self.parser2.do_pars(state2) {
+            Ok((new_state, v2)) => Ok((new_state, (self.f)(v1, v2))),
+            Err(e) => Err(P1::Error::join_second(e))
+        }
+    }
+
+    fn describe(&self, grammar: &mut Grammar) -> Description {
+        Description::sequence(vec![self.parser1.describe(grammar), self.parser2.describe(grammar)])
     }
 }
 
@@ -62,7 +84,8 @@ pub struct Flatten<P> {
 }
 
 impl<P1: Parser, P2> Flatten<P2>
-    where P2: Parser<Value=P1, State=P1::State, Error=P1::Error>
+    where P2: Parser<Value=P1, State=P1::State>,
+          P2::Error: JoinError<P1::Error>
 {
     pub(in crate) fn new(parser: P2) -> Self {
         Self { inner: parser }
@@ -70,14 +93,21 @@ impl<P1: Parser, P2> Flatten<P2>
 }
 
 impl<P1: Parser, P2> Parser for Flatten<P2>
-    where P2: Parser<Value=P1, State=P1::State, Error=P1::Error>
+    where P2: Parser<Value=P1, State=P1::State>,
+          P2::Error: JoinError<P1::Error>
 {
     type Value = P1::Value;
     type State = P1::State;
-    type Error = P1::Error;
+    type Error = <P2::Error as JoinError<P1::Error>>::Joined;
 
     fn do_pars(&self, state: Self::State) -> Result<(Self::State, Self::Value), Self::Error> {
-        self.inner.do_pars(state).and_then(|(new_state, p1)| p1.do_pars(new_state))
+        let (new_state, p1) = self.inner.do_pars(state).map_err(JoinError::join_first)?;
+        p1.do_pars(new_state).map_err(P2::Error::join_second)
+    }
+
+    /// The inner parser is only known after parsing, so it is opaque.
+    fn describe(&self, grammar: &mut Grammar) -> Description {
+        Description::sequence(vec![self.inner.describe(grammar), Description::Opaque])
     }
 }
 
@@ -86,7 +116,8 @@ pub struct FlatMap<P, F> {
 }
 
 impl<P1: Parser, P2, F> FlatMap<P1, F>
-    where P2: Parser<State=P1::State, Error=P1::Error>,
+    where P2: Parser<State=P1::State>,
+          P1::Error: JoinError<P2::Error>,
           F: Fn(P1::Value) -> P2
 {
     pub(in crate) fn new(parser: P1, f: F) -> Self {
@@ -95,14 +126,501 @@ impl<P1: Parser, P2, F> FlatMap<P1, F>
 }
 
 impl<P1: Parser, P2, F> Parser for FlatMap<P1, F>
-    where P2: Parser<State=P1::State, Error=P1::Error>,
+    where P2: Parser<State=P1::State>,
+          P1::Error: JoinError<P2::Error>,
           F: Fn(P1::Value) -> P2
 {
     type Value = P2::Value;
     type State = P1::State;
-    type Error = P1::Error;
+    type Error = <P1::Error as JoinError<P2::Error>>::Joined;
 
     fn do_pars(&self, state: Self::State) -> Result<(Self::State, Self::Value), Self::Error> {
         self.inner.do_pars(state)
     }
-}
\ No newline at end of file
+
+    fn describe(&self, grammar: &mut Grammar) -> Description {
+        self.inner.describe(grammar)
+    }
+}
+
+pub struct ErrInto<P, E> {
+    parser: P,
+    _error: PhantomData<E>,
+}
+
+impl<P: Parser, E> ErrInto<P, E> where P::Error: IntoError<E> {
+    pub(in crate) fn new(parser: P) -> Self {
+        Self { parser, _error: PhantomData }
+    }
+}
+
+impl<P: Parser, E> Parser for ErrInto<P, E> where P::Error: IntoError<E> {
+    type Value = P::Value;
+    type State = P::State;
+    type Error = E;
+
+    fn do_pars(&self, state: Self::State) -> Result<(Self::State, Self::Value), Self::Error> {
+        self.parser.do_pars(state).map_err(IntoError::into_error)
+    }
+
+    fn describe(&self, grammar: &mut Grammar) -> Description {
+        self.parser.describe(grammar)
+    }
+}
+
+pub struct Or<P1, P2> {
+    parser1: P1,
+    parser2: P2,
+}
+
+impl<P1: Parser, P2> Or<P1, P2>
+    where P2: Parser<Value=P1::Value, State=P1::State>,
+          P1::Error: JoinError<P2::Error>,
+          <P1::Error as JoinError<P2::Error>>::Joined: Merge,
+          P1::State: Clone
+{
+    pub(in crate) fn new(parser1: P1, parser2: P2) -> Self {
+        Self { parser1, parser2 }
+    }
+}
+
+impl<P1: Parser, P2> Parser for Or<P1, P2>
+    where P2: Parser<Value=P1::Value, State=P1::State>,
+          P1::Error: JoinError<P2::Error>,
+          <P1::Error as JoinError<P2::Error>>::Joined: Merge,
+          P1::State: Clone
+{
+    type Value = P1::Value;
+    type State = P1::State;
+    type Error = <P1::Error as JoinError<P2::Error>>::Joined;
+
+    fn do_pars(&self, state: Self::State) -> Result<(Self::State, Self::Value), Self::Error> {
+        match self.parser1.do_pars(state.clone()) {
+            Ok(result) => Ok(result),
+            Err(error1) => self.parser2.do_pars(state)
+                .map_err(|error2| error1.join_first().merge(P1::Error::join_second(error2)))
+        }
+    }
+
+    fn describe(&self, grammar: &mut Grammar) -> Description {
+        Description::choice(vec![self.parser1.describe(grammar), self.parser2.describe(grammar)])
+    }
+}
+
+/// A type-erased parser, see `Parser::boxed`.
+pub struct BoxedParser<'a, V, S, E> {
+    inner: Box<dyn Parser<Value=V, State=S, Error=E> + 'a>,
+}
+
+impl<'a, V, S, E> BoxedParser<'a, V, S, E> {
+    pub(in crate) fn new<P: Parser<Value=V, State=S, Error=E> + 'a>(parser: P) -> Self {
+        Self { inner: Box::new(parser) }
+    }
+}
+
+impl<'a, V, S, E> Parser for BoxedParser<'a, V, S, E> {
+    type Value = V;
+    type State = S;
+    type Error = E;
+
+    fn do_pars(&self, state: Self::State) -> Result<(Self::State, Self::Value), Self::Error> {
+        self.inner.do_pars(state)
+    }
+
+    fn describe(&self, grammar: &mut Grammar) -> Description {
+        self.inner.describe(grammar)
+    }
+}
+
+pub struct FromFn<F, S, V, E> {
+    f: F,
+    _signature: PhantomData<ParserFn<S, V, E>>,
+}
+
+impl<F, S, V, E> FromFn<F, S, V, E> where F: Fn(S) -> Result<(S, V), E> {
+    pub(in crate) fn new(f: F) -> Self {
+        Self { f, _signature: PhantomData }
+    }
+}
+
+impl<F, S, V, E> Parser for FromFn<F, S, V, E> where F: Fn(S) -> Result<(S, V), E> {
+    type Value = V;
+    type State = S;
+    type Error = E;
+
+    fn do_pars(&self, state: Self::State) -> Result<(Self::State, Self::Value), Self::Error> {
+        (self.f)(state)
+    }
+}
+
+pub struct Repeat<P> {
+    parser: P,
+    min: usize,
+    max: Option<usize>,
+}
+
+impl<P: Parser> Repeat<P> where P::State: Clone {
+    pub(in crate) fn new(parser: P, min: usize, max: Option<usize>) -> Self {
+        Self { parser, min, max }
+    }
+}
+
+impl<P: Parser> Parser for Repeat<P> where P::State: Clone {
+    type Value = Vec<P::Value>;
+    type State = P::State;
+    type Error = P::Error;
+
+    fn do_pars(&self, mut state: Self::State) -> Result<(Self::State, Self::Value), Self::Error> {
+        let mut values = vec![];
+        while self.max.is_none_or(|max| values.len() < max) {
+            match self.parser.do_pars(state.clone()) {
+                Ok((new_state, value)) => {
+                    state = new_state;
+                    values.push(value);
+                }
+                Err(error) if values.len() < self.min => return Err(error),
+                Err(_) => break
+            }
+        }
+        Ok((state, values))
+    }
+
+    fn describe(&self, grammar: &mut Grammar) -> Description {
+        Description::repetition(self.parser.describe(grammar), self.min, self.max)
+    }
+}
+
+pub struct Optional<P> {
+    parser: P,
+}
+
+impl<P: Parser> Optional<P> where P::State: Clone {
+    pub(in crate) fn new(parser: P) -> Self {
+        Self { parser }
+    }
+}
+
+impl<P: Parser> Parser for Optional<P> where P::State: Clone {
+    type Value = Option<P::Value>;
+    type State = P::State;
+    type Error = P::Error;
+
+    fn do_pars(&self, state: Self::State) -> Result<(Self::State, Self::Value), Self::Error> {
+        match self.parser.do_pars(state.clone()) {
+            Ok((new_state, value)) => Ok((new_state, Some(value))),
+            Err(_) => Ok((state, None))
+        }
+    }
+
+    fn describe(&self, grammar: &mut Grammar) -> Description {
+        Description::repetition(self.parser.describe(grammar), 0, Some(1))
+    }
+}
+
+pub struct Named<P> {
+    name: String,
+    parser: P,
+}
+
+impl<P: Parser> Named<P> {
+    pub(in crate) fn new(name: String, parser: P) -> Self {
+        Self { name, parser }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl<P: Parser> Parser for Named<P> where P::State: RuleHooks<P::Error> {
+    type Value = P::Value;
+    type State = P::State;
+    type Error = P::Error;
+
+    fn do_pars(&self, mut state: Self::State) -> Result<(Self::State, Self::Value), Self::Error> {
+        state.enter_rule();
+        let trace = state.trace().cloned();
+        if let Some(trace) = &trace {
+            trace.enter(&self.name, state.trace_location());
+        }
+        let result = self.parser.do_pars(state);
+        if let Some(trace) = &trace {
+            match &result {
+                Ok((new_state, _)) => trace.exit(Some(new_state.trace_location()), true),
+                Err(error) => trace.exit(P::State::trace_end(error), false),
+            }
+        }
+        result.map(|(mut new_state, value)| {
+            new_state.exit_rule();
+            (new_state, value)
+        })
+    }
+
+    fn describe(&self, grammar: &mut Grammar) -> Description {
+        if grammar.reserve(&self.name) {
+            let description = self.parser.describe(grammar);
+            grammar.define(&self.name, description);
+        }
+        Description::Rule(self.name.clone())
+    }
+}
+
+/// The parser is built once for every `Lazy`, so a recursive grammar builds one parser per level
+/// of nesting it reaches, each owned by the `Lazy` of the level above. Dropping them is stack
+/// safe anyway, see `Drop for Lazy`.
+pub struct Lazy<F, P: 'static> {
+    build: F,
+    parser: OnceCell<P>,
+}
+
+impl<F: Fn() -> P, P: Parser> Lazy<F, P> {
+    pub(in crate) fn new(build: F) -> Self {
+        Self { build, parser: OnceCell::new() }
+    }
+
+    fn parser(&self) -> &P {
+        self.parser.get_or_init(&self.build)
+    }
+}
+
+impl<F: Fn() -> P, P: Parser> Parser for Lazy<F, P> {
+    type Value = P::Value;
+    type State = P::State;
+    type Error = P::Error;
+
+    fn do_pars(&self, state: Self::State) -> Result<(Self::State, Self::Value), Self::Error> {
+        self.parser().do_pars(state)
+    }
+
+    fn describe(&self, grammar: &mut Grammar) -> Description {
+        self.parser().describe(grammar)
+    }
+}
+
+thread_local! {
+    static DROPPING_LAZY: Cell<bool> = const { Cell::new(false) };
+    static PENDING_LAZY_DROPS: RefCell<Vec<Box<dyn Any>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Dropping the parsers of a deeply nested grammar one inside the other would recurse as deep as
+/// the nesting. Instead, the outermost `Lazy` being dropped drops the parsers of the nested ones
+/// one after another, which only queue theirs.
+impl<F, P: 'static> Drop for Lazy<F, P> {
+    fn drop(&mut self) {
+        let parser = match self.parser.take() {
+            Some(parser) => parser,
+            None => return,
+        };
+        match DROPPING_LAZY.try_with(|dropping| dropping.replace(true)) {
+            Ok(false) => {
+                drop(parser);
+                while let Some(pending) = PENDING_LAZY_DROPS.with(|pending| pending.borrow_mut().pop()) {
+                    drop(pending);
+                }
+                DROPPING_LAZY.with(|dropping| dropping.set(false));
+            }
+            Ok(true) => PENDING_LAZY_DROPS.with(|pending| pending.borrow_mut().push(Box::new(parser))),
+            // The thread is shutting down, so there is no queue left.
+            Err(_) => drop(parser),
+        }
+    }
+}
+
+impl<'d, T: 'd, P, F> StackParser<'d> for Map<P, F>
+    where P: StackParser<'d>,
+          P::State: 'd,
+          P::Error: 'd,
+          F: Fn(P::Value) -> T
+{
+    fn bounce<'a>(&'a self, state: Self::State, continuation: Continuation<'a, Self>) -> Step<'a>
+        where 'd: 'a
+    {
+        self.parser.bounce(state, Box::new(move |result| {
+            resume(continuation, result.map(|(new_state, value)| (new_state, (self.f)(value))))
+        }))
+    }
+}
+
+impl<'d, T: 'd, P1, P2, F> StackParser<'d> for Map2<P1, P2, F>
+    where P1: StackParser<'d>,
+          P1::State: 'd,
+          P1::Value: 'd,
+          P1::Error: 'd,
+          P2: StackParser<'d, State=P1::State>,
+          P2::Error: 'd,
+          P1::Error: JoinError<P2::Error>,
+          F: Fn(P1::Value, P2::Value) -> T
+{
+    fn bounce<'a>(&'a self, state: Self::State, continuation: Continuation<'a, Self>) -> Step<'a>
+        where 'd: 'a
+    {
+        self.parser1.bounce(state, Box::new(move |result1| match result1 {
+            Err(error) => resume(continuation, Err(error.join_first())),
+            Ok((state2, v1)) => self.parser2.bounce(state2, Box::new(move |result2| {
+                resume(continuation, match result2 {
+                    Ok((new_state, v2)) => Ok((new_state, (self.f)(v1, v2))),
+                    Err(e) => Err(P1::Error::join_second(e))
+                })
+            }))
+        }))
+    }
+}
+
+/// The parser produced at parse time is run directly, only the first parser is trampolined.
+impl<'d, P1, P2, F> StackParser<'d> for FlatMap<P1, F>
+    where P1: StackParser<'d>,
+          P1::State: 'd,
+          P1::Error: 'd,
+          P2: Parser<State=P1::State>,
+          P2::Value: 'd,
+          P2::Error: 'd,
+          P1::Error: JoinError<P2::Error>,
+          F: Fn(P1::Value) -> P2
+{
+    fn bounce<'a>(&'a self, state: Self::State, continuation: Continuation<'a, Self>) -> Step<'a>
+        where 'd: 'a
+    {
+        let map = &self.inner.inner;
+        map.parser.bounce(state, Box::new(move |result| {
+            resume(continuation, result.map_err(JoinError::join_first).and_then(|(new_state, value)| {
+                (map.f)(value).do_pars(new_state).map_err(P1::Error::join_second)
+            }))
+        }))
+    }
+}
+
+impl<'d, P, E: 'd> StackParser<'d> for ErrInto<P, E>
+    where P: StackParser<'d>,
+          P::State: 'd,
+          P::Value: 'd,
+          P::Error: IntoError<E>
+{
+    fn bounce<'a>(&'a self, state: Self::State, continuation: Continuation<'a, Self>) -> Step<'a>
+        where 'd: 'a
+    {
+        self.parser.bounce(state, Box::new(move |result| {
+            resume(continuation, result.map_err(IntoError::into_error))
+        }))
+    }
+}
+
+impl<'d, P1, P2> StackParser<'d> for Or<P1, P2>
+    where P1: StackParser<'d>,
+          P1::State: Clone + 'd,
+          P1::Value: 'd,
+          P1::Error: JoinError<P2::Error> + 'd,
+          <P1::Error as JoinError<P2::Error>>::Joined: Merge,
+          P2: StackParser<'d, Value=P1::Value, State=P1::State>,
+          P2::Error: 'd
+{
+    fn bounce<'a>(&'a self, state: Self::State, continuation: Continuation<'a, Self>) -> Step<'a>
+        where 'd: 'a
+    {
+        self.parser1.bounce(state.clone(), Box::new(move |result1| match result1 {
+            Ok(result) => resume(continuation, Ok(result)),
+            Err(error1) => self.parser2.bounce(state, Box::new(move |result2| {
+                resume(continuation, result2.map_err(|error2| error1.join_first().merge(P1::Error::join_second(error2))))
+            }))
+        }))
+    }
+}
+
+impl<'d, F, S: 'd, V: 'd, E: 'd> StackParser<'d> for FromFn<F, S, V, E> where F: Fn(S) -> Result<(S, V), E> {
+    fn bounce<'a>(&'a self, state: Self::State, continuation: Continuation<'a, Self>) -> Step<'a>
+        where 'd: 'a
+    {
+        Step::more(move || continuation((self.f)(state)))
+    }
+}
+
+impl<'d, P> Repeat<P>
+    where P: StackParser<'d>,
+          P::State: Clone + 'd,
+          P::Value: 'd,
+          P::Error: 'd
+{
+    fn bounce_from<'a>(&'a self, state: P::State, mut values: Vec<P::Value>, continuation: Continuation<'a, Self>) -> Step<'a>
+        where 'd: 'a
+    {
+        if self.max.is_some_and(|max| values.len() >= max) {
+            return resume(continuation, Ok((state, values)));
+        }
+        Step::more(move || self.parser.bounce(state.clone(), Box::new(move |result| match result {
+            Ok((new_state, value)) => {
+                values.push(value);
+                self.bounce_from(new_state, values, continuation)
+            }
+            Err(error) if values.len() < self.min => resume(continuation, Err(error)),
+            Err(_) => resume(continuation, Ok((state, values)))
+        })))
+    }
+}
+
+impl<'d, P> StackParser<'d> for Repeat<P>
+    where P: StackParser<'d>,
+          P::State: Clone + 'd,
+          P::Value: 'd,
+          P::Error: 'd
+{
+    fn bounce<'a>(&'a self, state: Self::State, continuation: Continuation<'a, Self>) -> Step<'a>
+        where 'd: 'a
+    {
+        self.bounce_from(state, vec![], continuation)
+    }
+}
+
+impl<'d, P> StackParser<'d> for Optional<P>
+    where P: StackParser<'d>,
+          P::State: Clone + 'd,
+          P::Value: 'd,
+          P::Error: 'd
+{
+    fn bounce<'a>(&'a self, state: Self::State, continuation: Continuation<'a, Self>) -> Step<'a>
+        where 'd: 'a
+    {
+        self.parser.bounce(state.clone(), Box::new(move |result| {
+            resume(continuation, match result {
+                Ok((new_state, value)) => Ok((new_state, Some(value))),
+                Err(_) => Ok((state, None))
+            })
+        }))
+    }
+}
+
+impl<'d, P> StackParser<'d> for Named<P>
+    where P: StackParser<'d>,
+          P::State: RuleHooks<P::Error> + 'd,
+          P::Value: 'd,
+          P::Error: 'd
+{
+    fn bounce<'a>(&'a self, mut state: Self::State, continuation: Continuation<'a, Self>) -> Step<'a>
+        where 'd: 'a
+    {
+        state.enter_rule();
+        let trace = state.trace().cloned();
+        if let Some(trace) = &trace {
+            trace.enter(&self.name, state.trace_location());
+        }
+        self.parser.bounce(state, Box::new(move |result| {
+            if let Some(trace) = &trace {
+                match &result {
+                    Ok((new_state, _)) => trace.exit(Some(new_state.trace_location()), true),
+                    Err(error) => trace.exit(P::State::trace_end(error), false),
+                }
+            }
+            resume(continuation, result.map(|(mut new_state, value)| {
+                new_state.exit_rule();
+                (new_state, value)
+            }))
+        }))
+    }
+}
+
+/// Recursion passes through here, so the inner parser is only started on the next bounce.
+impl<'d, F: Fn() -> P, P: StackParser<'d>> StackParser<'d> for Lazy<F, P> where P::State: 'd {
+    fn bounce<'a>(&'a self, state: Self::State, continuation: Continuation<'a, Self>) -> Step<'a>
+        where 'd: 'a
+    {
+        Step::more(move || self.parser().bounce(state, continuation))
+    }
+}
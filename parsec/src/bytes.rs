@@ -0,0 +1,151 @@
+//! Parsing of binary or mostly-ASCII input that isn't necessarily UTF-8, e.g. network protocols.
+//!
+//! Locations count bytes: the column is the byte in the row and rows end at `\n`.
+
+use std::convert::Infallible;
+use std::rc::Rc;
+
+use crate::error::ParseError;
+use crate::parser::{from_fn, Parser};
+use crate::text::location::{Located, Location};
+use crate::trace::{RuleHooks, Trace, TraceError};
+
+#[derive(Clone, Debug)]
+pub struct ByteState {
+    input: Rc<Vec<u8>>,
+    /// Where the input ends for the parsers, see `with_end`.
+    end: usize,
+    location: Location,
+    trace: Option<Trace>,
+}
+
+impl ByteState {
+    pub fn new(input: impl Into<Vec<u8>>) -> Self {
+        let input: Vec<u8> = input.into();
+        Self { end: input.len(), input: Rc::new(input), location: Location::start(), trace: None }
+    }
+
+    /// Records every `named` rule that is tried while parsing into `trace`.
+    pub fn with_trace(self, trace: Trace) -> Self {
+        Self { trace: Some(trace), ..self }
+    }
+
+    /// Hides the input after the byte offset `end`, so parsers stop there as if the input
+    /// ended. Offsets past the actual input are clamped to its length.
+    pub(crate) fn with_end(self, end: usize) -> Self {
+        Self { end: end.min(self.input.len()), ..self }
+    }
+
+    pub(crate) fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn peek(&self) -> Option<u8> {
+        self.remaining().first().copied()
+    }
+
+    pub fn advance(&mut self) {
+        self.location = match self.peek() {
+            None => return,
+            Some(b'\n') => self.location.new_line(1),
+            Some(_) => self.location.increment(1),
+        };
+    }
+
+    /// The input that is left.
+    pub fn remaining(&self) -> &[u8] {
+        &self.input[self.location.byte_offset().min(self.end)..self.end]
+    }
+
+    /// The input from `start` up to this state.
+    pub fn since(&self, start: &Location) -> &[u8] {
+        &self.input[start.byte_offset()..self.location.byte_offset()]
+    }
+
+    pub fn locate_at_exactly<T>(&self, target: T) -> Located<T> {
+        self.locate(self.location.clone(), target)
+    }
+
+    pub fn locate<T>(&self, start_location: Location, target: T) -> Located<T> {
+        start_location.locate(self.location.clone(), target)
+    }
+
+    pub fn location(&self) -> &Location {
+        &self.location
+    }
+}
+
+impl<E: TraceError> RuleHooks<E> for ByteState {
+    fn trace(&self) -> Option<&Trace> {
+        self.trace.as_ref()
+    }
+
+    fn trace_location(&self) -> Location {
+        self.location.clone()
+    }
+
+    fn trace_end(error: &E) -> Option<Location> {
+        error.trace_end()
+    }
+}
+
+pub trait ByteParser<E = ParseError>: Parser<State=ByteState, Error=Located<E>> {
+    fn pars(&self, input: impl Into<Vec<u8>>) -> Result<Self::Value, Self::Error> {
+        self.pars_with(ByteState::new(input))
+    }
+
+    /// Parses starting from a configured state, e.g. one with a trace.
+    fn pars_with(&self, state: ByteState) -> Result<Self::Value, Self::Error> {
+        self.do_pars(state).map(|(_, value)| value)
+    }
+}
+
+impl<E, P: Parser<State=ByteState, Error=Located<E>>> ByteParser<E> for P {}
+
+/// Exactly the bytes of `tag`.
+pub fn tag(tag: &'static [u8]) -> impl Parser<Value=(), State=ByteState, Error=Located<ParseError>> {
+    from_fn(move |mut state: ByteState| {
+        if !state.remaining().starts_with(tag) {
+            return Err(state.locate_at_exactly(ParseError::expected_token(String::from_utf8_lossy(tag))));
+        }
+        for _ in tag {
+            state.advance();
+        }
+        Ok((state, ()))
+    })
+}
+
+/// The bytes up to the first one that doesn't match `predicate`.
+pub fn take_while<F>(predicate: F) -> impl Parser<Value=Vec<u8>, State=ByteState, Error=Infallible>
+    where F: Fn(u8) -> bool
+{
+    from_fn(move |mut state: ByteState| {
+        let start = state.location().clone();
+        while state.peek().is_some_and(&predicate) {
+            state.advance();
+        }
+        let taken = state.since(&start).to_vec();
+        Ok((state, taken))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bytes::{ByteParser, tag, take_while};
+    use crate::error::ParseError;
+    use crate::parser::Parser;
+    use crate::text::location::Location;
+
+    #[test]
+    fn bytes_and_locations() {
+        let parser = tag(b"GET ").map2(take_while(|b| b != b'\n'), |_, rest| rest).ignore(tag(b"\n\xff"));
+        assert_eq!(Ok(b"/\xe9t\xe9".to_vec()), parser.pars(&b"GET /\xe9t\xe9\n\xff"[..]));
+
+        let error = parser.pars(&b"GET /\nx"[..]).unwrap_err();
+        assert_eq!(ParseError::expected_token("\n\u{fffd}"), *error.target());
+        assert_eq!(Location::new(5, 6, 1), error.source_range().start);
+
+        let error = tag(b"a").ignore(tag(b"\nb")).ignore(tag(b"c")).pars("a\nbd").unwrap_err();
+        assert_eq!(Location::new(3, 2, 2), error.source_range().start);
+    }
+}
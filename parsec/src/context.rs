@@ -0,0 +1,139 @@
+use std::any::Any;
+use std::convert::Infallible;
+use std::marker::PhantomData;
+
+use polymap::{Key, PolyMap};
+
+use crate::grammar::{Description, Grammar};
+use crate::parser::Parser;
+use crate::trampoline::{Continuation, StackParser, Step};
+
+/// States that carry user defined values, like a symbol table or a strict mode flag.
+///
+/// The context is part of the state, so it is restored together with the input position when a
+/// parser backtracks.
+pub trait ContextState {
+    fn context(&self) -> &PolyMap;
+
+    fn context_mut(&mut self) -> &mut PolyMap;
+}
+
+pub struct GetCtx<K, T, S> {
+    key: K,
+    _value: PhantomData<T>,
+    _state: PhantomData<S>,
+}
+
+/// Reads the value of `key` from the context without consuming input.
+pub fn get_ctx<K: Key<T>, T: Any + Clone, S: ContextState>(key: K) -> GetCtx<K, T, S> {
+    GetCtx { key, _value: PhantomData, _state: PhantomData }
+}
+
+impl<K: Key<T>, T: Any + Clone, S: ContextState> Parser for GetCtx<K, T, S> {
+    type Value = Option<T>;
+    type State = S;
+    type Error = Infallible;
+
+    fn do_pars(&self, state: Self::State) -> Result<(Self::State, Self::Value), Self::Error> {
+        let value = state.context().get(&self.key).cloned();
+        Ok((state, value))
+    }
+
+    fn describe(&self, _grammar: &mut Grammar) -> Description {
+        Description::Empty
+    }
+}
+
+impl<'d, K: Key<T>, T: Any + Clone, S: ContextState + 'd> StackParser<'d> for GetCtx<K, T, S> {
+    fn bounce<'a>(&'a self, state: Self::State, continuation: Continuation<'a, Self>) -> Step<'a>
+        where 'd: 'a
+    {
+        Step::more(move || continuation(self.do_pars(state)))
+    }
+}
+
+pub struct WithCtx<K, T, S> {
+    key: K,
+    value: T,
+    _state: PhantomData<S>,
+}
+
+/// Sets `key` to `value` for the parsers that run after this one.
+pub fn with_ctx<K: Key<T>, T: Any + Clone, S: ContextState>(key: K, value: T) -> WithCtx<K, T, S> {
+    WithCtx { key, value, _state: PhantomData }
+}
+
+impl<K: Key<T>, T: Any + Clone, S: ContextState> Parser for WithCtx<K, T, S> {
+    type Value = ();
+    type State = S;
+    type Error = Infallible;
+
+    fn do_pars(&self, mut state: Self::State) -> Result<(Self::State, Self::Value), Self::Error> {
+        state.context_mut().insert(&self.key, self.value.clone());
+        Ok((state, ()))
+    }
+
+    fn describe(&self, _grammar: &mut Grammar) -> Description {
+        Description::Empty
+    }
+}
+
+impl<'d, K: Key<T>, T: Any + Clone, S: ContextState + 'd> StackParser<'d> for WithCtx<K, T, S> {
+    fn bounce<'a>(&'a self, state: Self::State, continuation: Continuation<'a, Self>) -> Step<'a>
+        where 'd: 'a
+    {
+        Step::more(move || continuation(self.do_pars(state)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use polymap::{NormalKey, PolyMap};
+
+    use crate::context::{get_ctx, with_ctx};
+    use crate::error::ParseError;
+    use crate::parser::Parser;
+    use crate::text::location::{Located, Location};
+    use crate::text::text_parser::{integer, TextParser, TextState, token, whitespace};
+
+    const STRICT: &NormalKey<bool> = &NormalKey::new(1);
+    const DECLARED: &NormalKey<BTreeSet<i64>> = &NormalKey::new(2);
+
+    #[test]
+    fn reads_initial_context() {
+        let mut context = PolyMap::new();
+        context.insert(STRICT, true);
+        let strict = get_ctx(STRICT).map(|strict| strict.unwrap_or(false)).err_into::<Located<ParseError>>();
+        assert_eq!(Ok(true), strict.pars_with(TextState::new("").with_context(context)));
+        assert_eq!(Ok(false), strict.pars(""));
+    }
+
+    #[test]
+    fn writes_are_undone_on_backtracking() {
+        let declare = token("let ").map2(integer(), |_, number| number).flat_map(|number| {
+            get_ctx(DECLARED).flat_map(move |declared| {
+                let mut declared = declared.unwrap_or_default();
+                declared.insert(number);
+                with_ctx(DECLARED, declared)
+            })
+        }).ignore(token(";"));
+        let statement = declare.or(token("let ").map(|_| ()).ignore(token("x;")));
+        let program = statement.ignore(whitespace()).many().map2(get_ctx(DECLARED), |_, declared| declared);
+
+        let declared = program.pars("let 1; let 2; let x; let 3").map(Option::unwrap_or_default);
+        assert_eq!(Ok(BTreeSet::from([1, 2])), declared);
+    }
+
+    #[test]
+    fn later_parsers_see_writes() {
+        let checked = token("mode ").ignore(with_ctx(STRICT, true)).map2(get_ctx(STRICT), |_, strict| strict).flat_map(|strict| {
+            if strict == Some(true) { token("strict") } else { token("lenient") }
+        });
+        let location = Location::new(5, 6, 1);
+        assert_eq!(Ok(String::from("strict")), checked.pars("mode strict"));
+        assert_eq!(Err(location.locate(Location::new(6, 7, 1), ParseError::expected_token("strict"))),
+                   checked.pars("mode lenient"));
+    }
+}
@@ -0,0 +1,517 @@
+use std::cell::RefCell;
+use std::fmt::{Display, Formatter, Write};
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::grammar::{Description, Grammar};
+use crate::parser::Parser;
+use crate::trampoline::{Continuation, resume, StackParser, Step};
+
+/// The kind of a node or token of a concrete syntax tree.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct SyntaxKind(pub &'static str);
+
+impl SyntaxKind {
+    /// The node around the whole input.
+    pub const ROOT: SyntaxKind = SyntaxKind("ROOT");
+    /// Input that was consumed without being part of a token, like skipped whitespace.
+    pub const TRIVIA: SyntaxKind = SyntaxKind("TRIVIA");
+    /// Input after the end of the parse.
+    pub const UNPARSED: SyntaxKind = SyntaxKind("UNPARSED");
+}
+
+impl Display for SyntaxKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct GreenToken {
+    kind: SyntaxKind,
+    text: String,
+}
+
+impl GreenToken {
+    pub fn new(kind: SyntaxKind, text: impl Into<String>) -> Self {
+        Self { kind, text: text.into() }
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum GreenElement {
+    Node(Rc<GreenNode>),
+    Token(Rc<GreenToken>),
+}
+
+impl GreenElement {
+    pub fn kind(&self) -> SyntaxKind {
+        match self {
+            GreenElement::Node(node) => node.kind,
+            GreenElement::Token(token) => token.kind,
+        }
+    }
+
+    pub fn text_len(&self) -> usize {
+        match self {
+            GreenElement::Node(node) => node.text_len,
+            GreenElement::Token(token) => token.text.len(),
+        }
+    }
+}
+
+/// An immutable node of the tree without a position, so equal subtrees can be shared.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct GreenNode {
+    kind: SyntaxKind,
+    text_len: usize,
+    children: Vec<GreenElement>,
+}
+
+impl GreenNode {
+    pub fn new(kind: SyntaxKind, children: Vec<GreenElement>) -> Self {
+        let text_len = children.iter().map(GreenElement::text_len).sum();
+        Self { kind, text_len, children }
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.kind
+    }
+
+    pub fn text_len(&self) -> usize {
+        self.text_len
+    }
+
+    pub fn children(&self) -> &[GreenElement] {
+        &self.children
+    }
+}
+
+/// Prints the text of all tokens, which is the input the tree was built from.
+impl Display for GreenNode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.children.iter().try_for_each(|child| match child {
+            GreenElement::Node(node) => node.fmt(f),
+            GreenElement::Token(token) => f.write_str(&token.text),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct NodeData {
+    green: Rc<GreenNode>,
+    offset: usize,
+    parent: Option<SyntaxNode>,
+}
+
+/// A green node together with its position and parent, created on demand while walking the
+/// tree. Clones are cheap.
+#[derive(Debug, Clone)]
+pub struct SyntaxNode {
+    data: Rc<NodeData>,
+}
+
+impl SyntaxNode {
+    pub fn new_root(green: Rc<GreenNode>) -> Self {
+        Self { data: Rc::new(NodeData { green, offset: 0, parent: None }) }
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.data.green.kind
+    }
+
+    pub fn green(&self) -> &Rc<GreenNode> {
+        &self.data.green
+    }
+
+    /// The byte range of the node in the input.
+    pub fn text_range(&self) -> Range<usize> {
+        self.data.offset..self.data.offset + self.data.green.text_len
+    }
+
+    pub fn parent(&self) -> Option<&SyntaxNode> {
+        self.data.parent.as_ref()
+    }
+
+    pub fn children(&self) -> Vec<SyntaxElement> {
+        let mut offset = self.data.offset;
+        self.data.green.children.iter().map(|child| {
+            let element = match child {
+                GreenElement::Node(node) => SyntaxElement::Node(SyntaxNode {
+                    data: Rc::new(NodeData { green: node.clone(), offset, parent: Some(self.clone()) })
+                }),
+                GreenElement::Token(token) => SyntaxElement::Token(SyntaxToken {
+                    green: token.clone(),
+                    offset,
+                    parent: self.clone(),
+                }),
+            };
+            offset += child.text_len();
+            element
+        }).collect()
+    }
+
+    pub fn child_nodes(&self) -> Vec<SyntaxNode> {
+        self.children().into_iter().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        }).collect()
+    }
+
+    /// All tokens below this node in input order.
+    pub fn tokens(&self) -> Vec<SyntaxToken> {
+        let mut tokens = vec![];
+        for child in self.children() {
+            match child {
+                SyntaxElement::Node(node) => tokens.extend(node.tokens()),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+        tokens
+    }
+
+    /// One line per node and token, indented by depth, e.g. `NUMBER@0..2 "12"`.
+    pub fn debug_tree(&self) -> String {
+        fn write_node(node: &SyntaxNode, depth: usize, output: &mut String) {
+            let range = node.text_range();
+            writeln!(output, "{:indent$}{}@{}..{}", "", node.kind(), range.start, range.end, indent = depth * 2)
+                .expect("write to string");
+            for child in node.children() {
+                match child {
+                    SyntaxElement::Node(child) => write_node(&child, depth + 1, output),
+                    SyntaxElement::Token(token) => {
+                        let range = token.text_range();
+                        writeln!(output, "{:indent$}{}@{}..{} {:?}", "", token.kind(), range.start, range.end,
+                                 token.text(), indent = (depth + 1) * 2).expect("write to string");
+                    }
+                }
+            }
+        }
+
+        let mut output = String::new();
+        write_node(self, 0, &mut output);
+        output
+    }
+}
+
+impl Display for SyntaxNode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.data.green.fmt(f)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SyntaxToken {
+    green: Rc<GreenToken>,
+    offset: usize,
+    parent: SyntaxNode,
+}
+
+impl SyntaxToken {
+    pub fn kind(&self) -> SyntaxKind {
+        self.green.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.green.text
+    }
+
+    pub fn text_range(&self) -> Range<usize> {
+        self.offset..self.offset + self.green.text.len()
+    }
+
+    pub fn parent(&self) -> &SyntaxNode {
+        &self.parent
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+/// What a parser in CST mode reports, with byte offsets into the input.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum CstEvent {
+    Start { kind: SyntaxKind, offset: usize },
+    Token { kind: SyntaxKind, start: usize, end: usize },
+    Finish { offset: usize },
+}
+
+impl CstEvent {
+    /// The event with its offsets moved by `to - from`.
+    pub(crate) fn moved(&self, from: usize, to: usize) -> Self {
+        let move_offset = |offset: usize| offset - from + to;
+        match *self {
+            CstEvent::Start { kind, offset } => CstEvent::Start { kind, offset: move_offset(offset) },
+            CstEvent::Token { kind, start, end } => CstEvent::Token { kind, start: move_offset(start), end: move_offset(end) },
+            CstEvent::Finish { offset } => CstEvent::Finish { offset: move_offset(offset) },
+        }
+    }
+}
+
+/// The events of one parse. Clones share the log but remember how much of it belongs to them,
+/// so a state that backtracks drops the events of the abandoned branch on its next record.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CstRecorder {
+    events: Rc<RefCell<Vec<CstEvent>>>,
+    len: usize,
+}
+
+impl CstRecorder {
+    pub(crate) fn record(&mut self, event: CstEvent) {
+        let mut events = self.events.borrow_mut();
+        events.truncate(self.len);
+        events.push(event);
+        self.len += 1;
+    }
+
+    /// The number of events recorded by this clone.
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The events this clone recorded after it had recorded `start` events.
+    pub(crate) fn events_since(&self, start: usize) -> Vec<CstEvent> {
+        self.events.borrow()[start..self.len].to_vec()
+    }
+
+    /// Builds the tree of the recorded events. Input between tokens becomes `TRIVIA` and the
+    /// rest of the input after `end` becomes `UNPARSED`, so the tree prints as `input`.
+    pub(crate) fn build(&self, input: &str, end: usize) -> SyntaxNode {
+        let mut builder = TreeBuilder { input, cursor: 0, stack: vec![(SyntaxKind::ROOT, vec![])] };
+        for event in &self.events.borrow()[..self.len] {
+            match *event {
+                CstEvent::Start { kind, offset } => {
+                    builder.trivia_until(offset);
+                    builder.stack.push((kind, vec![]));
+                }
+                CstEvent::Token { kind, start, end } => {
+                    builder.trivia_until(start);
+                    builder.token(kind, end);
+                }
+                CstEvent::Finish { offset } => {
+                    builder.trivia_until(offset);
+                    builder.finish_node();
+                }
+            }
+        }
+        builder.trivia_until(end);
+        builder.token(SyntaxKind::UNPARSED, input.len());
+        while builder.stack.len() > 1 {
+            builder.finish_node();
+        }
+        let (kind, children) = builder.stack.pop().expect("root node");
+        SyntaxNode::new_root(Rc::new(GreenNode::new(kind, children)))
+    }
+}
+
+struct TreeBuilder<'i> {
+    input: &'i str,
+    cursor: usize,
+    stack: Vec<(SyntaxKind, Vec<GreenElement>)>,
+}
+
+impl TreeBuilder<'_> {
+    fn trivia_until(&mut self, offset: usize) {
+        self.token(SyntaxKind::TRIVIA, offset);
+    }
+
+    /// Adds the input from the cursor to `end` as a token. Input that is already part of a token
+    /// is not added again, so nested tokens are dropped.
+    fn token(&mut self, kind: SyntaxKind, end: usize) {
+        if end <= self.cursor {
+            return;
+        }
+        let token = GreenToken::new(kind, &self.input[self.cursor..end]);
+        self.stack.last_mut().expect("root node").1.push(GreenElement::Token(Rc::new(token)));
+        self.cursor = end;
+    }
+
+    fn finish_node(&mut self) {
+        if self.stack.len() > 1 {
+            let (kind, children) = self.stack.pop().expect("open node");
+            let node = GreenElement::Node(Rc::new(GreenNode::new(kind, children)));
+            self.stack.last_mut().expect("root node").1.push(node);
+        }
+    }
+}
+
+/// States that can record a concrete syntax tree.
+pub trait CstState {
+    fn cst_offset(&self) -> usize;
+
+    fn record(&mut self, event: CstEvent);
+}
+
+impl CstState for () {
+    fn cst_offset(&self) -> usize {
+        0
+    }
+
+    fn record(&mut self, _event: CstEvent) {}
+}
+
+pub struct Node<P> {
+    kind: SyntaxKind,
+    parser: P,
+}
+
+/// Wraps everything `parser` consumes into a node of `kind`.
+pub fn node<P: Parser>(kind: SyntaxKind, parser: P) -> Node<P> where P::State: CstState {
+    Node { kind, parser }
+}
+
+impl<P: Parser> Parser for Node<P> where P::State: CstState {
+    type Value = P::Value;
+    type State = P::State;
+    type Error = P::Error;
+
+    fn do_pars(&self, mut state: Self::State) -> Result<(Self::State, Self::Value), Self::Error> {
+        state.record(CstEvent::Start { kind: self.kind, offset: state.cst_offset() });
+        self.parser.do_pars(state).map(|(mut new_state, value)| {
+            new_state.record(CstEvent::Finish { offset: new_state.cst_offset() });
+            (new_state, value)
+        })
+    }
+
+    fn describe(&self, grammar: &mut Grammar) -> Description {
+        self.parser.describe(grammar)
+    }
+}
+
+impl<'d, P> StackParser<'d> for Node<P>
+    where P: StackParser<'d>,
+          P::State: CstState + 'd,
+          P::Value: 'd,
+          P::Error: 'd
+{
+    fn bounce<'a>(&'a self, mut state: Self::State, continuation: Continuation<'a, Self>) -> Step<'a>
+        where 'd: 'a
+    {
+        state.record(CstEvent::Start { kind: self.kind, offset: state.cst_offset() });
+        self.parser.bounce(state, Box::new(move |result| {
+            resume(continuation, result.map(|(mut new_state, value)| {
+                new_state.record(CstEvent::Finish { offset: new_state.cst_offset() });
+                (new_state, value)
+            }))
+        }))
+    }
+}
+
+pub struct Leaf<P> {
+    kind: SyntaxKind,
+    parser: P,
+}
+
+/// Turns everything `parser` consumes into a single token of `kind`. Leaves should not be
+/// nested, the input of an inner leaf is not repeated in the outer one.
+pub fn leaf<P: Parser>(kind: SyntaxKind, parser: P) -> Leaf<P> where P::State: CstState {
+    Leaf { kind, parser }
+}
+
+impl<P: Parser> Parser for Leaf<P> where P::State: CstState {
+    type Value = P::Value;
+    type State = P::State;
+    type Error = P::Error;
+
+    fn do_pars(&self, state: Self::State) -> Result<(Self::State, Self::Value), Self::Error> {
+        let start = state.cst_offset();
+        self.parser.do_pars(state).map(|(mut new_state, value)| {
+            new_state.record(CstEvent::Token { kind: self.kind, start, end: new_state.cst_offset() });
+            (new_state, value)
+        })
+    }
+
+    fn describe(&self, grammar: &mut Grammar) -> Description {
+        self.parser.describe(grammar)
+    }
+}
+
+impl<'d, P> StackParser<'d> for Leaf<P>
+    where P: StackParser<'d>,
+          P::State: CstState + 'd,
+          P::Value: 'd,
+          P::Error: 'd
+{
+    fn bounce<'a>(&'a self, state: Self::State, continuation: Continuation<'a, Self>) -> Step<'a>
+        where 'd: 'a
+    {
+        let start = state.cst_offset();
+        self.parser.bounce(state, Box::new(move |result| {
+            resume(continuation, result.map(|(mut new_state, value)| {
+                new_state.record(CstEvent::Token { kind: self.kind, start, end: new_state.cst_offset() });
+                (new_state, value)
+            }))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cst::{leaf, node, SyntaxElement, SyntaxKind};
+    use crate::parser::{lazy, Parser};
+    use crate::adapter::BoxedParser;
+    use crate::error::ParseError;
+    use crate::text::location::Located;
+    use crate::text::text_parser::{Chop, integer, TextParser, TextState, token, whitespace};
+
+    const NUMBER: SyntaxKind = SyntaxKind("NUMBER");
+    const PLUS: SyntaxKind = SyntaxKind("PLUS");
+    const PAREN: SyntaxKind = SyntaxKind("PAREN");
+    const COMMENT: SyntaxKind = SyntaxKind("COMMENT");
+    const SUM: SyntaxKind = SyntaxKind("SUM");
+    const GROUP: SyntaxKind = SyntaxKind("GROUP");
+
+    fn sum<'a>() -> BoxedParser<'a, i64, TextState, Located<ParseError>> {
+        let group = node(GROUP, leaf(PAREN, token("("))
+            .map2(lazy(sum), |_, value| value)
+            .ignore(leaf(PAREN, token(")"))));
+        let term = leaf(NUMBER, integer()).or(group).ignore(whitespace());
+        let comment = leaf(COMMENT, token("#").ignore(Chop::while_con(|c| c != '\n'))).ignore(whitespace());
+        node(SUM, term.map2(leaf(PLUS, token("+")).ignore(whitespace()).map2(lazy(sum), |_, rhs| rhs).optional(),
+                            |lhs, rhs| lhs + rhs.unwrap_or(0)))
+            .ignore(comment.optional())
+            .boxed()
+    }
+
+    #[test]
+    fn round_trips_input() {
+        let input = "1 + (2 +3) + 4  # four\n\t";
+        let (value, tree) = sum().pars_cst(input).expect("valid sum");
+        assert_eq!(10, value);
+        assert_eq!(input, tree.to_string());
+        assert_eq!(0..input.len(), tree.text_range());
+
+        let comments: Vec<_> = tree.tokens().into_iter().filter(|token| token.kind() == COMMENT).collect();
+        assert_eq!(1, comments.len());
+        assert_eq!("# four", comments[0].text());
+        assert_eq!(16..22, comments[0].text_range());
+    }
+
+    #[test]
+    fn backtracked_events_are_dropped() {
+        let (_, tree) = sum().pars_cst("(1) x").expect("valid sum");
+        assert_eq!("ROOT@0..5\n  SUM@0..4\n    GROUP@0..3\n      PAREN@0..1 \"(\"\n      SUM@1..2\n        NUMBER@1..2 \"1\"\n      \
+                    PAREN@2..3 \")\"\n    TRIVIA@3..4 \" \"\n  UNPARSED@4..5 \"x\"\n", tree.debug_tree());
+
+        let group = &tree.child_nodes()[0].child_nodes()[0];
+        assert_eq!(GROUP, group.kind());
+        assert_eq!(Some(SUM), group.parent().map(|parent| parent.kind()));
+        assert!(matches!(&group.children()[0], SyntaxElement::Token(token) if token.text() == "("));
+
+        let first = node(SyntaxKind("FIRST"), leaf(PLUS, token("+")).ignore(token("a")));
+        let second = node(SyntaxKind("SECOND"), leaf(PLUS, token("+")).ignore(token("b")));
+        let (_, tree) = first.or(second).pars_cst("+b").expect("second alternative");
+        assert_eq!("ROOT@0..2\n  SECOND@0..2\n    PLUS@0..1 \"+\"\n    TRIVIA@1..2 \"b\"\n", tree.debug_tree());
+    }
+}
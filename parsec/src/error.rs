@@ -0,0 +1,320 @@
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::convert::Infallible;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use crate::limits::LimitExceeded;
+use crate::text::location::Located;
+
+/// Something a parser was looking for when it failed.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Expectation {
+    Token(String),
+    Rule(String),
+    EndOfInput,
+}
+
+impl Display for Expectation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expectation::Token(token) => write!(f, "`{}`", token),
+            Expectation::Rule(rule) => write!(f, "{}", rule),
+            Expectation::EndOfInput => write!(f, "end of input"),
+        }
+    }
+}
+
+/// Something a parser found but could not handle.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Unexpected {
+    Char(char),
+    EndOfInput,
+}
+
+impl Display for Unexpected {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Unexpected::Char(c) => write!(f, "unexpected character `{}`", c.escape_debug()),
+            Unexpected::EndOfInput => write!(f, "unexpected end of input"),
+        }
+    }
+}
+
+/// The default error of the built-in text parsers.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ParseError {
+    Expected(BTreeSet<Expectation>),
+    Unexpected(Unexpected),
+    Custom(String),
+    LimitExceeded(LimitExceeded),
+}
+
+impl ParseError {
+    pub fn expected(expectation: Expectation) -> Self {
+        let mut expected = BTreeSet::new();
+        expected.insert(expectation);
+        ParseError::Expected(expected)
+    }
+
+    pub fn expected_token(token: impl Into<String>) -> Self {
+        Self::expected(Expectation::Token(token.into()))
+    }
+
+    pub fn expected_rule(rule: impl Into<String>) -> Self {
+        Self::expected(Expectation::Rule(rule.into()))
+    }
+
+    pub fn unexpected(found: Option<char>) -> Self {
+        match found {
+            None => ParseError::Unexpected(Unexpected::EndOfInput),
+            Some(c) => ParseError::Unexpected(Unexpected::Char(c)),
+        }
+    }
+
+    pub fn custom(message: impl Into<String>) -> Self {
+        ParseError::Custom(message.into())
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            ParseError::Unexpected(_) => 0,
+            ParseError::Expected(_) => 1,
+            ParseError::Custom(_) => 2,
+            ParseError::LimitExceeded(_) => 3,
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::Expected(expected) => {
+                write!(f, "expected ")?;
+                let count = expected.len();
+                for (index, expectation) in expected.iter().enumerate() {
+                    if index > 0 && index + 1 == count {
+                        write!(f, " or ")?;
+                    } else if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", expectation)?;
+                }
+                Ok(())
+            }
+            ParseError::Unexpected(unexpected) => write!(f, "{}", unexpected),
+            ParseError::Custom(message) => write!(f, "{}", message),
+            ParseError::LimitExceeded(limit) => write!(f, "{}", limit),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+impl From<String> for ParseError {
+    fn from(message: String) -> Self {
+        ParseError::Custom(message)
+    }
+}
+
+impl From<LimitExceeded> for ParseError {
+    fn from(limit: LimitExceeded) -> Self {
+        ParseError::LimitExceeded(limit)
+    }
+}
+
+impl From<Infallible> for ParseError {
+    fn from(infallible: Infallible) -> Self {
+        match infallible {}
+    }
+}
+
+/// Combination of the errors of two alternatives that failed at the same input.
+pub trait Merge {
+    fn merge(self, other: Self) -> Self;
+}
+
+impl Merge for ParseError {
+    fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (ParseError::Expected(mut expected), ParseError::Expected(other_expected)) => {
+                expected.extend(other_expected);
+                ParseError::Expected(expected)
+            }
+            (this, other) => if other.rank() > this.rank() { other } else { this }
+        }
+    }
+}
+
+/// The error that got further into the input wins, ties are merged.
+impl<E: Merge> Merge for Located<E> {
+    fn merge(self, other: Self) -> Self {
+        match self.source_range().end.cmp(&other.source_range().end) {
+            Ordering::Less => other,
+            Ordering::Greater => self,
+            Ordering::Equal => {
+                let other_target = other.into_target();
+                self.map(|target| target.merge(other_target))
+            }
+        }
+    }
+}
+
+impl Merge for Infallible {
+    fn merge(self, _: Self) -> Self {
+        match self {}
+    }
+}
+
+impl Merge for () {
+    fn merge(self, _: Self) {}
+}
+
+/// Conversion between the error types of two parsers, applied with `Parser::err_into`.
+///
+/// `Located` errors convert whenever their targets do (via `From`), and `Infallible` converts
+/// into every error.
+pub trait IntoError<E> {
+    fn into_error(self) -> E;
+}
+
+impl<T, U: From<T>> IntoError<Located<U>> for Located<T> {
+    fn into_error(self) -> Located<U> {
+        self.map(U::from)
+    }
+}
+
+impl<E> IntoError<E> for Infallible {
+    fn into_error(self) -> E {
+        match self {}
+    }
+}
+
+impl IntoError<()> for () {
+    fn into_error(self) {}
+}
+
+/// The error of a sequence like `map2` or `flat_map` whose first parser fails with `Self` and
+/// whose second parser fails with `E`. Equal errors join into themselves and a parser that
+/// can't fail takes the `Located` error of the other one, e.g. `whitespace()` before or after a
+/// token. Other conversions are explicit through `err_into`.
+///
+/// The first error has to be known to pick the join, so a `Succeed::with` that starts a
+/// sequence needs its error type spelled out.
+pub trait JoinError<E> {
+    type Joined;
+
+    fn join_first(self) -> Self::Joined;
+
+    fn join_second(error: E) -> Self::Joined;
+}
+
+impl<E> JoinError<E> for E {
+    type Joined = E;
+
+    fn join_first(self) -> E {
+        self
+    }
+
+    fn join_second(error: E) -> E {
+        error
+    }
+}
+
+impl<T> JoinError<Located<T>> for Infallible {
+    type Joined = Located<T>;
+
+    fn join_first(self) -> Located<T> {
+        match self {}
+    }
+
+    fn join_second(error: Located<T>) -> Located<T> {
+        error
+    }
+}
+
+impl<T> JoinError<Infallible> for Located<T> {
+    type Joined = Located<T>;
+
+    fn join_first(self) -> Located<T> {
+        self
+    }
+
+    fn join_second(error: Infallible) -> Located<T> {
+        match error {}
+    }
+}
+
+impl JoinError<()> for Infallible {
+    type Joined = ();
+
+    fn join_first(self) {
+        match self {}
+    }
+
+    fn join_second(_error: ()) {}
+}
+
+impl JoinError<Infallible> for () {
+    type Joined = ();
+
+    fn join_first(self) {}
+
+    fn join_second(error: Infallible) {
+        match error {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::Infallible;
+
+    use crate::error::{Expectation, IntoError, Merge, ParseError};
+    use crate::text::location::{Located, Location};
+
+    #[derive(Debug, Eq, PartialEq)]
+    struct AppError(String);
+
+    impl From<String> for AppError {
+        fn from(message: String) -> Self {
+            AppError(message)
+        }
+    }
+
+    #[test]
+    fn located_converts_target() {
+        let error = Location::start().locate(Location::new(1, 2, 1), String::from("oops"));
+        let converted: Located<AppError> = error.into_error();
+        assert_eq!(Location::start().locate(Location::new(1, 2, 1), AppError(String::from("oops"))), converted);
+    }
+
+    #[test]
+    fn infallible_converts_into_anything() {
+        let result: Result<(), Infallible> = Ok(());
+        let converted: Result<(), Located<AppError>> = result.map_err(IntoError::into_error);
+        assert_eq!(Ok(()), converted);
+    }
+
+    #[test]
+    fn expected_sets_merge() {
+        let merged = ParseError::expected_token("+").merge(ParseError::expected_rule("number"));
+        assert_eq!("expected `+` or number", merged.to_string());
+
+        let merged = merged.merge(ParseError::expected(Expectation::EndOfInput));
+        assert_eq!("expected `+`, number or end of input", merged.to_string());
+    }
+
+    #[test]
+    fn farthest_error_wins() {
+        let near = Location::start().locate(Location::new(1, 2, 1), ParseError::expected_token("a"));
+        let far = Location::start().locate(Location::new(2, 3, 1), ParseError::expected_token("b"));
+        assert_eq!(far.clone(), near.clone().merge(far.clone()));
+        assert_eq!(far.clone(), far.merge(near));
+    }
+
+    #[test]
+    fn custom_error_beats_unexpected() {
+        let merged = ParseError::unexpected(None).merge(ParseError::custom("number too large"));
+        assert_eq!("number too large", merged.to_string());
+    }
+}
@@ -0,0 +1,572 @@
+//! Configuration files: the common subset of TOML and classic INI files.
+//!
+//! Both formats are read into the same document model, a `Table` whose keys and values keep
+//! their locations in the input.
+//!
+//! ```
+//! use parsec::formats::config;
+//!
+//! let document = config::parse_toml("[server]\nport = 8080\nhosts = [\"a\", \"b\"]\n").unwrap();
+//! let port = document.get_path(&["server", "port"]).unwrap();
+//! assert_eq!(Some(8080), port.target().as_integer());
+//! assert_eq!(2, port.source_range().start.row());
+//! ```
+//!
+//! TOML support covers tables, dotted and quoted keys, basic and literal strings on one line,
+//! integers, floats, booleans, arrays and inline tables. Multi-line strings, dates and arrays of
+//! tables are not supported. Unlike TOML 1.0, arrays must not mix types.
+
+use std::iter::once;
+
+use crate::error::ParseError;
+use crate::formats::{chars1, fail, FormatParser, satisfy, separated1, spanned, too_deep, until};
+use crate::parser::{from_fn, lazy, named, Parser, Succeed};
+use crate::text::location::Located;
+use crate::text::text_parser::{Chop, end, TextParser, TextState, token};
+
+/// How deep arrays and inline tables may be nested.
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Located<Value>>),
+    Table(Table),
+}
+
+impl Value {
+    /// The name of the type, as used in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Integer(_) => "integer",
+            Value::Float(_) => "float",
+            Value::Boolean(_) => "boolean",
+            Value::Array(_) => "array",
+            Value::Table(_) => "table",
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Integer(integer) => Some(*integer),
+            _ => None,
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Value::Float(float) => Some(*float),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Boolean(boolean) => Some(*boolean),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Located<Value>]> {
+        match self {
+            Value::Array(elements) => Some(elements),
+            _ => None,
+        }
+    }
+
+    pub fn as_table(&self) -> Option<&Table> {
+        match self {
+            Value::Table(table) => Some(table),
+            _ => None,
+        }
+    }
+}
+
+/// Keys with their values in input order. The keys of a table are unique.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table {
+    entries: Vec<(Located<String>, Located<Value>)>,
+    origin: Origin,
+}
+
+/// How a table was defined, which decides what later lines may add to it.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Origin {
+    /// The parent of a header, which may still get a header of its own.
+    Implicit,
+    Header,
+    Dotted,
+    Inline,
+}
+
+impl Table {
+    fn new(origin: Origin) -> Self {
+        Table { entries: vec![], origin }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn entries(&self) -> &[(Located<String>, Located<Value>)] {
+        &self.entries
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Located<Value>> {
+        self.entries.iter().find(|(entry_key, _)| entry_key.target() == key).map(|(_, value)| value)
+    }
+
+    /// The value at the end of a path of keys through nested tables.
+    pub fn get_path(&self, path: &[&str]) -> Option<&Located<Value>> {
+        let (last, parents) = path.split_last()?;
+        let mut table = self;
+        for key in parents {
+            table = table.get(key)?.target().as_table()?;
+        }
+        table.get(last)
+    }
+
+    fn position(&self, key: &str) -> Option<usize> {
+        self.entries.iter().position(|(entry_key, _)| entry_key.target() == key)
+    }
+}
+
+/// Parses a TOML document.
+pub fn parse_toml(input: impl Into<String>) -> Result<Table, Located<ParseError>> {
+    toml().pars(input)
+}
+
+/// Parses an INI file.
+pub fn parse_ini(input: impl Into<String>) -> Result<Table, Located<ParseError>> {
+    ini().pars(input)
+}
+
+/// A TOML document up to the end of the input.
+pub fn toml() -> FormatParser<Table> {
+    let header = spanned(token("[").ignore(blank()).map2(key(), |_, key| key).ignore(token("]")))
+        .map(Line::Header)
+        .ignore(line_end("#"));
+    let pair = key().ignore(token("=")).ignore(blank())
+        .map2(value(0), Line::Pair)
+        .ignore(line_end("#"));
+    document(blank().map2(header.or(pair).or(line_end("#").map(|_| Line::Blank)), |_, line| line).boxed())
+}
+
+/// An INI file up to the end of the input: `[section]` headers and `key = value` or
+/// `key: value` pairs. Values are strings that reach to the end of the line, comments start
+/// with `;` or `#` on lines of their own.
+pub fn ini() -> FormatParser<Table> {
+    let name = spanned(text("section name", |c| c == ']'));
+    let header = spanned(token("[").ignore(blank()).map2(name, |_, name| vec![name]).ignore(blank()).ignore(token("]")))
+        .map(Line::Header)
+        .ignore(line_end(";#"));
+    let value = spanned(text("value", |_| false).optional()).map(|value| value.map(|value| Value::String(value.unwrap_or_default())));
+    let pair = spanned(text("key", |c| c == '=' || c == ':')).ignore(blank()).ignore(token("=").or(token(":"))).ignore(blank())
+        .map2(value, |key, value| Line::Pair(vec![key], value))
+        .ignore(line_end(""));
+    document(blank().map2(header.or(pair).or(line_end(";#").map(|_| Line::Blank)), |_, line| line).boxed())
+}
+
+/// A value without surrounding whitespace, nested in `depth` arrays or inline tables.
+pub fn value(depth: usize) -> FormatParser<Located<Value>> {
+    let boolean = token("true").map(|_| Value::Boolean(true)).or(token("false").map(|_| Value::Boolean(false)));
+    let value = basic_string().or(literal_string()).map(Value::String)
+        .or(boolean)
+        .or(float().map(Value::Float))
+        .or(integer().map(Value::Integer))
+        .or(array(depth))
+        .or(inline_table(depth));
+    named("value", spanned(value)).boxed()
+}
+
+#[derive(Debug, Clone)]
+enum Line {
+    Header(Located<Vec<Located<String>>>),
+    Pair(Vec<Located<String>>, Located<Value>),
+    Blank,
+}
+
+fn document(line: FormatParser<Line>) -> FormatParser<Table> {
+    until(line, end().boxed()).flat_map(|lines| match build(lines) {
+        Ok(table) => Succeed::with(table).boxed(),
+        Err(error) => fail(error),
+    }).boxed()
+}
+
+/// Spaces and tabs, but no line breaks.
+fn blank() -> FormatParser<String> {
+    Chop::while_con(|c| c == ' ' || c == '\t')
+        .described_as("whitespace")
+        .err_into::<Located<ParseError>>()
+        .boxed()
+}
+
+/// Whitespace, line breaks and comments, as they may appear inside of arrays.
+fn space() -> FormatParser<()> {
+    let whitespace = || Chop::while_con(|c| matches!(c, ' ' | '\t' | '\n' | '\r')).described_as("whitespace");
+    whitespace().ignore(comment("#").ignore(whitespace()).many()).map(|_| ()).boxed()
+}
+
+/// A comment that starts with one of the chars of `starts` and reaches to the end of the line.
+fn comment(starts: &'static str) -> FormatParser<String> {
+    satisfy("comment", move |c| starts.contains(c))
+        .map2(Chop::while_con(|c| c != '\n' && c != '\r'), |_, comment| comment)
+        .boxed()
+}
+
+/// The rest of a line with an optional comment, up to and including the line break.
+fn line_end(comment_starts: &'static str) -> FormatParser<()> {
+    let line_break = token("\n").or(token("\r\n")).map(|_| ()).or(end());
+    blank().map(|_| ()).ignore(comment(comment_starts).optional()).ignore(line_break).boxed()
+}
+
+/// Words separated by blanks. Words end at whitespace and at chars for which `stop` is true.
+fn text(description: &'static str, stop: fn(char) -> bool) -> FormatParser<String> {
+    let is_word = move |c: char| !stop(c) && !c.is_whitespace();
+    let word = move || chars1(description, is_word);
+    word().map2((blank(), word()).map(|(blank, word)| blank + &word).many(), |first, rest| first + &rest.concat()).boxed()
+}
+
+/// A dotted key with the blanks after it.
+fn key() -> FormatParser<Vec<Located<String>>> {
+    let is_bare = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+    let bare = chars1("key", is_bare);
+    let part = spanned(bare.or(basic_string()).or(literal_string())).ignore(blank());
+    named("key", separated1(part.boxed(), token(".").ignore(blank()).boxed())).boxed()
+}
+
+/// Text that may not contain line breaks or other control chars than tabs.
+fn is_text(c: char) -> bool {
+    c == '\t' || !c.is_control()
+}
+
+fn basic_string() -> FormatParser<String> {
+    let unescaped = satisfy("character", |c| c != '"' && c != '\\' && is_text(c));
+    let simple = satisfy("escape character", |c| "btnfr\"\\".contains(c)).map(|c| match c {
+        'b' => '\u{8}',
+        't' => '\t',
+        'n' => '\n',
+        'f' => '\u{c}',
+        'r' => '\r',
+        c => c,
+    });
+    let unicode = token("u").map2(scalar(4), |_, c| c).or(token("U").map2(scalar(8), |_, c| c));
+    let escape = token("\\").map2(simple.or(unicode), |_, c| c);
+    token("\"").map2(until(unescaped.or(escape).boxed(), token("\"").boxed()), |_, chars| chars.into_iter().collect::<String>()).boxed()
+}
+
+/// A unicode scalar value as `digits` hex digits.
+fn scalar(digits: usize) -> FormatParser<char> {
+    let hex = || satisfy("hex digit", |c| c.is_ascii_hexdigit()).map(|c| c.to_digit(16).expect("hex digit"));
+    let mut code: FormatParser<u32> = Succeed::with(0).boxed();
+    for _ in 0..digits {
+        code = code.map2(hex(), |code, digit| code << 4 | digit).boxed();
+    }
+    spanned(code).flat_map(|code| match char::from_u32(*code.target()) {
+        Some(c) => Succeed::with(c).boxed(),
+        None => fail(code.map(|code| ParseError::custom(format!("{:X} is not a unicode scalar value", code)))),
+    }).boxed()
+}
+
+fn literal_string() -> FormatParser<String> {
+    let character = satisfy("character", |c| c != '\'' && is_text(c));
+    token("'").map2(until(character.boxed(), token("'").boxed()), |_, chars| chars.into_iter().collect::<String>()).boxed()
+}
+
+fn sign() -> FormatParser<Option<char>> {
+    satisfy("sign", |c| c == '+' || c == '-').optional().boxed()
+}
+
+/// Digits in `radix` with single underscores between them, which are dropped.
+fn digits(radix: u32) -> FormatParser<String> {
+    let digit = move || satisfy("digit", move |c| c.is_digit(radix));
+    let rest = token("_").optional().map2(digit(), |_, digit| digit).many();
+    digit().map2(rest, |first, rest| once(first).chain(rest).collect::<String>()).boxed()
+}
+
+/// Decimal digits without leading zeros.
+fn decimal() -> FormatParser<String> {
+    token("0").or(digits(10)).boxed()
+}
+
+fn integer() -> FormatParser<i64> {
+    let prefixed = |prefix: &'static str, radix: u32| token(prefix).map2(digits(radix), move |_, digits| (radix, digits));
+    let decimal = sign().map2(decimal(), |sign, digits| (10, sign.map(String::from).unwrap_or_default() + &digits));
+    let integer = prefixed("0x", 16).or(prefixed("0o", 8)).or(prefixed("0b", 2)).or(decimal);
+    named("integer", spanned(integer).flat_map(|integer| {
+        let (radix, digits) = integer.target();
+        match i64::from_str_radix(digits, *radix) {
+            Ok(value) => Succeed::with(value).boxed(),
+            Err(_) => fail(integer.map(|_| ParseError::custom("integer does not fit in 64 bits"))),
+        }
+    })).boxed()
+}
+
+fn float() -> FormatParser<f64> {
+    let exponent = || (satisfy("exponent", |c| c == 'e' || c == 'E'), sign(), digits(10))
+        .map(|(_, sign, digits)| format!("e{}{}", sign.map(String::from).unwrap_or_default(), digits));
+    let fraction = token(".").map2(digits(10), |_, digits| format!(".{}", digits))
+        .map2(exponent().optional(), |fraction, exponent| fraction + &exponent.unwrap_or_default());
+    let finite = (sign(), decimal(), fraction.or(exponent()))
+        .map(|(sign, integer, rest)| format!("{}{}{}", sign.map(String::from).unwrap_or_default(), integer, rest)
+            .parse::<f64>()
+            .expect("TOML floats are valid floats"));
+    let special = sign().map2(token("inf").map(|_| f64::INFINITY).or(token("nan").map(|_| f64::NAN)), |sign, value| {
+        if sign == Some('-') { -value } else { value }
+    });
+    named("float", finite.or(special)).boxed()
+}
+
+fn array(depth: usize) -> FormatParser<Value> {
+    if depth >= MAX_DEPTH {
+        return too_deep("[", MAX_DEPTH);
+    }
+    let close = from_fn(|state: TextState| match state.peek() {
+        Some(']') => Ok((state, ())),
+        _ => Err(state.locate_at_exactly(ParseError::expected_token("]"))),
+    });
+    let separator = token(",").ignore(space()).map(|_| ()).or(close);
+    let element = lazy(move || value(depth + 1)).ignore(space()).ignore(separator);
+    token("[").ignore(space()).map2(until(element.boxed(), token("]").boxed()), |_, elements| elements)
+        .flat_map(|elements| {
+            let first = elements.first().map(|first| first.target().type_name());
+            match elements.iter().find(|element| Some(element.target().type_name()) != first) {
+                Some(element) => {
+                    let message = format!("array mixes {} and {}", first.unwrap_or_default(), element.target().type_name());
+                    fail(element.clone().map(|_| ParseError::custom(message)))
+                }
+                None => Succeed::with(Value::Array(elements)).boxed(),
+            }
+        })
+        .boxed()
+}
+
+fn inline_table(depth: usize) -> FormatParser<Value> {
+    if depth >= MAX_DEPTH {
+        return too_deep("{", MAX_DEPTH);
+    }
+    let pair = key().ignore(token("=")).ignore(blank())
+        .map2(lazy(move || value(depth + 1)), |key, value| (key, value))
+        .ignore(blank());
+    let pairs = separated1(pair.boxed(), token(",").ignore(blank()).boxed());
+    let contents = token("}").map(|_| vec![]).or(pairs.ignore(token("}")));
+    token("{").ignore(blank()).map2(contents, |_, pairs| pairs)
+        .flat_map(|pairs| {
+            let mut table = Table::new(Origin::Inline);
+            for (path, value) in pairs {
+                if let Err(error) = insert(&mut table, &path, value) {
+                    return fail(error);
+                }
+            }
+            Succeed::with(Value::Table(table)).boxed()
+        })
+        .boxed()
+}
+
+/// Puts the lines of a document into tables. Fails at the first key that conflicts with an
+/// earlier definition.
+fn build(lines: Vec<Line>) -> Result<Table, Located<ParseError>> {
+    let mut root = Table::new(Origin::Header);
+    let mut current = vec![];
+    for line in lines {
+        match line {
+            Line::Header(header) => {
+                define(&mut root, &header)?;
+                current = header.into_target().into_iter().map(Located::into_target).collect();
+            }
+            Line::Pair(path, value) => insert(table_at(&mut root, &current), &path, value)?,
+            Line::Blank => {}
+        }
+    }
+    Ok(root)
+}
+
+fn table_at<'t>(root: &'t mut Table, path: &[String]) -> &'t mut Table {
+    let mut table = root;
+    for key in path {
+        let index = table.position(key).expect("headers define their tables");
+        table = match table.entries[index].1.target_mut() {
+            Value::Table(child) => child,
+            _ => unreachable!("headers define tables"),
+        };
+    }
+    table
+}
+
+fn conflict(key: &Located<String>, message: String) -> Located<ParseError> {
+    key.clone().map(|_| ParseError::custom(message))
+}
+
+/// The table at `key`, which is created if it does not exist. Dotted keys may only extend the
+/// tables of other dotted keys.
+fn child<'t>(table: &'t mut Table, key: &Located<String>, origin: Origin) -> Result<&'t mut Table, Located<ParseError>> {
+    let index = match table.position(key.target()) {
+        Some(index) => index,
+        None => {
+            table.entries.push((key.clone(), key.clone().map(|_| Value::Table(Table::new(origin)))));
+            table.entries.len() - 1
+        }
+    };
+    match table.entries[index].1.target_mut() {
+        Value::Table(child) if child.origin == Origin::Inline =>
+            Err(conflict(key, format!("inline table `{}` cannot be extended", key.target()))),
+        Value::Table(child) if origin == Origin::Dotted && child.origin == Origin::Header =>
+            Err(conflict(key, format!("table `{}` cannot be extended with dotted keys", key.target()))),
+        Value::Table(child) => Ok(child),
+        _ => Err(conflict(key, format!("key `{}` is not a table", key.target()))),
+    }
+}
+
+fn define(root: &mut Table, header: &Located<Vec<Located<String>>>) -> Result<(), Located<ParseError>> {
+    let (last, parents) = header.target().split_last().expect("headers have a key");
+    let mut table = root;
+    for key in parents {
+        table = child(table, key, Origin::Implicit)?;
+    }
+    let index = match table.position(last.target()) {
+        Some(index) => index,
+        None => {
+            table.entries.push((last.clone(), header.clone().map(|_| Value::Table(Table::new(Origin::Header)))));
+            return Ok(());
+        }
+    };
+    match table.entries[index].1.target_mut() {
+        Value::Table(existing) if existing.origin == Origin::Implicit => {
+            existing.origin = Origin::Header;
+            Ok(())
+        }
+        Value::Table(_) => {
+            let name = header.target().iter().map(|key| key.target().as_str()).collect::<Vec<_>>().join(".");
+            Err(conflict(last, format!("table `{}` is defined twice", name)))
+        }
+        _ => Err(conflict(last, format!("key `{}` is not a table", last.target()))),
+    }
+}
+
+fn insert(table: &mut Table, path: &[Located<String>], value: Located<Value>) -> Result<(), Located<ParseError>> {
+    let (last, parents) = path.split_last().expect("keys are not empty");
+    let mut table = table;
+    for key in parents {
+        table = child(table, key, Origin::Dotted)?;
+    }
+    if table.position(last.target()).is_some() {
+        return Err(conflict(last, format!("duplicate key `{}`", last.target())));
+    }
+    table.entries.push((last.clone(), value));
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::formats::config::{self, Value};
+    use crate::text::location::Location;
+    use crate::text::source_map::SourceMap;
+    use crate::text::text_parser::TextParser;
+
+    const TOML: &str = r#"# Settings of the server
+title = "Example \"config\" \u00e9"
+path = 'C:\temp'
+
+[server]
+port = 8_080
+mask = 0xff_ff
+ratio = -1.5e3
+enabled = true
+hosts = [
+    "alpha", # the first one
+    "beta",
+]
+limits = { memory.max = 512, cpu = 0.5 }
+
+[server.tls]
+"key file" = "server.key"
+"#;
+
+    #[test]
+    fn reads_toml() {
+        let document = config::parse_toml(TOML).expect("valid TOML");
+        assert_eq!(Some("Example \"config\" \u{e9}"), document.get("title").and_then(|title| title.target().as_str()));
+        assert_eq!(Some("C:\\temp"), document.get("path").and_then(|path| path.target().as_str()));
+        let value = |path: &[&str]| document.get_path(path).expect("value exists").target().clone();
+        assert_eq!(Value::Integer(8080), value(&["server", "port"]));
+        assert_eq!(Value::Integer(0xffff), value(&["server", "mask"]));
+        assert_eq!(Value::Float(-1500.0), value(&["server", "ratio"]));
+        assert_eq!(Value::Boolean(true), value(&["server", "enabled"]));
+        assert_eq!(Value::Integer(512), value(&["server", "limits", "memory", "max"]));
+        assert_eq!(Value::Float(0.5), value(&["server", "limits", "cpu"]));
+        assert_eq!(Value::String(String::from("server.key")), value(&["server", "tls", "key file"]));
+
+        let hosts = document.get_path(&["server", "hosts"]).expect("hosts");
+        let hosts = hosts.target().as_array().expect("array");
+        assert_eq!(Some("beta"), hosts[1].target().as_str());
+        assert_eq!(Location::new(189, 5, 12), hosts[1].source_range().start);
+
+        let server = &document.entries()[2];
+        assert_eq!("server", server.0.target());
+        assert_eq!(Location::new(79, 1, 5), server.1.source_range().start);
+        assert_eq!(Location::new(87, 9, 5), server.1.source_range().end);
+    }
+
+    #[test]
+    fn reads_ini() {
+        let document = config::parse_ini("; global\nname = My App\n\n[database]\nhost: db.example.com \nuser =\n# done\n").expect("valid INI");
+        assert_eq!(Some("My App"), document.get("name").and_then(|name| name.target().as_str()));
+        let host = document.get_path(&["database", "host"]).expect("host");
+        assert_eq!(Some("db.example.com"), host.target().as_str());
+        assert_eq!(Location::new(41, 7, 5), host.source_range().start);
+        assert_eq!(Location::new(55, 21, 5), host.source_range().end);
+        assert_eq!(Some(""), document.get_path(&["database", "user"]).and_then(|user| user.target().as_str()));
+    }
+
+    #[test]
+    fn rejects_conflicting_definitions() {
+        let message = |input: &str| config::parse_toml(input).map(|_| ()).map_err(|error| (error.target().to_string(), error.source_range().start.row()));
+        assert_eq!(Err((String::from("duplicate key `a`"), 2)), message("a = 1\na = 2\n"));
+        assert_eq!(Err((String::from("duplicate key `b`"), 2)), message("a.b = 1\na.b = 2"));
+        assert_eq!(Err((String::from("table `x.y` is defined twice"), 3)), message("[x.y]\n[x]\n[x.y]\n"));
+        assert_eq!(Err((String::from("key `a` is not a table"), 2)), message("a = 1\n[a.b]\n"));
+        assert_eq!(Err((String::from("inline table `t` cannot be extended"), 2)), message("t = { a = 1 }\nt.b = 2\n"));
+        assert_eq!(Err((String::from("table `u` cannot be extended with dotted keys"), 4)), message("[t.u]\n[x]\n[t]\nu.v = 1\n"));
+        assert_eq!(Err((String::from("duplicate key `a`"), 1)), message("t = { a = 1, a = 2 }"));
+        assert!(message("[x.y]\n[x]\n").is_ok());
+        assert!(message("a.b = 1\n[a.c]\n").is_ok());
+        assert!(config::parse_ini("[s]\nk = 1\n[s]\n").is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_syntax() {
+        for input in ["a = ", "a = 1 2", "a = [1 2]", "a = 01", "a = 1_", "a = \"unclosed", "a = 'new\nline'", "a = 0x", "a = 1.",
+            "a = \"\\q\"", "a = \"\\uD800\"", "a = { b = 1, }", "a = 9223372036854775808", "[a", "= 1", "[[a]]", "a = 1979-05-27"] {
+            assert!(config::parse_toml(input).is_err(), "rejects {:?}", input);
+        }
+        for input in ["a = 9223372036854775807", "a = -0", "a = +inf", "a = 1e-2", "a = 0b101", "a = 0o17", "a = []", "a = {}", "a = [[1], ['a']]"] {
+            assert!(config::parse_toml(input).is_ok(), "accepts {:?}", input);
+        }
+    }
+
+    #[test]
+    fn renders_errors_with_line_numbers() {
+        let mut sources = SourceMap::new();
+        let id = sources.add("app.toml", "[server]\nports = [80, \"443\"]\n");
+        let error = config::toml().pars_with(sources.state(id).expect("source")).unwrap_err();
+        assert_eq!("app.toml:2:14: array mixes integer and string\nports = [80, \"443\"]\n             ^^^^^", sources.render(&error));
+
+        let nested = format!("a = {}{}", "[".repeat(40), "]".repeat(40));
+        assert!(config::parse_toml(nested).is_err());
+    }
+}
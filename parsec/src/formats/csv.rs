@@ -0,0 +1,363 @@
+//! CSV as specified by RFC 4180, with configurable delimiter and quote, so TSV and similar
+//! formats work as well.
+//!
+//! ```
+//! use parsec::formats::csv::{self, CsvOptions};
+//! use parsec::text::text_parser::integer;
+//!
+//! let mut records = csv::records("name,age\r\n\"Doe, Jane\",42\r\n", CsvOptions::new().with_header(true));
+//! let record = records.next().unwrap().unwrap();
+//! assert_eq!("Doe, Jane", record.target().get(0).unwrap().target());
+//! assert_eq!(Ok(42), record.target().decode("age", &integer()));
+//! assert!(records.next().is_none());
+//! ```
+
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::rc::Rc;
+
+use crate::error::{Expectation, ParseError};
+use crate::formats::{FormatParser, satisfy, separated1, spanned};
+use crate::parser::{from_fn, Parser};
+use crate::text::location::{Located, Location};
+use crate::text::text_parser::{Chop, TextState, token};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct CsvOptions {
+    delimiter: char,
+    quote: char,
+    has_header: bool,
+}
+
+impl CsvOptions {
+    /// Comma separated fields quoted with `"` and no header row.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tab separated fields quoted with `"` and no header row.
+    pub fn tsv() -> Self {
+        Self::new().with_delimiter('\t')
+    }
+
+    pub fn with_delimiter(self, delimiter: char) -> Self {
+        Self { delimiter, ..self }
+    }
+
+    pub fn with_quote(self, quote: char) -> Self {
+        Self { quote, ..self }
+    }
+
+    /// Treats the first record as the names of the columns, see `Record::by_name`.
+    pub fn with_header(self, has_header: bool) -> Self {
+        Self { has_header, ..self }
+    }
+
+    pub fn delimiter(&self) -> char {
+        self.delimiter
+    }
+
+    pub fn quote(&self) -> char {
+        self.quote
+    }
+
+    pub fn has_header(&self) -> bool {
+        self.has_header
+    }
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self { delimiter: ',', quote: '"', has_header: false }
+    }
+}
+
+/// The fields of one record. The location of a quoted field is the location of its content
+/// between the quotes.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Record {
+    fields: Vec<Located<String>>,
+    headers: Option<Rc<[String]>>,
+}
+
+impl Record {
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn fields(&self) -> &[Located<String>] {
+        &self.fields
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Located<String>> {
+        self.fields.get(index)
+    }
+
+    /// The field in the column with the header `name`.
+    pub fn by_name(&self, name: &str) -> Option<&Located<String>> {
+        name.index(self).and_then(|index| self.get(index))
+    }
+
+    /// Parses the field in `column` with `parser`, which has to consume all of it. Errors are
+    /// located in the input of the record, not in the field.
+    pub fn decode<C: Column, P>(&self, column: C, parser: &P) -> Result<P::Value, Located<ParseError>>
+        where P: Parser<State=TextState, Error=Located<ParseError>>
+    {
+        let field = match column.index(self).and_then(|index| self.get(index)) {
+            Some(field) => field,
+            None => {
+                let end = self.fields.last().map_or_else(Location::start, |field| field.source_range().end.clone());
+                return Err(end.clone().locate(end, ParseError::custom(format!("missing column {}", column))));
+            }
+        };
+        let base = &field.source_range().start;
+        let relocate = |error: Located<ParseError>| {
+            let range = error.source_range().clone();
+            relocate(base, &range.start).locate(relocate(base, &range.end), error.into_target())
+        };
+        let (state, value) = parser.do_pars(TextState::new(field.target().as_str())).map_err(relocate)?;
+        match state.peek() {
+            None => Ok(value),
+            Some(_) => Err(relocate(state.locate_at_exactly(ParseError::expected(Expectation::EndOfInput)))),
+        }
+    }
+}
+
+/// Moves a location in a field into the input the field was parsed from.
+fn relocate(base: &Location, location: &Location) -> Location {
+    let byte_offset = base.byte_offset() + location.byte_offset();
+    let relocated = if location.row() <= 1 {
+        Location::new(byte_offset, base.column() + location.column() - 1, base.row())
+    } else {
+        Location::new(byte_offset, location.column(), base.row() + location.row() - 1)
+    };
+    relocated.with_source(base.source())
+}
+
+/// How `Record::decode` finds a field: by index or by header name.
+pub trait Column: Display {
+    fn index(&self, record: &Record) -> Option<usize>;
+}
+
+impl Column for usize {
+    fn index(&self, _record: &Record) -> Option<usize> {
+        Some(*self)
+    }
+}
+
+impl Column for &str {
+    fn index(&self, record: &Record) -> Option<usize> {
+        record.headers.as_ref().and_then(|headers| headers.iter().position(|header| header == self))
+    }
+}
+
+/// Reads the records of `input` one at a time.
+pub fn records(input: impl Into<String>, options: CsvOptions) -> Records {
+    let mut records = Records {
+        state: TextState::new(input),
+        parser: record(options),
+        header: None,
+        width: None,
+        pending: None,
+    };
+    if options.has_header {
+        match records.read_header() {
+            Some(Ok(header)) => records.header = Some(header),
+            Some(Err(error)) => records.pending = Some(error),
+            None => {}
+        }
+    }
+    records
+}
+
+/// The fields of a record up to and including its line break. Blank lines are records with a
+/// single empty field.
+pub fn record(options: CsvOptions) -> FormatParser<Vec<Located<String>>> {
+    let CsvOptions { delimiter, quote, .. } = options;
+    let quote_token = || token(&quote.to_string());
+    let escaped = quote_token().map2(quote_token(), move |_, _| quote)
+        .or(satisfy("character", move |c| c != quote));
+    let quoted = quote_token()
+        .map2(spanned(escaped.many().map(|chars| chars.into_iter().collect::<String>())), |_, content| content)
+        .ignore(quote_token());
+    let unquoted = spanned(Chop::while_con(move |c| c != delimiter && c != quote && c != '\n' && c != '\r')
+        .err_into::<Located<ParseError>>());
+    let field = quoted.boxed().or(unquoted);
+    separated1(field.boxed(), token(&delimiter.to_string()).boxed())
+        .ignore(record_end(delimiter))
+        .boxed()
+}
+
+/// A line break or the end of the input.
+fn record_end(delimiter: char) -> FormatParser<()> {
+    from_fn(move |mut state: TextState| match state.peek() {
+        None => Ok((state, ())),
+        Some('\n') => {
+            state.advance();
+            Ok((state, ()))
+        }
+        Some('\r') => {
+            state.advance();
+            if state.peek() == Some('\n') {
+                state.advance();
+            }
+            Ok((state, ()))
+        }
+        Some(_) => {
+            let mut expected = BTreeSet::new();
+            expected.insert(Expectation::Token(delimiter.to_string()));
+            expected.insert(Expectation::Rule(String::from("line break")));
+            Err(state.locate_at_exactly(ParseError::Expected(expected)))
+        }
+    }).boxed()
+}
+
+/// An iterator over the records of an input, see `records`. Blank lines are skipped. After a
+/// malformed record it continues with the next line, so all malformed records can be reported.
+pub struct Records {
+    state: TextState,
+    parser: FormatParser<Vec<Located<String>>>,
+    header: Option<Located<Record>>,
+    width: Option<usize>,
+    pending: Option<Located<ParseError>>,
+}
+
+impl Records {
+    /// The header record, if the options ask for one and it is well-formed. A malformed header
+    /// is the first error of the iterator.
+    pub fn header(&self) -> Option<&Located<Record>> {
+        self.header.as_ref()
+    }
+
+    fn read_header(&mut self) -> Option<Result<Located<Record>, Located<ParseError>>> {
+        let mut header = self.next()?;
+        if let Ok(record) = &mut header {
+            let names: Vec<String> = record.target().fields.iter().map(|field| field.target().clone()).collect();
+            let headers = Some(Rc::from(names));
+            *record = record.clone().map(|record| Record { headers, ..record });
+        }
+        Some(header)
+    }
+
+    /// Skips the rest of the line that contains `location`.
+    fn skip_line(&mut self, location: &Location) {
+        while self.state.location().byte_offset() < location.byte_offset() {
+            self.state.advance();
+        }
+        while let Some(c) = self.state.next() {
+            if c == '\n' || (c == '\r' && self.state.peek() != Some('\n')) {
+                break;
+            }
+        }
+    }
+}
+
+impl Iterator for Records {
+    type Item = Result<Located<Record>, Located<ParseError>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(error) = self.pending.take() {
+            return Some(Err(error));
+        }
+        loop {
+            self.state.peek()?;
+            let start = self.state.location().clone();
+            let fields = match self.parser.do_pars(self.state.clone()) {
+                Ok((state, fields)) => {
+                    self.state = state;
+                    fields
+                }
+                Err(error) => {
+                    self.skip_line(&error.source_range().end);
+                    return Some(Err(error));
+                }
+            };
+            // Blank lines are skipped, a quoted empty field starts after the record.
+            if fields.len() == 1 && fields[0].target().is_empty() && fields[0].source_range().start == start {
+                continue;
+            }
+            let end = fields.last().expect("records have a field").source_range().end.clone();
+            let width = *self.width.get_or_insert(fields.len());
+            let headers = self.header.as_ref().and_then(|header| header.target().headers.clone());
+            let record = start.locate(end, Record { fields, headers });
+            if record.target().len() != width {
+                let found = record.target().len();
+                return Some(Err(record.map(|_| ParseError::custom(format!("expected {} fields, found {}", width, found)))));
+            }
+            return Some(Ok(record));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::{Expectation, ParseError};
+    use crate::formats::csv::{self, CsvOptions};
+    use crate::parser::Parser;
+    use crate::text::location::Location;
+    use crate::text::text_parser::{float, integer, whitespace};
+
+    fn values(input: &str, options: CsvOptions) -> Vec<Vec<String>> {
+        csv::records(input, options)
+            .map(|record| record.expect("valid record").into_target().fields().iter().map(|field| field.target().clone()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn reads_rfc_4180() {
+        let input = "a,\"b \"\"quoted\"\"\",c\r\n\"multi\r\nline\",,\"\"\r\n\nlast,x,\"y\"";
+        assert_eq!(vec![
+            vec!["a", "b \"quoted\"", "c"],
+            vec!["multi\r\nline", "", ""],
+            vec!["last", "x", "y"],
+        ], values(input, CsvOptions::new()));
+
+        let records: Vec<_> = csv::records(input, CsvOptions::new()).map(Result::unwrap).collect();
+        assert_eq!(Location::new(20, 1, 2), records[1].source_range().start);
+        assert_eq!(Location::new(40, 1, 5), records[2].source_range().start);
+        assert_eq!(Location::new(21, 2, 2), records[1].target().fields()[0].source_range().start);
+    }
+
+    #[test]
+    fn configurable_delimiter_and_quote() {
+        assert_eq!(vec![vec!["a,b", "c\td"], vec!["e", ""]], values("a,b\t'c\td'\ne\t\n", CsvOptions::tsv().with_quote('\'')));
+        assert_eq!(vec![vec!["x", "y"]], values("x;y", CsvOptions::new().with_delimiter(';')));
+    }
+
+    #[test]
+    fn reports_malformed_records_by_line() {
+        let input = "a,b\nd,e,f\n1,2\n\"x\"y,z\n3,4";
+        let lines: Vec<_> = csv::records(input, CsvOptions::new()).map(|result| match result {
+            Ok(record) => Ok(record.source_range().start.row()),
+            Err(error) => Err((error.source_range().start.row(), error.target().to_string())),
+        }).collect();
+        assert_eq!(vec![
+            Ok(1),
+            Err((2, String::from("expected 2 fields, found 3"))),
+            Ok(3),
+            Err((4, String::from("expected `,` or line break"))),
+            Ok(5),
+        ], lines);
+    }
+
+    #[test]
+    fn decodes_columns_by_header() {
+        let mut records = csv::records("id,price\n1,9.5\n2,\"1\n0x\"", CsvOptions::new().with_header(true));
+        assert_eq!(Some("price"), records.header().and_then(|header| header.target().get(1)).map(|field| field.target().as_str()));
+
+        let first = records.next().expect("first record").expect("valid record");
+        assert_eq!(Ok(1), first.target().decode("id", &integer()));
+        assert_eq!(Ok(9.5), first.target().decode(1, &float()));
+        assert_eq!(Some("9.5"), first.target().by_name("price").map(|field| field.target().as_str()));
+
+        let second = records.next().expect("second record").expect("valid record");
+        let sum = integer().ignore(whitespace()).map2(integer(), |lhs, rhs| lhs + rhs);
+        let error = second.target().decode("price", &sum).unwrap_err();
+        assert_eq!(Location::new(21, 2, 4).locate(Location::new(21, 2, 4), ParseError::expected(Expectation::EndOfInput)), error);
+        assert_eq!("missing column name", second.target().decode("name", &integer()).unwrap_err().target().to_string());
+    }
+}
@@ -0,0 +1,680 @@
+//! HTTP/1.1 message heads as specified by RFC 7230: the request or status line and the header
+//! fields up to the empty line before the body.
+//!
+//! ```
+//! use parsec::formats::http;
+//!
+//! let input = &b"GET /index.html HTTP/1.1\r\nHost: example.com\r\nAccept: text/html,\r\n  text/plain\r\n\r\n"[..];
+//! let head = http::parse_request(input).unwrap();
+//! assert_eq!("/index.html", head.target().target());
+//! assert_eq!(Some(&b"text/html, text/plain"[..]), head.header("accept"));
+//! assert_eq!(input.len(), head.body_offset());
+//!
+//! let error = http::parse_request(&b"GET / HTTP/1.1\r\nHost : example.com\r\n\r\n"[..]).unwrap_err();
+//! assert_eq!(400, error.target().status());
+//! assert_eq!("2:5: whitespace between header name and colon", error.to_string());
+//! ```
+//!
+//! Lines may also end with a bare `\n`. Header values continued on lines starting with
+//! whitespace (the obsolete line folding) are joined with a single space, so the span of such a
+//! value covers the line breaks in between. Input that ends before the head is complete fails
+//! with `HttpError::Incomplete`, which a server reading from a connection can treat as a signal
+//! to wait for more. The `HeadLimits` are checked while scanning, so a head that is already too
+//! large fails with a 431 error even if it is incomplete.
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use crate::adapter::BoxedParser;
+use crate::bytes::{ByteState, tag};
+use crate::error::{Merge, ParseError};
+use crate::parser::{from_fn, named, Parser};
+use crate::text::location::{Located, Location};
+
+/// The parsers of this module work on bytes and fail with `HttpError`s.
+pub type HeadParser<T> = BoxedParser<'static, T, ByteState, Located<HttpError>>;
+
+/// Bounds on the size of a head, which a server should enforce for untrusted clients.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct HeadLimits {
+    max_headers: usize,
+    max_field_size: usize,
+    max_head_size: usize,
+}
+
+impl HeadLimits {
+    /// At most 100 headers of 8 KiB each in a head of 64 KiB.
+    pub fn new() -> Self {
+        Self { max_headers: 100, max_field_size: 8 * 1024, max_head_size: 64 * 1024 }
+    }
+
+    pub fn with_max_headers(self, max_headers: usize) -> Self {
+        Self { max_headers, ..self }
+    }
+
+    /// The most bytes of one header field, from its name to the end of its value.
+    pub fn with_max_field_size(self, max_field_size: usize) -> Self {
+        Self { max_field_size, ..self }
+    }
+
+    /// The most bytes of the start line and all header fields.
+    pub fn with_max_head_size(self, max_head_size: usize) -> Self {
+        Self { max_head_size, ..self }
+    }
+}
+
+impl Default for HeadLimits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum HttpError {
+    /// The head doesn't follow the grammar, answered with `400 Bad Request`.
+    Malformed(ParseError),
+    /// The input ended before the empty line that ends the head.
+    Incomplete,
+    TooManyHeaders(usize),
+    FieldTooLarge(usize),
+    HeadTooLarge(usize),
+    /// A major version other than 1, answered with `505 HTTP Version Not Supported`.
+    UnsupportedVersion(Version),
+}
+
+impl HttpError {
+    /// The status code of the response a server should send for this error.
+    pub fn status(&self) -> u16 {
+        match self {
+            HttpError::Malformed(_) | HttpError::Incomplete => 400,
+            HttpError::TooManyHeaders(_) | HttpError::FieldTooLarge(_) | HttpError::HeadTooLarge(_) => 431,
+            HttpError::UnsupportedVersion(_) => 505,
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            HttpError::Malformed(_) => 0,
+            HttpError::Incomplete => 1,
+            HttpError::UnsupportedVersion(_) => 2,
+            HttpError::TooManyHeaders(_) | HttpError::FieldTooLarge(_) | HttpError::HeadTooLarge(_) => 3,
+        }
+    }
+}
+
+impl Display for HttpError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HttpError::Malformed(error) => write!(f, "{}", error),
+            HttpError::Incomplete => write!(f, "the head is incomplete"),
+            HttpError::TooManyHeaders(max) => write!(f, "more than {} header fields", max),
+            HttpError::FieldTooLarge(max) => write!(f, "header field is larger than {} bytes", max),
+            HttpError::HeadTooLarge(max) => write!(f, "head is larger than {} bytes", max),
+            HttpError::UnsupportedVersion(version) => write!(f, "{} is not supported", version),
+        }
+    }
+}
+
+impl Error for HttpError {}
+
+impl From<ParseError> for HttpError {
+    fn from(error: ParseError) -> Self {
+        HttpError::Malformed(error)
+    }
+}
+
+impl Merge for HttpError {
+    fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (HttpError::Malformed(error), HttpError::Malformed(other_error)) => HttpError::Malformed(error.merge(other_error)),
+            (this, other) => if other.rank() > this.rank() { other } else { this }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Version {
+    major: u8,
+    minor: u8,
+}
+
+impl Version {
+    pub fn major(&self) -> u8 {
+        self.major
+    }
+
+    pub fn minor(&self) -> u8 {
+        self.minor
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HTTP/{}.{}", self.major, self.minor)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Header {
+    name: Located<String>,
+    value: Located<Vec<u8>>,
+}
+
+impl Header {
+    /// The name as written. Names are compared ignoring ASCII case.
+    pub fn name(&self) -> &Located<String> {
+        &self.name
+    }
+
+    /// The value without surrounding whitespace. Values are usually ASCII, but may contain
+    /// other bytes.
+    pub fn value(&self) -> &Located<Vec<u8>> {
+        &self.value
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RequestHead {
+    method: Located<String>,
+    target: Located<String>,
+    version: Located<Version>,
+    headers: Vec<Header>,
+    body_offset: usize,
+}
+
+impl RequestHead {
+    pub fn method(&self) -> &Located<String> {
+        &self.method
+    }
+
+    /// The request target as written, usually a path with an optional query.
+    pub fn target(&self) -> &Located<String> {
+        &self.target
+    }
+
+    pub fn version(&self) -> &Located<Version> {
+        &self.version
+    }
+
+    pub fn headers(&self) -> &[Header] {
+        &self.headers
+    }
+
+    /// The value of the first header named `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&[u8]> {
+        find_header(&self.headers, name)
+    }
+
+    /// The length of the head including the empty line at its end. The body starts this many
+    /// bytes after the head.
+    pub fn body_offset(&self) -> usize {
+        self.body_offset
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ResponseHead {
+    version: Located<Version>,
+    status: Located<u16>,
+    reason: Located<Vec<u8>>,
+    headers: Vec<Header>,
+    body_offset: usize,
+}
+
+impl ResponseHead {
+    pub fn version(&self) -> &Located<Version> {
+        &self.version
+    }
+
+    pub fn status(&self) -> &Located<u16> {
+        &self.status
+    }
+
+    /// The reason phrase, which may be empty.
+    pub fn reason(&self) -> &Located<Vec<u8>> {
+        &self.reason
+    }
+
+    pub fn headers(&self) -> &[Header] {
+        &self.headers
+    }
+
+    /// The value of the first header named `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&[u8]> {
+        find_header(&self.headers, name)
+    }
+
+    /// The length of the head including the empty line at its end. The body starts this many
+    /// bytes after the head.
+    pub fn body_offset(&self) -> usize {
+        self.body_offset
+    }
+}
+
+fn find_header<'h>(headers: &'h [Header], name: &str) -> Option<&'h [u8]> {
+    headers.iter()
+        .find(|header| header.name.target().eq_ignore_ascii_case(name))
+        .map(|header| header.value.target().as_slice())
+}
+
+/// Parses the request head at the start of `input` within the default limits. The rest of the
+/// input is left for the body.
+pub fn parse_request(input: impl Into<Vec<u8>>) -> Result<RequestHead, Located<HttpError>> {
+    request_head(HeadLimits::default()).do_pars(ByteState::new(input)).map(|(_, head)| head)
+}
+
+/// Parses the response head at the start of `input` within the default limits. The rest of the
+/// input is left for the body.
+pub fn parse_response(input: impl Into<Vec<u8>>) -> Result<ResponseHead, Located<HttpError>> {
+    response_head(HeadLimits::default()).do_pars(ByteState::new(input)).map(|(_, head)| head)
+}
+
+/// A request line and header fields. Empty lines before the request line are skipped and
+/// HTTP/1.1 requests need exactly one `Host` header.
+pub fn request_head(limits: HeadLimits) -> HeadParser<RequestHead> {
+    let request_line = from_fn(|mut state: ByteState| {
+        while state.peek() == Some(b'\r') || state.peek() == Some(b'\n') {
+            state.advance();
+        }
+        Ok::<_, Located<HttpError>>((state, ()))
+    })
+        .map2(spanned(token("method")), |_, method| method)
+        .ignore(literal(b" "))
+        .map2(spanned(take_while1("request target", |b| b.is_ascii_graphic())), |method, target| (method, target))
+        .ignore(literal(b" "))
+        .map2(version(), |(method, target), version| (method, target, version))
+        .ignore(line_end());
+    let head = head(limits, request_line.boxed()).map(|((method, target, version), headers, body_offset)| RequestHead {
+        method: method.map(ascii),
+        target: target.map(ascii),
+        version,
+        headers,
+        body_offset,
+    });
+    named("request head", head.flat_map(|head| from_fn(move |state: ByteState| match check_host(&head) {
+        Some(error) => Err(error),
+        None => Ok((state, head.clone())),
+    }))).boxed()
+}
+
+/// A status line and header fields.
+pub fn response_head(limits: HeadLimits) -> HeadParser<ResponseHead> {
+    let status = spanned(take_while1("status code", |b| b.is_ascii_digit())).flat_map(|digits| from_fn(move |state: ByteState| {
+        let code = std::str::from_utf8(digits.target()).ok().and_then(|code| code.parse::<u16>().ok());
+        match code {
+            Some(code) if digits.target().len() == 3 && (100..600).contains(&code) => Ok((state, digits.clone().map(|_| code))),
+            _ => Err(error_at(&digits, ParseError::custom("status codes have three digits from 100 to 599"))),
+        }
+    }));
+    let reason = from_fn(|state: ByteState| {
+        // Some servers leave out the space before an empty reason phrase.
+        if matches!(state.peek(), Some(b'\r') | Some(b'\n')) {
+            let located = state.locate_at_exactly(vec![]);
+            return Ok((state, located));
+        }
+        let (mut state, _) = literal(b" ").do_pars(state)?;
+        let start = state.location().clone();
+        while state.peek().is_some_and(is_field_content) {
+            state.advance();
+        }
+        let reason = state.since(&start).to_vec();
+        let located = state.locate(start, reason);
+        Ok::<_, Located<HttpError>>((state, located))
+    });
+    let status_line = version()
+        .ignore(literal(b" "))
+        .map2(status, |version, status| (version, status))
+        .map2(reason, |(version, status), reason| (version, status, reason))
+        .ignore(line_end());
+    let head = head(limits, status_line.boxed()).map(|((version, status, reason), headers, body_offset)| ResponseHead {
+        version,
+        status,
+        reason,
+        headers,
+        body_offset,
+    });
+    named("response head", head).boxed()
+}
+
+/// Chars that may appear in methods and header names.
+fn is_tchar(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+/// Bytes of header values and reason phrases: anything but control chars, except for tabs.
+fn is_field_content(b: u8) -> bool {
+    b == b'\t' || (b >= 0x20 && b != 0x7f)
+}
+
+fn is_whitespace(b: u8) -> bool {
+    b == b' ' || b == b'\t'
+}
+
+/// The bytes are checked to be ASCII by the parsers that produce them.
+fn ascii(bytes: Vec<u8>) -> String {
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn error_at<T>(located: &Located<T>, error: impl Into<HttpError>) -> Located<HttpError> {
+    let range = located.source_range();
+    range.start.clone().locate(range.end.clone(), error.into())
+}
+
+/// The error for input that doesn't continue with `what`, which is `Incomplete` at its end.
+fn expected(state: &ByteState, what: &str) -> Located<HttpError> {
+    if state.remaining().is_empty() {
+        state.locate_at_exactly(HttpError::Incomplete)
+    } else {
+        state.locate_at_exactly(HttpError::Malformed(ParseError::expected_rule(what)))
+    }
+}
+
+/// Runs `parser` on the input up to the head size limit and the empty line after it, so that a
+/// head that doesn't end in time fails with `HeadTooLarge` instead of being scanned to its end.
+fn within_head<T: 'static>(limits: HeadLimits, parser: HeadParser<T>) -> HeadParser<T> {
+    from_fn(move |state: ByteState| {
+        let end = state.end();
+        let limit = state.location().byte_offset().saturating_add(limits.max_head_size).saturating_add(2);
+        match parser.do_pars(state.with_end(limit)) {
+            Ok((state, value)) => Ok((state.with_end(end), value)),
+            Err(error) if *error.target() == HttpError::Incomplete && limit < end => {
+                Err(error.map(|_| HttpError::HeadTooLarge(limits.max_head_size)))
+            }
+            Err(error) => Err(error),
+        }
+    }).boxed()
+}
+
+fn spanned<T: 'static>(parser: HeadParser<T>) -> HeadParser<Located<T>> {
+    from_fn(move |state: ByteState| {
+        let start = state.location().clone();
+        parser.do_pars(state).map(|(new_state, value)| {
+            let located = new_state.locate(start, value);
+            (new_state, located)
+        })
+    }).boxed()
+}
+
+fn literal(bytes: &'static [u8]) -> HeadParser<()> {
+    let tag = tag(bytes).err_into::<Located<HttpError>>();
+    from_fn(move |state: ByteState| {
+        if state.remaining().len() < bytes.len() && bytes.starts_with(state.remaining()) {
+            return Err(state.locate_at_exactly(HttpError::Incomplete));
+        }
+        tag.do_pars(state)
+    }).boxed()
+}
+
+fn take_while1(description: &'static str, predicate: fn(u8) -> bool) -> HeadParser<Vec<u8>> {
+    from_fn(move |mut state: ByteState| {
+        let start = state.location().clone();
+        while state.peek().is_some_and(predicate) {
+            state.advance();
+        }
+        if state.location() == &start {
+            return Err(expected(&state, description));
+        }
+        let taken = state.since(&start).to_vec();
+        Ok((state, taken))
+    }).boxed()
+}
+
+fn token(description: &'static str) -> HeadParser<Vec<u8>> {
+    take_while1(description, is_tchar)
+}
+
+/// `\r\n` or a bare `\n`.
+fn line_end() -> HeadParser<()> {
+    from_fn(|mut state: ByteState| {
+        if state.peek() == Some(b'\r') {
+            state.advance();
+        }
+        match state.peek() {
+            Some(b'\n') => {
+                state.advance();
+                Ok((state, ()))
+            }
+            _ => Err(expected(&state, "line end")),
+        }
+    }).boxed()
+}
+
+/// `HTTP/x.y`, failing with `UnsupportedVersion` for major versions other than 1.
+fn version() -> HeadParser<Located<Version>> {
+    let digit = || spanned(take_while1("digit", |b| b.is_ascii_digit())).flat_map(|digits| from_fn(move |state: ByteState| match digits.target().as_slice() {
+        [digit] => Ok((state, digit - b'0')),
+        _ => Err(error_at(&digits, ParseError::expected_rule("single digit"))),
+    })).boxed();
+    let version = literal(b"HTTP/").map2(digit(), |_, major| major).ignore(literal(b".")).map2(digit(), |major, minor| Version { major, minor });
+    spanned(version.boxed()).flat_map(|version| from_fn(move |state: ByteState| {
+        if version.target().major != 1 {
+            return Err(error_at(&version, HttpError::UnsupportedVersion(*version.target())));
+        }
+        Ok((state, version.clone()))
+    })).boxed()
+}
+
+/// The start line and header fields up to and including the empty line, together with the
+/// length of the head. Sizes are measured from where the head starts, which isn't the start of
+/// the input for pipelined requests.
+fn head<L: 'static>(limits: HeadLimits, start_line: HeadParser<L>) -> HeadParser<(L, Vec<Header>, usize)> {
+    let line_end = line_end();
+    let field = field(limits.max_field_size);
+    let head = from_fn(move |state: ByteState| {
+        let head_start = state.location().byte_offset();
+        let size = move |state: &ByteState| state.location().byte_offset() - head_start;
+        let (mut state, start_line) = start_line.do_pars(state)?;
+        if size(&state) > limits.max_head_size {
+            return Err(state.locate_at_exactly(HttpError::HeadTooLarge(limits.max_head_size)));
+        }
+        let mut headers = vec![];
+        loop {
+            match line_end.do_pars(state.clone()) {
+                Ok((after_head, _)) => {
+                    let length = size(&after_head);
+                    return Ok((after_head, (start_line, headers, length)));
+                }
+                Err(error) if *error.target() == HttpError::Incomplete => return Err(error),
+                Err(_) => {}
+            }
+            if headers.len() == limits.max_headers {
+                return Err(state.locate_at_exactly(HttpError::TooManyHeaders(limits.max_headers)));
+            }
+            let (next_state, header) = field.do_pars(state)?;
+            if size(&next_state) > limits.max_head_size {
+                return Err(error_at(&header.name, HttpError::HeadTooLarge(limits.max_head_size)));
+            }
+            state = next_state;
+            headers.push(header);
+        }
+    }).boxed();
+    within_head(limits, head)
+}
+
+/// One header field with its line end, joining folded lines. Fails with `FieldTooLarge` as soon
+/// as the name or value gets longer than `max_field_size`.
+fn field(max_field_size: usize) -> HeadParser<Header> {
+    let name = spanned(token("header name"));
+    let line_end = line_end();
+    from_fn(move |state: ByteState| {
+        let (mut state, name) = name.do_pars(state)?;
+        let field_start = name.source_range().start.byte_offset();
+        if state.location().byte_offset() - field_start > max_field_size {
+            return Err(error_at(&name, HttpError::FieldTooLarge(max_field_size)));
+        }
+        if state.peek().is_some_and(is_whitespace) {
+            let start = state.location().clone();
+            while state.peek().is_some_and(is_whitespace) {
+                state.advance();
+            }
+            return Err(state.locate(start, HttpError::Malformed(ParseError::custom("whitespace between header name and colon"))));
+        }
+        let (mut state, _) = literal(b":").do_pars(state)?;
+        while state.peek().is_some_and(is_whitespace) {
+            state.advance();
+        }
+        let start = state.location().clone();
+        let mut end = start.clone();
+        let mut value = vec![];
+        loop {
+            let line_start = state.location().clone();
+            while let Some(b) = state.peek().filter(|b| is_field_content(*b)) {
+                if !is_whitespace(b) && state.location().byte_offset() + 1 - field_start > max_field_size {
+                    return Err(error_at(&name, HttpError::FieldTooLarge(max_field_size)));
+                }
+                state.advance();
+            }
+            let line = state.since(&line_start);
+            if let Some(last) = line.iter().rposition(|b| !is_whitespace(*b)) {
+                if !value.is_empty() {
+                    value.push(b' ');
+                }
+                value.extend_from_slice(&line[..=last]);
+                end = Location::new(line_start.byte_offset() + last + 1, line_start.column() + last + 1, line_start.row());
+            }
+            state = line_end.do_pars(state)?.0;
+            if !state.peek().is_some_and(is_whitespace) {
+                break;
+            }
+            while state.peek().is_some_and(is_whitespace) {
+                state.advance();
+            }
+        }
+        Ok((state, Header { name: name.map(ascii), value: start.locate(end, value) }))
+    }).boxed()
+}
+
+/// HTTP/1.1 requests need exactly one `Host` header, see section 5.4.
+fn check_host(head: &RequestHead) -> Option<Located<HttpError>> {
+    let mut hosts = head.headers.iter().filter(|header| header.name.target().eq_ignore_ascii_case("host"));
+    let first = hosts.next();
+    if let Some(duplicate) = hosts.next() {
+        return Some(error_at(&duplicate.name, ParseError::custom("duplicate `Host` header")));
+    }
+    if first.is_none() && head.version.target().minor >= 1 {
+        return Some(error_at(&head.version, ParseError::custom("HTTP/1.1 requests need a `Host` header")));
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bytes::ByteState;
+    use crate::formats::http::{self, HeadLimits, HttpError};
+    use crate::parser::Parser;
+    use crate::text::location::Location;
+
+    #[test]
+    fn request_heads() {
+        let input = b"\r\nPOST /upload?x=1 HTTP/1.1\r\nHost: example.com\r\nX-Folded: first \r\n \t second\r\nX-Empty:\r\nX-Bytes: caf\xc3\xa9 \xff\n\r\nbody";
+        let head = http::parse_request(&input[..]).unwrap();
+        assert_eq!("POST", head.method().target());
+        assert_eq!(Location::new(2, 1, 2), head.method().source_range().start);
+        assert_eq!("/upload?x=1", head.target().target());
+        assert_eq!((1, 1), (head.version().target().major(), head.version().target().minor()));
+        assert_eq!(input.len() - 4, head.body_offset());
+
+        let names: Vec<&str> = head.headers().iter().map(|header| header.name().target().as_str()).collect();
+        assert_eq!(vec!["Host", "X-Folded", "X-Empty", "X-Bytes"], names);
+        let folded = &head.headers()[1];
+        assert_eq!(b"first second".to_vec(), *folded.value().target());
+        assert_eq!((Location::new(58, 11, 4), Location::new(75, 10, 5)), (folded.value().source_range().start.clone(), folded.value().source_range().end.clone()));
+        assert_eq!(Some(&b""[..]), head.header("x-empty"));
+        assert_eq!(Some(&b"caf\xc3\xa9 \xff"[..]), head.header("X-BYTES"));
+        assert_eq!(None, head.header("Accept"));
+
+        let old = http::parse_request(&b"GET / HTTP/1.0\n\n"[..]).unwrap();
+        assert_eq!(0, old.headers().len());
+    }
+
+    #[test]
+    fn response_heads() {
+        let head = http::parse_response(&b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n"[..]).unwrap();
+        assert_eq!(404, *head.status().target());
+        assert_eq!(b"Not Found".to_vec(), *head.reason().target());
+        assert_eq!(Some(&b"0"[..]), head.header("content-length"));
+
+        let head = http::parse_response(&b"HTTP/1.0 204\r\n\r\n"[..]).unwrap();
+        assert_eq!(204, *head.status().target());
+        assert!(head.reason().target().is_empty());
+
+        let error = http::parse_response(&b"HTTP/1.1 20 OK\r\n\r\n"[..]).unwrap_err();
+        assert_eq!("status codes have three digits from 100 to 599", error.target().to_string());
+        assert_eq!(9, error.source_range().start.byte_offset());
+        let error = http::parse_response(&b"HTTP/1.1 200OK\r\n\r\n"[..]).unwrap_err();
+        assert_eq!("1:13: expected ` `", error.to_string());
+    }
+
+    #[test]
+    fn pipelined_heads() {
+        let first = b"POST /upload HTTP/1.1\r\nHost: a\r\nContent-Length: 70000\r\n\r\n".to_vec();
+        let second = b"GET / HTTP/1.1\r\nHost: a\r\n\r\n".to_vec();
+        let input = [first.clone(), vec![b'x'; 70_000], second.clone()].concat();
+        let parser = http::request_head(HeadLimits::new());
+        let (state, head) = parser.do_pars(ByteState::new(input.clone())).unwrap();
+        assert_eq!(first.len(), head.body_offset());
+        assert_eq!(first.len(), state.location().byte_offset());
+
+        let mut state = state;
+        for _ in 0..70_000 {
+            state.advance();
+        }
+        let (state, head) = parser.do_pars(state).unwrap();
+        assert_eq!("/", head.target().target());
+        assert_eq!(second.len(), head.body_offset());
+        assert_eq!(input.len(), state.location().byte_offset());
+    }
+
+    #[test]
+    fn malformed_heads() {
+        let cases: [(&[u8], u16, &str); 9] = [
+            (b"GET / HTTP/1.1\r\nHost : a\r\n\r\n", 400, "2:5: whitespace between header name and colon"),
+            (b"GET / HTTP/1.1\r\n Host: a\r\n\r\n", 400, "2:1: expected header name"),
+            (b"GET / HTTP/1.1\r\nHost: a\rb\r\n\r\n", 400, "2:9: expected line end"),
+            (b"GET  / HTTP/1.1\r\n\r\n", 400, "1:5: expected request target"),
+            (b"GET / HTTP/1.1\r\n\r\n", 400, "1:7: HTTP/1.1 requests need a `Host` header"),
+            (b"GET / HTTP/1.1\r\nHost: a\r\nhost: b\r\n\r\n", 400, "3:1: duplicate `Host` header"),
+            (b"GET / HTTP/2.0\r\n\r\n", 505, "1:7: HTTP/2.0 is not supported"),
+            (b"GET / HTTP/1.10\r\n\r\n", 400, "1:14: expected single digit"),
+            (b"GET / HTTP/1.1\r\nHost: a\r\nAccept: */", 400, "3:11: the head is incomplete"),
+        ];
+        for (input, status, message) in cases.iter() {
+            let error = http::parse_request(*input).unwrap_err();
+            assert_eq!((*status, *message), (error.target().status(), error.to_string().as_str()), "{}", String::from_utf8_lossy(input));
+        }
+        assert_eq!(HttpError::Incomplete, *http::parse_request(&b"GET / HT"[..]).unwrap_err().target());
+    }
+
+    #[test]
+    fn limits() {
+        let input = b"GET / HTTP/1.1\r\nHost: a\r\nA: 1\r\nB: 2\r\n\r\n";
+        let parse = |limits: HeadLimits| http::request_head(limits).do_pars(ByteState::new(&input[..])).map(|(_, head)| head);
+        assert!(parse(HeadLimits::new().with_max_headers(3).with_max_field_size(7).with_max_head_size(38)).is_ok());
+
+        let error = parse(HeadLimits::new().with_max_headers(2)).unwrap_err();
+        assert_eq!((431, HttpError::TooManyHeaders(2)), (error.target().status(), error.target().clone()));
+        assert_eq!(4, error.source_range().start.row());
+
+        let error = parse(HeadLimits::new().with_max_field_size(6)).unwrap_err();
+        assert_eq!(HttpError::FieldTooLarge(6), *error.target());
+        assert_eq!(2, error.source_range().start.row());
+
+        let error = parse(HeadLimits::new().with_max_head_size(30)).unwrap_err();
+        assert_eq!(HttpError::HeadTooLarge(30), *error.target());
+        assert_eq!(3, error.source_range().start.row());
+
+        // Limits are checked while scanning, before the input runs out.
+        let long_target = format!("GET /{} HTTP/1.0\r\n\r\n", "a".repeat(100_000));
+        let error = http::request_head(HeadLimits::new().with_max_head_size(30)).do_pars(ByteState::new(long_target)).unwrap_err();
+        assert_eq!((HttpError::HeadTooLarge(30), 32), (error.target().clone(), error.source_range().start.byte_offset()));
+        let error = parse(HeadLimits::new().with_max_head_size(14)).unwrap_err();
+        assert_eq!((HttpError::HeadTooLarge(14), 2), (error.target().clone(), error.source_range().start.row()));
+
+        let long_host = format!("GET / HTTP/1.1\r\nHost: {}", "a".repeat(1_000_000));
+        let error = http::parse_request(long_host).unwrap_err();
+        assert_eq!((431, HttpError::FieldTooLarge(8 * 1024)), (error.target().status(), error.target().clone()));
+        assert_eq!(2, error.source_range().start.row());
+        let trailing = &b"GET / HTTP/1.1\r\nHost: a      \r\n\r\n"[..];
+        assert!(http::request_head(HeadLimits::new().with_max_field_size(7)).do_pars(ByteState::new(trailing)).is_ok());
+    }
+}
@@ -0,0 +1,434 @@
+//! Dates, times, durations and intervals as specified by ISO 8601, converted to `chrono` types.
+//!
+//! ```
+//! use chrono::{Datelike, Timelike};
+//! use parsec::formats::iso8601;
+//!
+//! let date = iso8601::parse_date("2024-W09-4").unwrap();
+//! assert_eq!((2024, 2, 29), (date.year(), date.month(), date.day()));
+//! let time = iso8601::parse_date_time("2024-02-29T13:45:30.25+01:00").unwrap();
+//! assert_eq!(250_000_000, time.nanosecond());
+//!
+//! let error = iso8601::parse_date("2023-02-29").unwrap_err();
+//! assert_eq!("day out of range for month", error.target().to_string());
+//! assert_eq!(9, error.source_range().start.column());
+//! ```
+//!
+//! Dates have four digit years, in calendar (`2024-02-29`), ordinal (`2024-060`) or week
+//! (`2024-W09-4`) form, each in the extended form with separators or the basic form without.
+//! Times have hours, minutes and optional seconds with a fraction. Only seconds may have a
+//! fraction and `24:00` is not supported.
+
+use std::convert::TryFrom;
+
+use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Weekday};
+
+use crate::error::ParseError;
+use crate::formats::{chars1, fail, FormatParser, satisfy, spanned};
+use crate::parser::{named, Parser, Succeed};
+use crate::text::location::Located;
+use crate::text::text_parser::{end, TextParser, token};
+
+/// A duration like `P1Y2M3DT4H5M6.5S`. Years and months have no fixed length, so a duration
+/// with them is only exact relative to a date, see `IsoDuration::add_to`.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct IsoDuration {
+    years: u32,
+    months: u32,
+    weeks: u32,
+    days: u32,
+    hours: u32,
+    minutes: u32,
+    seconds: u32,
+    nanoseconds: u32,
+}
+
+impl IsoDuration {
+    pub fn years(&self) -> u32 {
+        self.years
+    }
+
+    pub fn months(&self) -> u32 {
+        self.months
+    }
+
+    pub fn weeks(&self) -> u32 {
+        self.weeks
+    }
+
+    pub fn days(&self) -> u32 {
+        self.days
+    }
+
+    pub fn hours(&self) -> u32 {
+        self.hours
+    }
+
+    pub fn minutes(&self) -> u32 {
+        self.minutes
+    }
+
+    pub fn seconds(&self) -> u32 {
+        self.seconds
+    }
+
+    pub fn nanoseconds(&self) -> u32 {
+        self.nanoseconds
+    }
+
+    /// The exact length, if the duration has neither years nor months.
+    pub fn to_duration(&self) -> Option<Duration> {
+        if self.years != 0 || self.months != 0 {
+            return None;
+        }
+        Some(self.fixed())
+    }
+
+    /// The part of the duration with a fixed length, all but years and months.
+    fn fixed(&self) -> Duration {
+        Duration::weeks(self.weeks.into())
+            + Duration::days(self.days.into())
+            + Duration::hours(self.hours.into())
+            + Duration::minutes(self.minutes.into())
+            + Duration::seconds(self.seconds.into())
+            + Duration::nanoseconds(self.nanoseconds.into())
+    }
+
+    /// `start` moved forward by the duration. Years and months are added first and move to the
+    /// last day of the month if the day does not exist, like `2024-01-31` plus `P1M` is
+    /// `2024-02-29`.
+    pub fn add_to(&self, start: DateTime<FixedOffset>) -> Option<DateTime<FixedOffset>> {
+        self.shift(start, 1)
+    }
+
+    /// `end` moved backward by the duration, the fixed part first and then years and months.
+    pub fn subtract_from(&self, end: DateTime<FixedOffset>) -> Option<DateTime<FixedOffset>> {
+        self.shift(end, -1)
+    }
+
+    fn shift(&self, time: DateTime<FixedOffset>, sign: i64) -> Option<DateTime<FixedOffset>> {
+        let months = sign * (i64::from(self.years) * 12 + i64::from(self.months));
+        if sign > 0 {
+            add_months(time, months)?.checked_add_signed(self.fixed())
+        } else {
+            add_months(time.checked_sub_signed(self.fixed())?, months)
+        }
+    }
+}
+
+fn add_months(time: DateTime<FixedOffset>, months: i64) -> Option<DateTime<FixedOffset>> {
+    let local = time.naive_local();
+    let month0 = i64::from(local.year()) * 12 + i64::from(local.month0()) + months;
+    let year = i32::try_from(month0.div_euclid(12)).ok()?;
+    let month = month0.rem_euclid(12) as u32 + 1;
+    let date = (1..=local.day()).rev().find_map(|day| NaiveDate::from_ymd_opt(year, month, day))?;
+    time.offset().from_local_datetime(&date.and_time(local.time())).single()
+}
+
+/// A time interval, given by two of start, end and duration.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Interval {
+    Between(DateTime<FixedOffset>, DateTime<FixedOffset>),
+    Starting(DateTime<FixedOffset>, IsoDuration),
+    Ending(IsoDuration, DateTime<FixedOffset>),
+}
+
+impl Interval {
+    /// The start, if it can be represented.
+    pub fn start(&self) -> Option<DateTime<FixedOffset>> {
+        match self {
+            Interval::Between(start, _) | Interval::Starting(start, _) => Some(*start),
+            Interval::Ending(duration, end) => duration.subtract_from(*end),
+        }
+    }
+
+    /// The end, if it can be represented.
+    pub fn end(&self) -> Option<DateTime<FixedOffset>> {
+        match self {
+            Interval::Between(_, end) | Interval::Ending(_, end) => Some(*end),
+            Interval::Starting(start, duration) => duration.add_to(*start),
+        }
+    }
+}
+
+pub fn parse_date(input: impl Into<String>) -> Result<NaiveDate, Located<ParseError>> {
+    date().ignore(end()).pars(input)
+}
+
+pub fn parse_date_time(input: impl Into<String>) -> Result<DateTime<FixedOffset>, Located<ParseError>> {
+    date_time().ignore(end()).pars(input)
+}
+
+pub fn parse_duration(input: impl Into<String>) -> Result<IsoDuration, Located<ParseError>> {
+    duration().ignore(end()).pars(input)
+}
+
+pub fn parse_interval(input: impl Into<String>) -> Result<Interval, Located<ParseError>> {
+    interval().ignore(end()).pars(input)
+}
+
+/// The components of a date as written, before they are checked.
+#[derive(Debug, Clone)]
+enum RawDate {
+    Calendar(Located<u32>, Located<u32>),
+    Ordinal(Located<u32>),
+    Week(Located<u32>, Located<u32>),
+}
+
+/// A number of exactly `count` digits.
+fn digits(count: usize) -> FormatParser<Located<u32>> {
+    let digit = || satisfy("digit", |c| c.is_ascii_digit()).map(|c| c.to_digit(10).expect("digit"));
+    let mut number: FormatParser<u32> = Succeed::with(0).boxed();
+    for _ in 0..count {
+        number = number.map2(digit(), |number, digit| number * 10 + digit).boxed();
+    }
+    spanned(number)
+}
+
+/// Fails with `message` located at `component`.
+fn invalid<T: 'static, U>(component: &Located<U>, message: &str) -> FormatParser<T> {
+    let range = component.source_range();
+    fail(range.start.clone().locate(range.end.clone(), ParseError::custom(message)))
+}
+
+/// A calendar, ordinal or week date.
+pub fn date() -> FormatParser<NaiveDate> {
+    let extended = token("-W").map2(digits(2), |_, week| week).ignore(token("-")).map2(digits(1), RawDate::Week)
+        .or(token("-").map2(digits(2), |_, month| month).ignore(token("-")).map2(digits(2), RawDate::Calendar))
+        .or(token("-").map2(digits(3), |_, day| RawDate::Ordinal(day)));
+    let basic = token("W").map2(digits(2), |_, week| week).map2(digits(1), RawDate::Week)
+        .or(digits(2).map2(digits(2), RawDate::Calendar))
+        .or(digits(3).map(RawDate::Ordinal));
+    named("date", digits(4).map2(extended.or(basic), |year, date| (year, date)).flat_map(|(year, date)| {
+        let year = *year.target() as i32;
+        let checked = match &date {
+            RawDate::Calendar(month, day) => {
+                if !(1..=12).contains(month.target()) {
+                    return invalid(month, "month out of range");
+                }
+                NaiveDate::from_ymd_opt(year, *month.target(), *day.target()).ok_or((day, "day out of range for month"))
+            }
+            RawDate::Ordinal(day) => NaiveDate::from_yo_opt(year, *day.target()).ok_or((day, "day out of range for year")),
+            RawDate::Week(week, day) => {
+                let weekday = match day.target() {
+                    1 => Weekday::Mon,
+                    2 => Weekday::Tue,
+                    3 => Weekday::Wed,
+                    4 => Weekday::Thu,
+                    5 => Weekday::Fri,
+                    6 => Weekday::Sat,
+                    7 => Weekday::Sun,
+                    _ => return invalid(day, "weekday out of range"),
+                };
+                NaiveDate::from_isoywd_opt(year, *week.target(), weekday).ok_or((week, "week out of range for year"))
+            }
+        };
+        match checked {
+            Ok(date) => Succeed::with(date).boxed(),
+            Err((component, message)) => invalid(component, message),
+        }
+    })).boxed()
+}
+
+/// The fraction after a `.` or `,` in nanoseconds. Digits after the ninth are dropped.
+fn fraction() -> FormatParser<u32> {
+    satisfy("`.`", |c| c == '.' || c == ',')
+        .map2(chars1("digit", |c| c.is_ascii_digit()), |_, digits| {
+            let nanos: String = digits.chars().chain(std::iter::repeat('0')).take(9).collect();
+            nanos.parse().expect("nine digits")
+        })
+        .boxed()
+}
+
+/// A time of day with hours, minutes and optional seconds.
+pub fn time() -> FormatParser<NaiveTime> {
+    let seconds = || digits(2).map2(fraction().optional(), |seconds, fraction| (seconds, fraction.unwrap_or(0)));
+    let extended = token(":").map2(digits(2), |_, minutes| minutes).map2(token(":").map2(seconds(), |_, seconds| seconds).optional(), |minutes, seconds| (minutes, seconds));
+    let basic = digits(2).map2(seconds().optional(), |minutes, seconds| (minutes, seconds));
+    named("time", digits(2).map2(extended.or(basic), |hours, (minutes, seconds)| (hours, minutes, seconds)).flat_map(|(hours, minutes, seconds)| {
+        if *hours.target() > 23 {
+            return invalid(&hours, "hour out of range");
+        }
+        if *minutes.target() > 59 {
+            return invalid(&minutes, "minute out of range");
+        }
+        let (second, nanos) = match &seconds {
+            Some((seconds, _)) if *seconds.target() > 60 => return invalid(seconds, "second out of range"),
+            // chrono represents a leap second as the 59th second with more than a second of nanoseconds.
+            Some((seconds, nanos)) if *seconds.target() == 60 => (59, 1_000_000_000 + nanos),
+            Some((seconds, nanos)) => (*seconds.target(), *nanos),
+            None => (0, 0),
+        };
+        let time = NaiveTime::from_hms_nano_opt(*hours.target(), *minutes.target(), second, nanos).expect("components are in range");
+        Succeed::with(time).boxed()
+    })).boxed()
+}
+
+/// `Z` or an offset from UTC like `+01:00`, `-0530` or `+02`.
+pub fn offset() -> FormatParser<FixedOffset> {
+    let sign = token("+").map(|_| 1).or(token("-").map(|_| -1));
+    let minutes = token(":").optional().map2(digits(2), |_, minutes| minutes).optional();
+    let numeric = (sign, digits(2), minutes).flat_map(|(sign, hours, minutes)| {
+        if *hours.target() > 23 {
+            return invalid(&hours, "offset hour out of range");
+        }
+        let minutes = match minutes {
+            Some(minutes) if *minutes.target() > 59 => return invalid(&minutes, "offset minute out of range"),
+            Some(minutes) => *minutes.target() as i32,
+            None => 0,
+        };
+        let seconds = sign * ((*hours.target() as i32) * 3600 + minutes * 60);
+        Succeed::with(FixedOffset::east_opt(seconds).expect("offset is less than a day")).boxed()
+    });
+    named("offset", token("Z").map(|_| FixedOffset::east_opt(0).expect("zero offset")).or(numeric)).boxed()
+}
+
+/// A date and time without offset.
+pub fn naive_date_time() -> FormatParser<NaiveDateTime> {
+    date().ignore(token("T")).map2(time(), |date, time| date.and_time(time)).boxed()
+}
+
+/// A date and time with offset.
+pub fn date_time() -> FormatParser<DateTime<FixedOffset>> {
+    named("date time", naive_date_time().map2(offset(), |time, offset| {
+        offset.from_local_datetime(&time).single().expect("fixed offsets are unambiguous")
+    })).boxed()
+}
+
+/// A duration like `P3DT12H`, `PT0.5S` or `P2W`. A duration needs at least one component and
+/// only the seconds may have a fraction.
+pub fn duration() -> FormatParser<IsoDuration> {
+    let number = || spanned(chars1("digit", |c| c.is_ascii_digit()));
+    let component = move |designator: &'static str| number().ignore(token(designator)).optional();
+    let seconds = number().map2(fraction().optional(), |seconds, fraction| (seconds, fraction.unwrap_or(0))).ignore(token("S")).optional();
+    let date_part = (component("Y"), component("M"), component("W"), component("D"));
+    let time_part = spanned(token("T")).map2((component("H"), component("M"), seconds), |designator, time| (designator, time)).optional();
+    let duration = spanned(token("P").map2(date_part.map2(time_part, |date, time| (date, time)), |_, components| components));
+    named("duration", duration.flat_map(|components| {
+        let ((years, months, weeks, days), time) = components.target().clone();
+        let (hours, minutes, seconds) = match &time {
+            Some((designator, (None, None, None))) => return invalid(designator, "expected hours, minutes or seconds after `T`"),
+            Some((_, time)) => time.clone(),
+            None => (None, None, None),
+        };
+        let (seconds, nanoseconds) = match seconds {
+            Some((seconds, nanoseconds)) => (Some(seconds), nanoseconds),
+            None => (None, 0),
+        };
+        let fields = [years, months, weeks, days, hours, minutes, seconds];
+        if fields.iter().all(Option::is_none) {
+            return invalid(&components, "expected at least one component in duration");
+        }
+        let mut values = [0; 7];
+        for (value, field) in values.iter_mut().zip(fields.iter()) {
+            if let Some(number) = field {
+                *value = match number.target().parse() {
+                    Ok(parsed) => parsed,
+                    Err(_) => return invalid(number, "number out of range"),
+                };
+            }
+        }
+        let [years, months, weeks, days, hours, minutes, seconds] = values;
+        Succeed::with(IsoDuration { years, months, weeks, days, hours, minutes, seconds, nanoseconds }).boxed()
+    })).boxed()
+}
+
+/// An interval as `start/end`, `start/duration` or `duration/end`.
+pub fn interval() -> FormatParser<Interval> {
+    let ending = duration().ignore(token("/")).map2(date_time(), Interval::Ending);
+    let starting = date_time().ignore(token("/")).map2(
+        duration().map(Ok).or(spanned(date_time()).map(Err)),
+        |start, rest| (start, rest),
+    ).flat_map(|(start, rest)| match rest {
+        Ok(duration) => Succeed::with(Interval::Starting(start, duration)).boxed(),
+        Err(end) if *end.target() < start => invalid(&end, "interval ends before it starts"),
+        Err(end) => Succeed::with(Interval::Between(start, end.into_target())).boxed(),
+    });
+    named("interval", ending.or(starting)).boxed()
+}
+
+#[cfg(test)]
+mod test {
+    use std::fmt::Debug;
+
+    use chrono::{Duration, FixedOffset, NaiveDate, TimeZone, Timelike};
+
+    use crate::error::ParseError;
+    use crate::formats::iso8601::{self, Interval};
+    use crate::text::location::Located;
+
+    fn offset(seconds: i32) -> FixedOffset {
+        FixedOffset::east_opt(seconds).expect("offset in range")
+    }
+
+    fn error<T: Debug>(result: Result<T, Located<ParseError>>) -> (String, usize) {
+        let error = result.expect_err("invalid input");
+        (error.target().to_string(), error.source_range().start.column())
+    }
+
+    #[test]
+    fn parses_dates() {
+        let expected = NaiveDate::from_ymd_opt(2020, 12, 31).unwrap();
+        for input in ["2020-12-31", "20201231", "2020-366", "2020366", "2020-W53-4", "2020W534"] {
+            assert_eq!(Ok(expected), iso8601::parse_date(input), "parses {}", input);
+        }
+        assert_eq!(Ok(NaiveDate::from_ymd_opt(2019, 12, 30).unwrap()), iso8601::parse_date("2020-W01-1"));
+
+        assert_eq!((String::from("month out of range"), 6), error(iso8601::parse_date("2021-13-01")));
+        assert_eq!((String::from("day out of range for month"), 9), error(iso8601::parse_date("2021-04-31")));
+        assert_eq!((String::from("day out of range for year"), 6), error(iso8601::parse_date("2021-366")));
+        assert_eq!((String::from("week out of range for year"), 7), error(iso8601::parse_date("2021-W53-1")));
+        assert_eq!((String::from("weekday out of range"), 10), error(iso8601::parse_date("2021-W10-8")));
+        assert!(iso8601::parse_date("2021-1-01").is_err());
+    }
+
+    #[test]
+    fn parses_times_and_offsets() {
+        let time = iso8601::parse_date_time("2021-06-01T08:30:15,5-05:30").expect("valid date time");
+        assert_eq!(offset(-(5 * 3600 + 30 * 60)).with_ymd_and_hms(2021, 6, 1, 8, 30, 15).unwrap().with_nanosecond(500_000_000).unwrap(), time);
+        assert_eq!(time, iso8601::parse_date_time("20210601T083015.500-0530").expect("valid date time"));
+        let utc = iso8601::parse_date_time("2016-12-31T23:59:60Z").expect("leap second");
+        assert_eq!(1_000_000_000, utc.nanosecond());
+        assert_eq!(Ok(offset(2 * 3600).with_ymd_and_hms(2021, 6, 1, 8, 30, 0).unwrap()), iso8601::parse_date_time("2021-06-01T08:30+02"));
+
+        assert_eq!((String::from("hour out of range"), 12), error(iso8601::parse_date_time("2021-06-01T24:00Z")));
+        assert_eq!((String::from("minute out of range"), 15), error(iso8601::parse_date_time("2021-06-01T12:60Z")));
+        assert_eq!((String::from("second out of range"), 18), error(iso8601::parse_date_time("2021-06-01T12:00:61Z")));
+        assert_eq!((String::from("offset hour out of range"), 18), error(iso8601::parse_date_time("2021-06-01T12:00+24:00")));
+        assert!(iso8601::parse_date_time("2021-06-01T12:00").is_err());
+    }
+
+    #[test]
+    fn parses_durations() {
+        let duration = iso8601::parse_duration("P1Y2M3DT4H5M6.25S").expect("valid duration");
+        assert_eq!((1, 2, 3, 4, 5, 6, 250_000_000), (duration.years(), duration.months(), duration.days(), duration.hours(),
+            duration.minutes(), duration.seconds(), duration.nanoseconds()));
+        assert_eq!(None, duration.to_duration());
+        assert_eq!(Some(Duration::days(15)), iso8601::parse_duration("P2W1D").expect("valid duration").to_duration());
+        assert_eq!(Some(Duration::milliseconds(500)), iso8601::parse_duration("PT0,5S").expect("valid duration").to_duration());
+
+        let start = offset(0).with_ymd_and_hms(2024, 1, 31, 12, 0, 0).unwrap();
+        let month = iso8601::parse_duration("P1M").expect("valid duration");
+        assert_eq!(Some(offset(0).with_ymd_and_hms(2024, 2, 29, 12, 0, 0).unwrap()), month.add_to(start));
+
+        assert_eq!((String::from("expected at least one component in duration"), 1), error(iso8601::parse_duration("P")));
+        assert_eq!((String::from("expected hours, minutes or seconds after `T`"), 4), error(iso8601::parse_duration("P1DT")));
+        assert_eq!((String::from("number out of range"), 2), error(iso8601::parse_duration("P99999999999D")));
+        assert!(iso8601::parse_duration("P1H").is_err());
+        assert!(iso8601::parse_duration("PT1.5M").is_err());
+    }
+
+    #[test]
+    fn parses_intervals() {
+        let start = offset(0).with_ymd_and_hms(2007, 3, 1, 13, 0, 0).unwrap();
+        let end = offset(0).with_ymd_and_hms(2008, 5, 11, 15, 30, 0).unwrap();
+        let between = iso8601::parse_interval("2007-03-01T13:00:00Z/2008-05-11T15:30:00Z").expect("valid interval");
+        assert_eq!(Interval::Between(start, end), between);
+        let starting = iso8601::parse_interval("2007-03-01T13:00:00Z/P1Y2M10DT2H30M").expect("valid interval");
+        assert_eq!((Some(start), Some(end)), (starting.start(), starting.end()));
+        let ending = iso8601::parse_interval("P1Y2M10DT2H30M/2008-05-11T15:30:00Z").expect("valid interval");
+        assert_eq!((Some(start), Some(end)), (ending.start(), ending.end()));
+
+        assert_eq!((String::from("interval ends before it starts"), 22), error(iso8601::parse_interval("2008-05-11T15:30:00Z/2007-03-01T13:00:00Z")));
+    }
+}
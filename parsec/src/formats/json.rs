@@ -0,0 +1,418 @@
+//! JSON as specified by RFC 8259.
+//!
+//! ```
+//! use parsec::formats::json::{self, JsonOptions, JsonValue};
+//!
+//! let config = json::parse(r#"{"port": 80, "hosts": ["a", "b"]}"#, JsonOptions::default()).unwrap();
+//! let port = config.target().get("port").unwrap();
+//! assert_eq!(Some(80), port.target().as_number().and_then(|number| number.as_i64()));
+//! assert_eq!(10, port.source_range().start.column());
+//! ```
+
+use std::collections::hash_map::{Entry, HashMap};
+use std::fmt::{Display, Formatter};
+
+use crate::error::ParseError;
+use crate::formats::{chars1, fail, FormatParser, satisfy, separated1, spanned, too_deep, until};
+use crate::parser::{lazy, named, Parser, Succeed};
+use crate::text::location::Located;
+use crate::text::text_parser::{Chop, end, TextParser, token};
+
+/// A JSON value. Every nested value and every key carries its location in the input.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(JsonNumber),
+    String(String),
+    Array(Vec<Located<JsonValue>>),
+    /// The members in input order, see `DuplicateKeys` for members with the same key.
+    Object(Vec<(Located<String>, Located<JsonValue>)>),
+}
+
+impl JsonValue {
+    /// The value of the last member with `key`, if this is an object.
+    pub fn get(&self, key: &str) -> Option<&Located<JsonValue>> {
+        match self {
+            JsonValue::Object(members) => members.iter().rev()
+                .find(|(member_key, _)| member_key.target() == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            JsonValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_number(&self) -> Option<&JsonNumber> {
+        match self {
+            JsonValue::Number(number) => Some(number),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Located<JsonValue>]> {
+        match self {
+            JsonValue::Array(elements) => Some(elements),
+            _ => None,
+        }
+    }
+}
+
+/// A number as it was written, so integers keep their precision until they are converted.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct JsonNumber(String);
+
+impl JsonNumber {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The number as float. Numbers out of range become infinite.
+    pub fn as_f64(&self) -> f64 {
+        self.0.parse().expect("JSON numbers are valid floats")
+    }
+
+    /// The number as integer, if it is written without fraction and exponent and fits.
+    pub fn as_i64(&self) -> Option<i64> {
+        self.0.parse().ok()
+    }
+}
+
+impl Display for JsonNumber {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// What to do with objects that have several members with the same key. RFC 8259 leaves this
+/// open.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum DuplicateKeys {
+    /// Fail at the second occurrence of the key.
+    Reject,
+    /// Keep the member that comes first.
+    FirstWins,
+    /// Keep the member that comes last.
+    LastWins,
+    /// Keep all members.
+    KeepAll,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct JsonOptions {
+    duplicate_keys: DuplicateKeys,
+    max_depth: usize,
+}
+
+impl JsonOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_duplicate_keys(self, duplicate_keys: DuplicateKeys) -> Self {
+        Self { duplicate_keys, ..self }
+    }
+
+    /// How deep arrays and objects may be nested. Deeper input fails with
+    /// `LimitExceeded::Depth`, before the nesting can overflow the stack.
+    pub fn with_max_depth(self, max_depth: usize) -> Self {
+        Self { max_depth, ..self }
+    }
+
+    pub fn duplicate_keys(&self) -> DuplicateKeys {
+        self.duplicate_keys
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+}
+
+/// Rejects duplicate keys and allows 64 levels of nesting, which fits on the stack of a thread
+/// with the default size even in debug builds.
+impl Default for JsonOptions {
+    fn default() -> Self {
+        Self { duplicate_keys: DuplicateKeys::Reject, max_depth: 64 }
+    }
+}
+
+/// Parses a whole JSON text.
+pub fn parse(input: impl Into<String>, options: JsonOptions) -> Result<Located<JsonValue>, Located<ParseError>> {
+    document(options).pars(input)
+}
+
+/// A JSON text: one value with optional whitespace around it and nothing after it.
+pub fn document(options: JsonOptions) -> FormatParser<Located<JsonValue>> {
+    whitespace().map2(value(options, 0), |_, value| value)
+        .ignore(whitespace())
+        .ignore(end())
+        .boxed()
+}
+
+/// A value without surrounding whitespace, nested in `depth` arrays or objects.
+pub fn value(options: JsonOptions, depth: usize) -> FormatParser<Located<JsonValue>> {
+    let literal = token("null").map(|_| JsonValue::Null)
+        .or(token("true").map(|_| JsonValue::Bool(true)))
+        .or(token("false").map(|_| JsonValue::Bool(false)));
+    let value = object(options, depth)
+        .or(array(options, depth))
+        .or(string().map(JsonValue::String))
+        .or(number().map(JsonValue::Number))
+        .or(literal);
+    named("value", spanned(value)).boxed()
+}
+
+fn whitespace() -> FormatParser<String> {
+    Chop::while_con(|c| matches!(c, ' ' | '\t' | '\n' | '\r'))
+        .described_as("whitespace")
+        .err_into::<Located<ParseError>>()
+        .boxed()
+}
+
+/// A value with the whitespace after it.
+fn element(options: JsonOptions, depth: usize) -> FormatParser<Located<JsonValue>> {
+    value(options, depth).ignore(whitespace()).boxed()
+}
+
+fn comma() -> FormatParser<String> {
+    token(",").ignore(whitespace()).boxed()
+}
+
+fn array(options: JsonOptions, depth: usize) -> FormatParser<JsonValue> {
+    if depth >= options.max_depth {
+        return too_deep("[", options.max_depth);
+    }
+    let elements = separated1(lazy(move || element(options, depth + 1)).boxed(), comma());
+    let contents = token("]").map(|_| vec![]).or(elements.ignore(token("]")));
+    token("[").ignore(whitespace()).map2(contents, |_, elements| JsonValue::Array(elements)).boxed()
+}
+
+fn object(options: JsonOptions, depth: usize) -> FormatParser<JsonValue> {
+    if depth >= options.max_depth {
+        return too_deep("{", options.max_depth);
+    }
+    let member = spanned(string()).ignore(whitespace()).ignore(token(":")).ignore(whitespace())
+        .map2(lazy(move || element(options, depth + 1)), |key, value| (key, value));
+    let members = separated1(member.boxed(), comma());
+    let contents = token("}").map(|_| vec![]).or(members.ignore(token("}")));
+    token("{").ignore(whitespace()).map2(contents, |_, members| members)
+        .flat_map(move |members| resolve_duplicates(members, options.duplicate_keys))
+        .boxed()
+}
+
+type Member = (Located<String>, Located<JsonValue>);
+
+fn resolve_duplicates(members: Vec<Member>, policy: DuplicateKeys) -> FormatParser<JsonValue> {
+    if policy == DuplicateKeys::KeepAll {
+        return Succeed::with(JsonValue::Object(members)).boxed();
+    }
+    // The index of the member that is kept for each key.
+    let mut kept: HashMap<&str, usize> = HashMap::with_capacity(members.len());
+    for (index, (key, _)) in members.iter().enumerate() {
+        match kept.entry(key.target().as_str()) {
+            Entry::Vacant(entry) => {
+                entry.insert(index);
+            }
+            Entry::Occupied(_) if policy == DuplicateKeys::Reject => {
+                return fail(key.clone().map(|key| ParseError::custom(format!("duplicate key \"{}\"", key))));
+            }
+            Entry::Occupied(mut entry) => {
+                if policy == DuplicateKeys::LastWins {
+                    entry.insert(index);
+                }
+            }
+        }
+    }
+    let keep: Vec<bool> = members.iter().enumerate().map(|(index, (key, _))| kept[key.target().as_str()] == index).collect();
+    let kept = members.into_iter().zip(keep).filter(|(_, keep)| *keep).map(|(member, _)| member).collect();
+    Succeed::with(JsonValue::Object(kept)).boxed()
+}
+
+/// A char of a string, or one UTF-16 code unit of an escape. Escaped surrogate pairs are
+/// combined when the string is complete.
+#[derive(Debug, Clone, Copy)]
+enum Piece {
+    Char(char),
+    Unit(u16),
+}
+
+fn string() -> FormatParser<String> {
+    let unescaped = satisfy("character", |c| c >= '\u{20}' && c != '"' && c != '\\').map(Piece::Char);
+    let simple = satisfy("escape character", |c| "\"\\/bfnrt".contains(c)).map(|c| Piece::Char(match c {
+        'b' => '\u{8}',
+        'f' => '\u{c}',
+        'n' => '\n',
+        'r' => '\r',
+        't' => '\t',
+        c => c,
+    }));
+    let hex = || satisfy("hex digit", |c| c.is_ascii_hexdigit()).map(|c| c.to_digit(16).expect("hex digit") as u16);
+    let unit = token("u").map2((hex(), hex(), hex(), hex()), |_, (a, b, c, d)| Piece::Unit(a << 12 | b << 8 | c << 4 | d));
+    let escape = token("\\").map2(simple.or(unit), |_, piece| piece);
+    let quoted = token("\"").map2(until(unescaped.or(escape).boxed(), token("\"").boxed()), |_, pieces| pieces);
+    named("string", spanned(quoted).flat_map(|pieces| match decode(pieces.target()) {
+        Some(string) => Succeed::with(string).boxed(),
+        None => fail(pieces.map(|_| ParseError::custom("unpaired surrogate in string"))),
+    })).boxed()
+}
+
+fn decode(pieces: &[Piece]) -> Option<String> {
+    let mut decoded = String::new();
+    let mut units = vec![];
+    for piece in pieces {
+        match *piece {
+            Piece::Unit(unit) => units.push(unit),
+            Piece::Char(c) => {
+                decoded.push_str(&String::from_utf16(&units).ok()?);
+                units.clear();
+                decoded.push(c);
+            }
+        }
+    }
+    decoded.push_str(&String::from_utf16(&units).ok()?);
+    Some(decoded)
+}
+
+fn number() -> FormatParser<JsonNumber> {
+    let digits1 = || chars1("digit", |c| c.is_ascii_digit());
+    let integer = token("0").or(digits1());
+    let fraction = token(".").map2(digits1(), |dot, digits| dot + &digits);
+    let exponent = (satisfy("exponent", |c| c == 'e' || c == 'E'), satisfy("sign", |c| c == '+' || c == '-').optional(), digits1())
+        .map(|(e, sign, digits)| format!("{}{}{}", e, sign.map(String::from).unwrap_or_default(), digits));
+    let number = (token("-").optional(), integer, fraction.optional(), exponent.optional())
+        .map(|(minus, integer, fraction, exponent)| JsonNumber(format!("{}{}{}{}",
+            minus.unwrap_or_default(), integer, fraction.unwrap_or_default(), exponent.unwrap_or_default())));
+    named("number", number).boxed()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::ParseError;
+    use crate::formats::json::{self, DuplicateKeys, JsonOptions, JsonValue};
+    use crate::limits::LimitExceeded;
+    use crate::text::location::Location;
+
+    /// Accept cases of the JSONTestSuite by Nicolas Seriot, with the `y_` prefix dropped.
+    const ACCEPTED: &[&str] = &[
+        "[[]   ]", "[\"\"]", "[]", "[\"a\"]", "[false]", "[null, 1, \"1\", {}]", "[null]", "[1\n]",
+        " [1]", "[1,null,null,null,2]", "[2] ", "[123e65]", "[0e+1]", "[0e1]", "[ 4]", "[-0.000000000000000000000000000000000000000000000000000000000000000000000000000001]\n",
+        "[20e1]", "[-0]", "[-123]", "[-1]", "[1E22]", "[1E-2]", "[1E+2]", "[123e45]", "[123.456e78]",
+        "[1e-2]", "[1e+2]", "[123]", "[123.456789]", "{\"asd\":\"sdf\", \"dfg\":\"fgh\"}", "{\"asd\":\"sdf\"}",
+        "{\"\":0}", "{\"foo\\u0000bar\": 42}", "{ \"min\": -1.0e+28, \"max\": 1.0e+28 }", "{\"x\":[{\"id\": \"xxx\"}], \"id\": \"xxx\"}",
+        "{\"a\":[]}", "{\"title\":\"\\u041f\\u043e\\u043b\\u0442\\u043e\\u0440\\u0430 \\u0417\\u0435\\u043c\\u043b\\u0435\\u043a\\u043e\\u043f\\u0430\" }",
+        "{\n\"a\": \"b\"\n}", "[\"\\u0060\\u012a\\u12AB\"]", "[\"\\uD801\\udc37\"]", "[\"\\\"\\\\\\/\\b\\f\\n\\r\\t\"]",
+        "[\"\\\\u0000\"]", "[\"\\\"\"]", "[\"a/*b*/c/*d//e\"]", "[\"\\\\a\"]", "[\"\\\\n\"]", "[\"\\u0012\"]",
+        "[\"\\uFFFF\"]", "[\"asd\"]", "[ \"asd\"]", "[\"\\uDBFF\\uDFFF\"]", "[\"new\\u00A0line\"]", "[\"\u{10FFFF}\"]",
+        "[\"\u{7F}\"]", "[\"\\u0000\"]", "[\"\\u002c\"]", "[\"\u{3C0}\"]", "[\"asd \"]", "\" \"", "[\"\\uD834\\uDd1e\"]",
+        "[\"\\u0821\"]", "[\"\\u0123\"]", "[\"\u{2028}\"]", "[\"\u{2029}\"]", "[\"\\u0061\\u30af\\u30EA\\u30b9\"]",
+        "[\"new\\u000Aline\"]", "[\"\u{7F}\"]", "[\"\\uA66D\"]", "[\"\\u005C\"]", "[\"\u{2342}\u{3234}\u{2342}\"]",
+        "[\"\\uDBFF\\uDFFE\"]", "[\"\\uD83F\\uDFFE\"]", "[\"\\u200B\"]", "[\"\\u2064\"]", "[\"\\uFDD0\"]", "[\"\\uFFFE\"]",
+        "[\"\\u0022\"]", "[\"\u{20AC}\u{1D11E}\"]", "[\"a\u{7F}a\"]", "false", "42", "-0.1", "null", "\"asd\"", "true",
+        "\"\"", "[\"a\"]\n", "[true]", " [] ",
+    ];
+
+    /// Reject cases of the JSONTestSuite, with the `n_` prefix dropped.
+    const REJECTED: &[&str] = &[
+        "[1 true]", "[a\u{e5}]", "[\"\": 1]", "[\"\"],", "[,1]", "[1,,2]", "[\"x\",,]", "[\"x\"]]", "[\"\",]", "[\"x\"",
+        "[x", "[3[4]]", "[1:2]", "[,]", "[-]", "[   , \"\"]", "[\"a\",\n4\n,1,", "[1,]", "[1,,]", "[\"\u{b}a\"\\f]", "[*]",
+        "[\"\"", "[1,", "[1,\n1\n,1", "[{}", "[fals]", "[nul]", "[tru]", "[++1234]", "[+1]", "[+Inf]", "[-01]", "[-1.0.]",
+        "[-2.]", "[-NaN]", "[.-1]", "[.2e-3]", "[0.1.2]", "[0.3e+]", "[0.3e]", "[0.e1]", "[0E+]", "[0E]", "[0e+]", "[0e]",
+        "[1.0e+]", "[1.0e-]", "[1.0e]", "[1 000.0]", "[1eE2]", "[2.e+3]", "[2.e-3]", "[2.e3]", "[9.e+]", "[Inf]", "[NaN]",
+        "[\u{FF11}]", "[1+2]", "[0x1]", "[0x42]", "[Infinity]", "[0e+-1]", "[-123.123foo]", "[123\u{e5}]", "[1e1\u{e5}]",
+        "[0\u{e5}]", "[-Infinity]", "[-foo]", "[- 1]", "[-012]", "[-.123]", "[-1x]", "[1ea]", "[1.]", "[.123]", "[1.2a-3]",
+        "[1.8011670033376514H-308]", "[012]", "[\"x\", truth]", "{[: \"x\"}\n", "{\"x\", null}", "{\"x\"::\"b\"}",
+        "{\"a\":\"a\" 123}", "{key: 'value'}", "{\"a\" b}", "{:\"b\"}", "{\"a\" \"b\"}", "{\"a\":", "{\"a\"", "{1:1}",
+        "{9999E9999:1}", "{null:null,null:null}", "{\"id\":0,,,,,}", "{'a':0}", "{\"id\":0,}", "{\"a\":\"b\"}/**/",
+        "{\"a\":\"b\"}/**//", "{\"a\":\"b\"}//", "{\"a\":\"b\"}/", "{\"a\":\"b\",,\"c\":\"d\"}", "{a: \"b\"}", "{\"a\":\"a",
+        "{ \"foo\" : \"bar\", \"a\" }", "{\"a\":\"b\"}#", " ", "[\"\\uD800\\\"]", "[\"\\uD800\\u\"]", "[\"\\uD800\\u1\"]",
+        "[\"\\uD800\\u1x\"]", "[\u{e9}]", "[\"\\x00\"]", "[\"\\\\\\\"]", "[\"\\\t\"]", "[\"\\\u{1F300}\"]", "[\"\\\"]",
+        "[\"\\u00A\"]", "[\"\\uD834\\uDd\"]", "[\"\\uD800\\uD800\\x\"]", "[\"\\a\"]", "[\"\\uqqqq\"]", "[\\u0020\"asd\"]",
+        "[\\n]", "\"", "['single quote']", "abc", "[\"\\", "[\"new\nline\"]", "[\"\t\"]", "\"\\UA66D\"", "\"\"x",
+        "[\u{2060}]", "[\"\\uD800\"]", "[\"\\uDFAA\"]", "[\"\\uD888\\u1234\"]", "<.>", "[<null>]", "[1]x", "[1]]",
+        "[\"asd]", "a\u{e5}", "[True]", "1]", "{\"x\": true,", "[][]", "]", "[", "", "[\u{0}]", "2@", "{}}", "{\"\":",
+        "{\"a\":/*comment*/\"b\"}", "{\"a\": true} \"x\"", "['", "[,", "[{", "[\"a", "[\"a\"", "{", "{]", "{,", "{[",
+        "{\"a", "{'a'", "[\"\\{[\"\\{[\"\\{[\"\\{", "\u{e9}", "*", "{\"a\":\"b\"}#{}", "[\u{2028}]", "[1", "[ false, nul",
+        "[ true, fals", "[ false, tru", "{\"asd\":\"asd\"", "\u{e5}", "[\u{2060}]", "[\u{c}]",
+    ];
+
+    #[test]
+    fn conformance() {
+        let options = JsonOptions::new().with_duplicate_keys(DuplicateKeys::KeepAll);
+        for input in ACCEPTED {
+            assert!(json::parse(*input, options).is_ok(), "accepts {:?}", input);
+        }
+        for input in REJECTED {
+            assert!(json::parse(*input, options).is_err(), "rejects {:?}", input);
+        }
+    }
+
+    #[test]
+    fn values_know_their_location() {
+        let document = json::parse("{\n  \"a\": [1, \"\\u00e9\\uD83D\\uDE00\"],\n  \"b\": -1.5e3\n}", JsonOptions::default()).expect("valid JSON");
+        let a = document.target().get("a").expect("member a");
+        assert_eq!(Location::new(9, 8, 2), a.source_range().start);
+        let elements = a.target().as_array().expect("array");
+        assert_eq!(Some("\u{e9}\u{1F600}"), elements[1].target().as_str());
+        assert_eq!(Location::new(13, 12, 2), elements[1].source_range().start);
+        let b = document.target().get("b").and_then(|b| b.target().as_number()).expect("number b");
+        assert_eq!("-1.5e3", b.as_str());
+        assert_eq!(-1500.0, b.as_f64());
+        assert_eq!(None, b.as_i64());
+
+        let big = json::parse("12345678901234567890123", JsonOptions::default()).expect("valid JSON");
+        assert_eq!(Some("12345678901234567890123"), big.target().as_number().map(|number| number.as_str()));
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        let input = "{\"a\": 1, \"b\": 2, \"a\": 3}";
+        let keys = |policy| match json::parse(input, JsonOptions::new().with_duplicate_keys(policy)).map(|value| value.into_target()) {
+            Ok(JsonValue::Object(members)) => members.into_iter()
+                .map(|(key, value)| format!("{}={}", key.target(), value.target().as_number().expect("number")))
+                .collect::<Vec<_>>(),
+            other => panic!("expected an object, got {:?}", other),
+        };
+        assert_eq!(vec!["a=1", "b=2", "a=3"], keys(DuplicateKeys::KeepAll));
+        assert_eq!(vec!["a=1", "b=2"], keys(DuplicateKeys::FirstWins));
+        assert_eq!(vec!["b=2", "a=3"], keys(DuplicateKeys::LastWins));
+
+        let error = json::parse(input, JsonOptions::default()).unwrap_err();
+        assert_eq!(Location::new(17, 18, 1).locate(Location::new(20, 21, 1), ParseError::custom("duplicate key \"a\"")), error);
+    }
+
+    #[test]
+    fn errors_point_into_nested_values() {
+        let error = json::parse("[1, {\"a\": [tru]}]", JsonOptions::default()).unwrap_err();
+        assert_eq!(Location::new(11, 12, 1), error.source_range().start);
+
+        let error = json::parse("{\"a\": 1,}", JsonOptions::default()).unwrap_err();
+        assert_eq!(Location::new(8, 9, 1), error.source_range().start);
+        assert_eq!("expected `\"`", error.target().to_string());
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let options = JsonOptions::new().with_max_depth(3);
+        assert!(json::parse("[[{\"a\": 1}]]", options).is_ok());
+        let error = json::parse("[[{\"a\": []}]]", options).unwrap_err();
+        assert_eq!(Location::new(8, 9, 1).locate(Location::new(9, 10, 1), ParseError::LimitExceeded(LimitExceeded::Depth(3))), error);
+
+        let deep = format!("{}{}", "[".repeat(64), "]".repeat(64));
+        assert!(json::parse(deep.as_str(), JsonOptions::default()).is_ok());
+        let too_deep = format!("{}{}", "[".repeat(1000), "]".repeat(1000));
+        assert_eq!(&ParseError::LimitExceeded(LimitExceeded::Depth(64)),
+                   json::parse(too_deep.as_str(), JsonOptions::default()).unwrap_err().target());
+    }
+}
@@ -0,0 +1,661 @@
+//! Log lines: access logs in the Common and Combined Log Format of Apache and nginx, and syslog
+//! messages as specified by RFC 5424 and RFC 3164.
+//!
+//! ```
+//! use parsec::formats::logs::{self, LineError};
+//!
+//! let input = "127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] \"GET /apache_pb.gif HTTP/1.0\" 200 2326\n\
+//!              127.0.0.1 - - [10/Oct/2000:13:55:37 -0700] \"GET / HTTP/1.0\" 2OO 512\n";
+//! let mut lines = logs::lines(input.as_bytes(), logs::common());
+//!
+//! let record = lines.next().unwrap().unwrap();
+//! assert_eq!(Some("frank"), record.target().user());
+//! assert_eq!(Some(("GET", "/apache_pb.gif", "HTTP/1.0")), record.target().request_parts());
+//!
+//! match lines.next().unwrap() {
+//!     Err(LineError::Malformed { error, .. }) => assert_eq!("2:61: expected status code", error.to_string()),
+//!     other => panic!("unexpected {:?}", other),
+//! }
+//! assert!(lines.next().is_none());
+//! ```
+//!
+//! The parsers match a single line without its line break, `lines` reads an input line by line
+//! and reports malformed lines without stopping. RFC 3164 timestamps have neither a year nor an
+//! offset, so both are given to the parser.
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::{self, BufRead};
+
+use chrono::{DateTime, FixedOffset, TimeZone};
+
+use crate::error::ParseError;
+use crate::formats::{chars1, chars1_with, fail, FormatParser, iso8601, spanned};
+use crate::formats::strftime::DateFormat;
+use crate::parser::{from_fn, named, Parser, Succeed};
+use crate::text::location::{Located, Location};
+use crate::text::text_parser::{Chop, end, TextParser, TextState, token};
+
+/// A request as logged by a web server. `-` fields are `None`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AccessRecord {
+    host: String,
+    ident: Option<String>,
+    user: Option<String>,
+    time: DateTime<FixedOffset>,
+    request: String,
+    status: u16,
+    size: Option<u64>,
+    referer: Option<String>,
+    user_agent: Option<String>,
+}
+
+impl AccessRecord {
+    /// The address or name of the client.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// The identity reported by identd, which is almost always missing.
+    pub fn ident(&self) -> Option<&str> {
+        self.ident.as_deref()
+    }
+
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
+
+    pub fn time(&self) -> &DateTime<FixedOffset> {
+        &self.time
+    }
+
+    /// The request line as logged, which is arbitrary text for malformed requests.
+    pub fn request(&self) -> &str {
+        &self.request
+    }
+
+    /// Method, target and protocol of the request line, if it has the usual form.
+    pub fn request_parts(&self) -> Option<(&str, &str, &str)> {
+        let mut parts = self.request.split(' ');
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(method), Some(target), Some(protocol), None) => Some((method, target, protocol)),
+            _ => None,
+        }
+    }
+
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// The size of the response body in bytes.
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    /// The `Referer` header, only logged in the Combined Log Format.
+    pub fn referer(&self) -> Option<&str> {
+        self.referer.as_deref()
+    }
+
+    /// The `User-Agent` header, only logged in the Combined Log Format.
+    pub fn user_agent(&self) -> Option<&str> {
+        self.user_agent.as_deref()
+    }
+}
+
+/// A syslog message. RFC 3164 messages have no message id or structured data, their tag is the
+/// app name and the number in brackets after it the process id.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SyslogRecord {
+    priority: u8,
+    timestamp: Option<DateTime<FixedOffset>>,
+    hostname: Option<String>,
+    app_name: Option<String>,
+    proc_id: Option<String>,
+    msg_id: Option<String>,
+    structured_data: Vec<SdElement>,
+    message: String,
+}
+
+impl SyslogRecord {
+    /// 0 for kernel messages, 1 for user-level messages, up to 23 for local7.
+    pub fn facility(&self) -> u8 {
+        self.priority / 8
+    }
+
+    /// 0 for emergencies up to 7 for debug messages.
+    pub fn severity(&self) -> u8 {
+        self.priority % 8
+    }
+
+    pub fn timestamp(&self) -> Option<&DateTime<FixedOffset>> {
+        self.timestamp.as_ref()
+    }
+
+    pub fn hostname(&self) -> Option<&str> {
+        self.hostname.as_deref()
+    }
+
+    pub fn app_name(&self) -> Option<&str> {
+        self.app_name.as_deref()
+    }
+
+    pub fn proc_id(&self) -> Option<&str> {
+        self.proc_id.as_deref()
+    }
+
+    pub fn msg_id(&self) -> Option<&str> {
+        self.msg_id.as_deref()
+    }
+
+    pub fn structured_data(&self) -> &[SdElement] {
+        &self.structured_data
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+/// An element of RFC 5424 structured data like `[exampleSDID@32473 iut="3"]`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SdElement {
+    id: String,
+    params: Vec<(String, String)>,
+}
+
+impl SdElement {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The parameters in order, with their values unescaped.
+    pub fn params(&self) -> &[(String, String)] {
+        &self.params
+    }
+
+    /// The value of the first parameter named `name`.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter().find(|(param, _)| param == name).map(|(_, value)| value.as_str())
+    }
+}
+
+/// `host ident user [time] "request" status size`.
+pub fn common() -> FormatParser<AccessRecord> {
+    named("common log line", access_record(false)).boxed()
+}
+
+/// The Common Log Format followed by `"referer" "user agent"`.
+pub fn combined() -> FormatParser<AccessRecord> {
+    named("combined log line", access_record(true)).boxed()
+}
+
+/// An RFC 5424 message: `<priority>1 timestamp hostname app-name procid msgid structured-data
+/// message`, where every header field may be `-`.
+pub fn rfc5424() -> FormatParser<SyslogRecord> {
+    let nil_time = token("-").map(|_| None).or(iso8601::date_time().map(Some));
+    let header = priority().ignore(version())
+        .ignore(token(" ")).map2(nil_time, |priority, timestamp| (priority, timestamp))
+        .ignore(token(" ")).map2(header_field("hostname", 255), |(priority, timestamp), hostname| (priority, timestamp, hostname))
+        .ignore(token(" ")).map2(header_field("app name", 48), |(priority, timestamp, hostname), app_name| SyslogRecord {
+            priority,
+            timestamp,
+            hostname,
+            app_name,
+            proc_id: None,
+            msg_id: None,
+            structured_data: vec![],
+            message: String::new(),
+        })
+        .ignore(token(" ")).map2(header_field("process id", 128), |record, proc_id| SyslogRecord { proc_id, ..record })
+        .ignore(token(" ")).map2(header_field("message id", 32), |record, msg_id| SyslogRecord { msg_id, ..record });
+    let message = token(" ").map2(Chop::while_con(|_| true), |_, message| String::from(message.trim_start_matches('\u{feff}'))).optional();
+    let record = header
+        .ignore(token(" ")).map2(structured_data(), |record, structured_data| SyslogRecord { structured_data, ..record })
+        .map2(message, |record, message| SyslogRecord { message: message.unwrap_or_default(), ..record });
+    named("RFC 5424 syslog line", record).boxed()
+}
+
+/// An RFC 3164 message: `<priority>Mmm dd hh:mm:ss hostname tag[pid]: message`. The timestamp
+/// is in `year` and at `offset`. Without a tag the whole content is the message.
+pub fn rfc3164(year: i32, offset: FixedOffset) -> FormatParser<SyslogRecord> {
+    let hostname = chars1("hostname", |c| c.is_ascii_graphic());
+    let tag = chars1_with("tag", |c| c.is_ascii_alphanumeric(), |c| c.is_ascii_graphic() && c != ':' && c != '[');
+    let pid = token("[").map2(Chop::while_con(|c: char| c.is_ascii_digit()), |_, pid| pid).ignore(token("]"));
+    let tag = tag.map2(pid.optional(), |tag, pid| (tag, pid)).ignore(token(":")).ignore(token(" ").optional()).optional();
+    let record = priority()
+        .map2(bsd_time(year, offset), |priority, timestamp| (priority, timestamp))
+        .ignore(token(" "))
+        .map2(hostname, |header, hostname| (header, hostname))
+        .ignore(token(" "))
+        .map2(tag, |header, tag| (header, tag))
+        .map2(Chop::while_con(|_| true), |(((priority, timestamp), hostname), tag), message| {
+            let (app_name, proc_id) = match tag {
+                Some((tag, pid)) => (Some(tag), pid),
+                None => (None, None),
+            };
+            SyslogRecord {
+                priority,
+                timestamp: Some(timestamp),
+                hostname: Some(hostname),
+                app_name,
+                proc_id,
+                msg_id: None,
+                structured_data: vec![],
+                message,
+            }
+        });
+    named("RFC 3164 syslog line", record).boxed()
+}
+
+/// An RFC 5424 or RFC 3164 message, see `rfc3164` for `year` and `offset`.
+pub fn syslog(year: i32, offset: FixedOffset) -> FormatParser<SyslogRecord> {
+    rfc5424().or(rfc3164(year, offset)).boxed()
+}
+
+fn access_record(combined: bool) -> FormatParser<AccessRecord> {
+    let field = |name: &'static str| chars1(name, |c| !c.is_whitespace());
+    let optional_field = move |name| field(name).map(nil);
+    let status = spanned(Chop::while_con(|c: char| c.is_ascii_digit()).err_into::<Located<ParseError>>()).flat_map(|digits| {
+        match digits.target().parse::<u16>() {
+            Ok(status) if digits.target().len() == 3 => Succeed::with(status).boxed(),
+            _ => fail(digits.map(|_| ParseError::expected_rule("status code"))),
+        }
+    });
+    let size = token("-").map(|_| None)
+        .or(spanned(chars1("size", |c| c.is_ascii_digit()))
+            .flat_map(|digits| match digits.target().parse::<u64>() {
+                Ok(size) => Succeed::with(Some(size)).boxed(),
+                Err(_) => fail(digits.map(|_| ParseError::custom("size is too large"))),
+            }));
+    let common = field("host")
+        .ignore(token(" ")).map2(optional_field("ident"), |host, ident| (host, ident))
+        .ignore(token(" ")).map2(optional_field("user"), |(host, ident), user| (host, ident, user))
+        .ignore(token(" [")).map2(clf_time(), |(host, ident, user), time| (host, ident, user, time))
+        .ignore(token("] ")).map2(quoted(), |(host, ident, user, time), request| (host, ident, user, time, request))
+        .ignore(token(" ")).map2(status, |(host, ident, user, time, request), status| (host, ident, user, time, request, status))
+        .ignore(token(" ")).map2(size, |(host, ident, user, time, request, status), size| AccessRecord {
+            host,
+            ident,
+            user,
+            time,
+            request,
+            status,
+            size,
+            referer: None,
+            user_agent: None,
+        });
+    if !combined {
+        return common.boxed();
+    }
+    common.ignore(token(" ")).map2(quoted(), |record, referer| AccessRecord { referer: nil(referer), ..record })
+        .ignore(token(" ")).map2(quoted(), |record, user_agent| AccessRecord { user_agent: nil(user_agent), ..record })
+        .boxed()
+}
+
+/// `-` stands for a missing value.
+fn nil(field: String) -> Option<String> {
+    if field == "-" {
+        None
+    } else {
+        Some(field)
+    }
+}
+
+/// `10/Oct/2000:13:55:36 -0700`.
+fn clf_time() -> FormatParser<DateTime<FixedOffset>> {
+    let format = DateFormat::compile("%d/%b/%Y:%H:%M:%S %z").expect("valid format");
+    spanned(format.parser()).flat_map(|parsed| match parsed.target().to_datetime() {
+        Ok(time) => Succeed::with(time).boxed(),
+        Err(error) => fail(parsed.map(|_| ParseError::custom(error.to_string()))),
+    }).boxed()
+}
+
+/// `Oct  1 13:55:36` in `year` at `offset`.
+fn bsd_time(year: i32, offset: FixedOffset) -> FormatParser<DateTime<FixedOffset>> {
+    let format = DateFormat::compile("%b %e %H:%M:%S").expect("valid format");
+    spanned(format.parser()).flat_map(move |parsed| {
+        let mut fields = parsed.target().clone();
+        match fields.set_year(i64::from(year)).and_then(|_| fields.to_naive_datetime_with_offset(0)) {
+            Ok(time) => Succeed::with(offset.from_local_datetime(&time).single().expect("fixed offsets are unambiguous")).boxed(),
+            Err(error) => fail(parsed.map(|_| ParseError::custom(error.to_string()))),
+        }
+    }).boxed()
+}
+
+/// A string in double quotes, where `\"` and `\\` are escapes and other backslashes are kept.
+fn quoted() -> FormatParser<String> {
+    escaped('"', &['"', '\\'])
+}
+
+/// Text between `quote`s, where a backslash before one of `escapes` stands for that char.
+fn escaped(quote: char, escapes: &'static [char]) -> FormatParser<String> {
+    from_fn(move |mut state: TextState| {
+        let start = state.location().clone();
+        if state.peek() != Some(quote) {
+            return Err(state.locate_at_exactly(ParseError::expected_token(quote.to_string())));
+        }
+        state.advance();
+        let mut value = String::new();
+        loop {
+            match state.next() {
+                Some(c) if c == quote => return Ok((state, value)),
+                Some('\\') => match state.peek() {
+                    Some(c) if escapes.contains(&c) => {
+                        value.push(c);
+                        state.advance();
+                    }
+                    _ => value.push('\\'),
+                },
+                Some(c) => value.push(c),
+                None => return Err(state.locate(start, ParseError::custom(format!("unterminated `{}`", quote)))),
+            }
+        }
+    }).boxed()
+}
+
+/// `<0>` to `<191>`.
+fn priority() -> FormatParser<u8> {
+    let digits = spanned(Chop::while_con(|c: char| c.is_ascii_digit()).err_into::<Located<ParseError>>());
+    token("<").map2(digits, |_, digits| digits).ignore(token(">")).flat_map(|digits| {
+        let text = digits.target();
+        match text.parse::<u8>() {
+            Ok(priority) if priority <= 191 && (text.len() == 1 || !text.starts_with('0')) => Succeed::with(priority).boxed(),
+            _ => fail(digits.map(|_| ParseError::expected_rule("priority from 0 to 191"))),
+        }
+    }).boxed()
+}
+
+fn version() -> FormatParser<()> {
+    spanned(chars1("version", |c| c.is_ascii_digit()))
+        .flat_map(|version| {
+            if version.target() == "1" {
+                Succeed::with(()).boxed()
+            } else {
+                fail(version.map(|version| ParseError::custom(format!("syslog version {} is not supported", version))))
+            }
+        }).boxed()
+}
+
+fn is_print_us_ascii(c: char) -> bool {
+    c.is_ascii_graphic()
+}
+
+/// A header field of RFC 5424 of at most `max_len` chars, or `-`.
+fn header_field(name: &'static str, max_len: usize) -> FormatParser<Option<String>> {
+    spanned(chars1(name, is_print_us_ascii))
+        .flat_map(move |field| {
+            if field.target().len() > max_len {
+                return fail(field.map(|_| ParseError::custom(format!("{} is longer than {} characters", name, max_len))));
+            }
+            Succeed::with(nil(field.into_target())).boxed()
+        }).boxed()
+}
+
+/// `-` or elements like `[id name="value"]`.
+fn structured_data() -> FormatParser<Vec<SdElement>> {
+    let is_name_char = |c: char| is_print_us_ascii(c) && c != '=' && c != ']' && c != '"';
+    let name = move |description: &'static str| spanned(chars1(description, is_name_char))
+        .flat_map(move |name| {
+            if name.target().len() > 32 {
+                return fail(name.map(|_| ParseError::custom(format!("{} is longer than 32 characters", description))));
+            }
+            Succeed::with(name.into_target()).boxed()
+        });
+    let param = token(" ").map2(name("parameter name"), |_, name| name)
+        .ignore(token("="))
+        .map2(escaped('"', &['"', '\\', ']']), |name, value| (name, value));
+    let element = token("[").map2(name("structured data id"), |_, id| id)
+        .map2(param.many(), |id, params| SdElement { id, params })
+        .ignore(token("]"));
+    token("-").map(|_| vec![]).or(element.many1()).boxed()
+}
+
+/// Reads `reader` line by line and parses every non-blank line with `parser`, see `Lines`.
+pub fn lines<R: BufRead, T: 'static>(reader: R, parser: FormatParser<T>) -> Lines<R, T> {
+    Lines { reader, parser: parser.ignore(end()).boxed(), buffer: vec![], line: 0, offset: 0, failed: false }
+}
+
+/// An iterator over the records of the lines of a reader. Malformed lines are reported and
+/// skipped, an I/O error ends the iteration.
+///
+/// The locations of records and errors have the line number as row and the byte offset in the
+/// whole input.
+pub struct Lines<R, T> {
+    reader: R,
+    parser: FormatParser<T>,
+    buffer: Vec<u8>,
+    line: usize,
+    offset: usize,
+    failed: bool,
+}
+
+#[derive(Debug)]
+pub enum LineError {
+    Io(io::Error),
+    /// A line that didn't match the parser or isn't UTF-8.
+    Malformed { error: Located<ParseError>, line: String },
+}
+
+impl Display for LineError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LineError::Io(error) => write!(f, "{}", error),
+            LineError::Malformed { error, .. } => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for LineError {}
+
+impl<R, T> Lines<R, T> {
+    /// The number of lines read so far.
+    pub fn line_number(&self) -> usize {
+        self.line
+    }
+
+    /// Moves `location` of the current line into the whole input.
+    fn in_input(&self, location: &Location) -> Location {
+        Location::new(self.offset + location.byte_offset(), location.column(), self.line)
+    }
+
+    fn malformed(&self, error: Located<ParseError>, line: String) -> LineError {
+        let range = error.source_range();
+        let error = self.in_input(&range.start).locate(self.in_input(&range.end), error.target().clone());
+        LineError::Malformed { error, line }
+    }
+}
+
+impl<R: BufRead, T: 'static> Iterator for Lines<R, T> {
+    type Item = Result<Located<T>, LineError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.failed {
+            self.offset += self.buffer.len();
+            self.buffer.clear();
+            match self.reader.read_until(b'\n', &mut self.buffer) {
+                Ok(0) => return None,
+                Ok(_) => self.line += 1,
+                Err(error) => {
+                    self.failed = true;
+                    return Some(Err(LineError::Io(error)));
+                }
+            }
+            let mut len = self.buffer.len();
+            while len > 0 && (self.buffer[len - 1] == b'\n' || self.buffer[len - 1] == b'\r') {
+                len -= 1;
+            }
+            let text = match std::str::from_utf8(&self.buffer[..len]) {
+                Ok(text) if text.trim().is_empty() => continue,
+                Ok(text) => text.to_string(),
+                Err(error) => {
+                    let valid = error.valid_up_to();
+                    let line = String::from_utf8_lossy(&self.buffer[..len]).into_owned();
+                    let prefix = std::str::from_utf8(&self.buffer[..valid]).expect("prefix is valid UTF-8");
+                    let column = prefix.chars().count() + 1;
+                    let location = Location::new(valid, column, 1);
+                    let error = location.clone().locate(location, ParseError::custom("line is not valid UTF-8"));
+                    return Some(Err(self.malformed(error, line)));
+                }
+            };
+            // Rows and columns are only needed for errors, so lines are parsed without them and
+            // malformed lines parsed again to locate the error.
+            let untracked = TextState::new(text.as_str()).with_position_tracking(false);
+            return Some(match self.parser.pars_with(untracked) {
+                Ok(record) => {
+                    let end = Location::new(text.len(), text.chars().count() + 1, 1);
+                    Ok(self.in_input(&Location::start()).locate(self.in_input(&end), record))
+                }
+                Err(_) => {
+                    let error = self.parser.pars(text.as_str()).err().expect("parsing is deterministic");
+                    Err(self.malformed(error, text))
+                }
+            });
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{self, BufRead, BufReader, Read};
+
+    use chrono::{FixedOffset, TimeZone, Timelike};
+
+    use crate::formats::logs::{self, LineError};
+    use crate::parser::Parser;
+    use crate::text::text_parser::{end, TextParser};
+
+    #[test]
+    fn access_logs() {
+        let line = r#"203.0.113.7 - - [29/Feb/2024:23:59:59 +0100] "GET /search?q=\"rust\" HTTP/1.1" 304 - "-" "Mozilla/5.0 (X11; Linux x86_64)""#;
+        let record = logs::combined().pars(line).unwrap();
+        assert_eq!("203.0.113.7", record.host());
+        assert_eq!((None, None), (record.ident(), record.user()));
+        assert_eq!(FixedOffset::east_opt(3600).unwrap().with_ymd_and_hms(2024, 2, 29, 23, 59, 59).unwrap(), *record.time());
+        assert_eq!(Some(("GET", "/search?q=\"rust\"", "HTTP/1.1")), record.request_parts());
+        assert_eq!((304, None), (record.status(), record.size()));
+        assert_eq!(None, record.referer());
+        assert_eq!(Some("Mozilla/5.0 (X11; Linux x86_64)"), record.user_agent());
+
+        let record = logs::common().pars(r#"::1 - - [01/Jan/2021:00:00:00 +0000] "-" 400 0"#).unwrap();
+        assert_eq!(("-", None), (record.request(), record.request_parts()));
+        assert!(logs::common().ignore(end()).pars(line).is_err());
+
+        let error = logs::common().pars(r#"::1 - - [31/Apr/2021:00:00:00 +0000] "-" 400 0"#).unwrap_err();
+        assert_eq!(9, error.source_range().start.byte_offset());
+        let error = logs::common().pars(r#"::1 - - [01/Jan/2021:00:00:00 +0000] "GET / 400 0"#).unwrap_err();
+        assert_eq!("unterminated `\"`", error.target().to_string());
+    }
+
+    #[test]
+    fn rfc5424_lines() {
+        let line = "<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 [exampleSDID@32473 iut=\"3\" eventSource=\"App\\]lication\"][examplePriority@32473 class=\"high\"] \u{feff}An application event";
+        let record = logs::rfc5424().pars(line).unwrap();
+        assert_eq!((20, 5), (record.facility(), record.severity()));
+        assert_eq!(Some(&FixedOffset::east_opt(0).unwrap().with_ymd_and_hms(2003, 10, 11, 22, 14, 15).unwrap().with_nanosecond(3_000_000).unwrap()), record.timestamp());
+        assert_eq!((Some("mymachine.example.com"), Some("evntslog"), None, Some("ID47")), (record.hostname(), record.app_name(), record.proc_id(), record.msg_id()));
+        assert_eq!(2, record.structured_data().len());
+        assert_eq!(Some("App]lication"), record.structured_data()[0].param("eventSource"));
+        assert_eq!("examplePriority@32473", record.structured_data()[1].id());
+        assert_eq!("An application event", record.message());
+
+        let record = logs::rfc5424().pars("<0>1 - - - - - -").unwrap();
+        assert_eq!((None, None, ""), (record.timestamp(), record.hostname(), record.message()));
+
+        let error = logs::rfc5424().pars("<192>1 - - - - - -").unwrap_err();
+        assert_eq!("expected priority from 0 to 191", error.target().to_string());
+        assert_eq!(1, error.source_range().start.byte_offset());
+        let error = logs::rfc5424().pars("<13>2 - - - - - -").unwrap_err();
+        assert_eq!("syslog version 2 is not supported", error.target().to_string());
+        let error = logs::rfc5424().pars(format!("<13>1 - - {} - - -", "a".repeat(49))).unwrap_err();
+        assert_eq!("app name is longer than 48 characters", error.target().to_string());
+    }
+
+    #[test]
+    fn rfc3164_lines() {
+        let offset = FixedOffset::west_opt(5 * 3600).unwrap();
+        let record = logs::rfc3164(2023, offset).pars("<34>Oct  1 22:14:15 mymachine su[230]: 'su root' failed for lonvick on /dev/pts/8").unwrap();
+        assert_eq!((4, 2), (record.facility(), record.severity()));
+        assert_eq!(Some(&offset.with_ymd_and_hms(2023, 10, 1, 22, 14, 15).unwrap()), record.timestamp());
+        assert_eq!((Some("mymachine"), Some("su"), Some("230")), (record.hostname(), record.app_name(), record.proc_id()));
+        assert_eq!("'su root' failed for lonvick on /dev/pts/8", record.message());
+
+        let record = logs::rfc3164(2023, offset).pars("<13>Feb 28 00:00:00 host just a message").unwrap();
+        assert_eq!((None, "just a message"), (record.app_name(), record.message()));
+
+        let error = logs::rfc3164(2023, offset).pars("<13>Feb 29 00:00:00 host message").unwrap_err();
+        assert_eq!(4, error.source_range().start.byte_offset());
+
+        let syslog = logs::syslog(2023, offset);
+        assert_eq!(Some("evntslog"), syslog.pars("<165>1 - host evntslog - - - message").unwrap().app_name());
+        assert_eq!(Some("cron"), syslog.pars("<78>Oct 11 22:14:15 host cron: job").unwrap().app_name());
+    }
+
+    /// Fails after handing out its first bytes.
+    struct Broken<R>(R);
+
+    impl<R: Read> Read for Broken<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.read(buf)? {
+                0 => Err(io::Error::other("connection reset")),
+                read => Ok(read),
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_utf8_after_a_replacement_character() {
+        let input = b"<13>1 - b - - - - \xef\xbf\xbd \xff\n";
+        match logs::lines(&input[..], logs::rfc5424()).next() {
+            Some(Err(LineError::Malformed { error, .. })) => {
+                assert_eq!("1:21: line is not valid UTF-8", error.to_string());
+                assert_eq!(22, error.source_range().start.byte_offset());
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn streaming_lines() {
+        let input = b"<13>1 - a - - - -\r\n\n<13>1 - b - - - - \xff\xfe\n<13>2 - c - - - -\n<13>1 - d - - - - last";
+        let results: Vec<_> = logs::lines(&input[..], logs::rfc5424()).collect();
+        assert_eq!(4, results.len());
+
+        let first = results[0].as_ref().unwrap();
+        assert_eq!(Some("a"), first.target().hostname());
+        assert_eq!((0, 1), (first.source_range().start.byte_offset(), first.source_range().start.row()));
+        match &results[1] {
+            Err(LineError::Malformed { error, line }) => {
+                assert_eq!("3:19: line is not valid UTF-8", error.to_string());
+                assert_eq!(38, error.source_range().start.byte_offset());
+                assert!(line.starts_with("<13>1 - b"));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match &results[2] {
+            Err(error) => assert_eq!("4:5: syslog version 2 is not supported", error.to_string()),
+            other => panic!("unexpected {:?}", other),
+        }
+        let last = results[3].as_ref().unwrap();
+        assert_eq!("last", last.target().message());
+        assert_eq!(5, last.source_range().end.row());
+        assert_eq!(input.len(), last.source_range().end.byte_offset());
+
+        let reader = BufReader::with_capacity(4, Broken(&b"<13>1 - a - - - -\n<13>1 - b"[..]));
+        let mut lines = logs::lines(reader, logs::rfc5424());
+        assert!(lines.next().unwrap().is_ok());
+        assert!(matches!(lines.next(), Some(Err(LineError::Io(_)))));
+        assert!(lines.next().is_none());
+        assert_eq!(1, lines.line_number());
+    }
+
+    #[test]
+    fn many_lines() {
+        let line = "10.0.0.1 - alice [10/Oct/2000:13:55:36 -0700] \"GET /a HTTP/1.0\" 200 2326 \"http://example.com/\" \"curl/7.64\"\n";
+        let input = line.repeat(5_000);
+        let reader: Box<dyn BufRead> = Box::new(input.as_bytes());
+        let sizes = logs::lines(reader, logs::combined()).map(|record| record.unwrap().target().size().unwrap()).sum::<u64>();
+        assert_eq!(5_000 * 2326, sizes);
+    }
+}
@@ -0,0 +1,123 @@
+//! Parsers for common data formats, built from the combinators of this crate.
+
+use crate::adapter::BoxedParser;
+use crate::error::{Merge, ParseError};
+use crate::limits::LimitExceeded;
+use crate::parser::{from_fn, Parser};
+use crate::text::location::Located;
+use crate::text::text_parser::{Chop, TextState, token};
+
+pub mod config;
+pub mod csv;
+pub mod http;
+pub mod iso8601;
+pub mod json;
+pub mod logs;
+pub mod semver;
+pub mod sexpr;
+pub mod strftime;
+pub mod uri;
+
+/// The parsers of this module all work on text and fail with `ParseError`s.
+pub type FormatParser<T> = BoxedParser<'static, T, TextState, Located<ParseError>>;
+
+/// A single char that matches `predicate`.
+pub(crate) fn satisfy<F>(description: &'static str, predicate: F) -> FormatParser<char>
+    where F: Fn(char) -> bool + 'static
+{
+    from_fn(move |mut state: TextState| match state.peek() {
+        Some(c) if predicate(c) => {
+            state.advance();
+            Ok((state, c))
+        }
+        _ => Err(state.locate_at_exactly(ParseError::expected_rule(description))),
+    }).boxed()
+}
+
+/// One or more chars that match `predicate`.
+pub(crate) fn chars1<F>(description: &'static str, predicate: F) -> FormatParser<String>
+    where F: Fn(char) -> bool + Clone + 'static
+{
+    chars1_with(description, predicate.clone(), predicate)
+}
+
+/// A char that matches `first` followed by any number of chars that match `rest`.
+pub(crate) fn chars1_with<F, G>(description: &'static str, first: F, rest: G) -> FormatParser<String>
+    where F: Fn(char) -> bool + 'static,
+          G: Fn(char) -> bool + Clone + 'static
+{
+    satisfy(description, first).map2(Chop::while_con(rest), |first, rest| {
+        let mut chars = String::from(first);
+        chars.push_str(&rest);
+        chars
+    }).boxed()
+}
+
+/// Wraps the value of `parser` with the locations of the input it consumed.
+pub(crate) fn spanned<P>(parser: P) -> FormatParser<Located<P::Value>>
+    where P: Parser<State=TextState, Error=Located<ParseError>> + 'static
+{
+    from_fn(move |state: TextState| {
+        let start = state.location().clone();
+        parser.do_pars(state).map(|(new_state, value)| {
+            let located = new_state.locate(start, value);
+            (new_state, located)
+        })
+    }).boxed()
+}
+
+/// Fails with `error` without looking at the input.
+pub(crate) fn fail<T: 'static>(error: Located<ParseError>) -> FormatParser<T> {
+    from_fn(move |_: TextState| Err(error.clone())).boxed()
+}
+
+/// One or more `item`s with a `separator` between them. An item after a separator is required,
+/// so its error is reported instead of being dropped like by `Parser::many`.
+pub(crate) fn separated1<T: 'static, S: 'static>(item: FormatParser<T>, separator: FormatParser<S>) -> FormatParser<Vec<T>> {
+    from_fn(move |state: TextState| {
+        let (mut state, first) = item.do_pars(state)?;
+        let mut items = vec![first];
+        while let Ok((after_separator, _)) = separator.do_pars(state.clone()) {
+            let (next_state, next) = item.do_pars(after_separator)?;
+            state = next_state;
+            items.push(next);
+        }
+        Ok((state, items))
+    }).boxed()
+}
+
+/// `item`s up to and including `terminator`. If neither matches, both errors are reported.
+pub(crate) fn until<T: 'static, S: 'static>(item: FormatParser<T>, terminator: FormatParser<S>) -> FormatParser<Vec<T>> {
+    from_fn(move |mut state: TextState| {
+        let mut items = vec![];
+        loop {
+            match terminator.do_pars(state.clone()) {
+                Ok((end_state, _)) => return Ok((end_state, items)),
+                Err(terminator_error) => match item.do_pars(state) {
+                    Ok((next_state, next)) => {
+                        state = next_state;
+                        items.push(next);
+                    }
+                    Err(item_error) => return Err(terminator_error.merge(item_error)),
+                },
+            }
+        }
+    }).boxed()
+}
+
+/// `parser` if the input continues with `prefix`. Unlike `optional`, the errors of `parser`
+/// are kept once the prefix matched.
+pub(crate) fn after<T: 'static>(prefix: &'static str, parser: FormatParser<T>) -> FormatParser<Option<T>> {
+    let prefix = token(prefix);
+    from_fn(move |state: TextState| match prefix.do_pars(state.clone()) {
+        Ok((after_prefix, _)) => parser.do_pars(after_prefix).map(|(state, value)| (state, Some(value))),
+        Err(_) => Ok((state, None)),
+    }).boxed()
+}
+
+/// Fails at the opening bracket of a container that is nested too deep.
+pub(crate) fn too_deep<T: 'static>(open: &str, max_depth: usize) -> FormatParser<T> {
+    spanned(token(open))
+        .flat_map(move |bracket| fail(bracket.map(|_| ParseError::LimitExceeded(LimitExceeded::Depth(max_depth)))))
+        .boxed()
+}
@@ -0,0 +1,577 @@
+//! Semantic versions as specified by SemVer 2.0.0 and requirements on them.
+//!
+//! ```
+//! use parsec::formats::semver;
+//!
+//! let requirement = semver::parse_req(">=1.2, <2.0 || ^3.1.4").unwrap();
+//! assert!(requirement.matches(&semver::parse("1.10.0").unwrap()));
+//! assert!(!requirement.matches(&semver::parse("3.1.4-rc.1").unwrap()));
+//!
+//! let error = semver::parse("1.02.0").unwrap_err();
+//! assert_eq!("numeric identifier `02` has a leading zero", error.target().to_string());
+//! assert_eq!(3, error.source_range().start.column());
+//! ```
+//!
+//! A requirement is made of alternatives separated by `||`, each a list of comparators separated
+//! by `,` that all have to match. The comparators follow Cargo:
+//!
+//! - `=`, `>`, `>=`, `<` and `<=` compare with the version, where a missing minor or patch
+//!   number matches any, so `<=1.2` allows `1.2.9`,
+//! - `~1.2.3` allows patch updates, `~1.2` and `~1` are `=1.2` and `=1`,
+//! - `^1.2.3` allows updates that keep the leftmost non-zero number, a version without an
+//!   operator is a `^` requirement,
+//! - `*`, `1.*` and `1.2.x` are wildcards.
+//!
+//! A pre-release version only matches an alternative with a comparator on a pre-release of the
+//! same major, minor and patch version, so `>=1.0.0-rc.1` allows `1.0.0-rc.2` but not
+//! `1.1.0-rc.1`.
+
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+
+use crate::error::ParseError;
+use crate::formats::{after, chars1, fail, FormatParser, satisfy, separated1, spanned};
+use crate::parser::{named, Parser, Succeed};
+use crate::text::location::Located;
+use crate::text::text_parser::{Chop, end, TextParser, token};
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Version {
+    major: u64,
+    minor: u64,
+    patch: u64,
+    pre: Vec<Identifier>,
+    build: Vec<String>,
+}
+
+/// An identifier of a pre-release. Numeric identifiers have lower precedence than alphanumeric
+/// ones.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Identifier {
+    Numeric(u64),
+    AlphaNumeric(String),
+}
+
+impl Version {
+    pub fn new(major: u64, minor: u64, patch: u64) -> Self {
+        Version { major, minor, patch, pre: vec![], build: vec![] }
+    }
+
+    pub fn major(&self) -> u64 {
+        self.major
+    }
+
+    pub fn minor(&self) -> u64 {
+        self.minor
+    }
+
+    pub fn patch(&self) -> u64 {
+        self.patch
+    }
+
+    /// The identifiers after `-`, empty for a release.
+    pub fn pre(&self) -> &[Identifier] {
+        &self.pre
+    }
+
+    /// The identifiers after `+`, which don't affect precedence.
+    pub fn build(&self) -> &[String] {
+        &self.build
+    }
+
+    pub fn is_pre_release(&self) -> bool {
+        !self.pre.is_empty()
+    }
+
+    /// Compares by precedence as defined by section 11 of the specification, which ignores
+    /// build metadata. `Ord` uses the build metadata only to order otherwise equal versions.
+    pub fn cmp_precedence(&self, other: &Version) -> Ordering {
+        (self.major, self.minor, self.patch).cmp(&(other.major, other.minor, other.patch))
+            .then_with(|| cmp_pre(&self.pre, &other.pre))
+    }
+}
+
+/// A release has higher precedence than its pre-releases.
+fn cmp_pre(pre: &[Identifier], other: &[Identifier]) -> Ordering {
+    match (pre.is_empty(), other.is_empty()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => pre.cmp(other),
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.cmp_precedence(other).then_with(|| self.build.cmp(&other.build))
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        write_pre(f, &self.pre)?;
+        if !self.build.is_empty() {
+            write!(f, "+{}", self.build.join("."))?;
+        }
+        Ok(())
+    }
+}
+
+fn write_pre(f: &mut Formatter<'_>, pre: &[Identifier]) -> std::fmt::Result {
+    for (index, identifier) in pre.iter().enumerate() {
+        write!(f, "{}{}", if index == 0 { '-' } else { '.' }, identifier)?;
+    }
+    Ok(())
+}
+
+impl Display for Identifier {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Identifier::Numeric(number) => write!(f, "{}", number),
+            Identifier::AlphaNumeric(identifier) => write!(f, "{}", identifier),
+        }
+    }
+}
+
+/// Requirements on versions, see the module documentation.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct VersionReq {
+    /// An empty alternative is `*`.
+    alternatives: Vec<Vec<Comparator>>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Comparator {
+    op: Op,
+    major: u64,
+    minor: Option<u64>,
+    patch: Option<u64>,
+    pre: Vec<Identifier>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Op {
+    Exact,
+    Greater,
+    GreaterEq,
+    Less,
+    LessEq,
+    Tilde,
+    Caret,
+    /// `1.*` or `1.2.*`, the missing numbers are the wildcards.
+    Wildcard,
+}
+
+impl VersionReq {
+    pub fn alternatives(&self) -> &[Vec<Comparator>] {
+        &self.alternatives
+    }
+
+    pub fn matches(&self, version: &Version) -> bool {
+        self.alternatives.iter().any(|comparators| {
+            comparators.iter().all(|comparator| comparator.matches(version))
+                && (version.pre.is_empty() || comparators.iter().any(|comparator| comparator.allows_pre_release_of(version)))
+        })
+    }
+}
+
+impl Comparator {
+    pub fn op(&self) -> Op {
+        self.op
+    }
+
+    pub fn major(&self) -> u64 {
+        self.major
+    }
+
+    pub fn minor(&self) -> Option<u64> {
+        self.minor
+    }
+
+    pub fn patch(&self) -> Option<u64> {
+        self.patch
+    }
+
+    pub fn pre(&self) -> &[Identifier] {
+        &self.pre
+    }
+
+    /// Whether `version` matches, ignoring whether pre-releases are allowed at all.
+    pub fn matches(&self, version: &Version) -> bool {
+        match self.op {
+            Op::Exact | Op::Wildcard => self.matches_exact(version),
+            Op::Greater => self.matches_greater(version),
+            Op::GreaterEq => self.matches_exact(version) || self.matches_greater(version),
+            Op::Less => self.matches_less(version),
+            Op::LessEq => self.matches_exact(version) || self.matches_less(version),
+            Op::Tilde => self.matches_tilde(version),
+            Op::Caret => self.matches_caret(version),
+        }
+    }
+
+    fn allows_pre_release_of(&self, version: &Version) -> bool {
+        !self.pre.is_empty()
+            && self.major == version.major
+            && self.minor == Some(version.minor)
+            && self.patch == Some(version.patch)
+    }
+
+    fn matches_exact(&self, version: &Version) -> bool {
+        version.major == self.major
+            && self.minor.is_none_or(|minor| version.minor == minor)
+            && self.patch.is_none_or(|patch| version.patch == patch)
+            && version.pre == self.pre
+    }
+
+    fn matches_greater(&self, version: &Version) -> bool {
+        self.compare_with(version) == Some(Ordering::Greater)
+    }
+
+    fn matches_less(&self, version: &Version) -> bool {
+        self.compare_with(version) == Some(Ordering::Less)
+    }
+
+    /// How `version` compares to this comparator's version, or `None` if it is within the
+    /// numbers that are missing.
+    fn compare_with(&self, version: &Version) -> Option<Ordering> {
+        let numbers = [(version.major, Some(self.major)), (version.minor, self.minor), (version.patch, self.patch)];
+        for (number, bound) in numbers.iter() {
+            match bound {
+                Some(bound) if number != bound => return Some(number.cmp(bound)),
+                Some(_) => {}
+                None => return None,
+            }
+        }
+        Some(cmp_pre(&version.pre, &self.pre))
+    }
+
+    fn matches_tilde(&self, version: &Version) -> bool {
+        if version.major != self.major || self.minor.is_some_and(|minor| version.minor != minor) {
+            return false;
+        }
+        match self.patch {
+            Some(patch) if version.patch != patch => version.patch > patch,
+            _ => cmp_pre(&version.pre, &self.pre) != Ordering::Less,
+        }
+    }
+
+    fn matches_caret(&self, version: &Version) -> bool {
+        if version.major != self.major {
+            return false;
+        }
+        let minor = match self.minor {
+            Some(minor) => minor,
+            None => return true,
+        };
+        let patch = match self.patch {
+            Some(patch) => patch,
+            None if self.major > 0 => return version.minor >= minor,
+            None => return version.minor == minor,
+        };
+        if self.major > 0 {
+            if version.minor != minor {
+                return version.minor > minor;
+            }
+        } else if version.minor != minor || (minor == 0 && version.patch != patch) {
+            return false;
+        }
+        if version.patch != patch {
+            return version.patch > patch;
+        }
+        cmp_pre(&version.pre, &self.pre) != Ordering::Less
+    }
+}
+
+impl Display for VersionReq {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (index, comparators) in self.alternatives.iter().enumerate() {
+            if index > 0 {
+                write!(f, " || ")?;
+            }
+            if comparators.is_empty() {
+                write!(f, "*")?;
+            }
+            for (index, comparator) in comparators.iter().enumerate() {
+                if index > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", comparator)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for Comparator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let op = match self.op {
+            Op::Exact => "=",
+            Op::Greater => ">",
+            Op::GreaterEq => ">=",
+            Op::Less => "<",
+            Op::LessEq => "<=",
+            Op::Tilde => "~",
+            Op::Caret => "^",
+            Op::Wildcard => "",
+        };
+        write!(f, "{}{}", op, self.major)?;
+        for number in [self.minor, self.patch].iter() {
+            match number {
+                Some(number) => write!(f, ".{}", number)?,
+                None if self.op == Op::Wildcard => return write!(f, ".*"),
+                None => return Ok(()),
+            }
+        }
+        write_pre(f, &self.pre)
+    }
+}
+
+pub fn parse(input: impl Into<String>) -> Result<Version, Located<ParseError>> {
+    version().ignore(end()).pars(input)
+}
+
+pub fn parse_req(input: impl Into<String>) -> Result<VersionReq, Located<ParseError>> {
+    version_req().ignore(spaces()).ignore(end()).pars(input)
+}
+
+/// `major.minor.patch`, optionally followed by `-` and pre-release identifiers and `+` and build
+/// metadata.
+pub fn version() -> FormatParser<Version> {
+    let core = number().ignore(token(".")).map2(number(), |major, minor| (major, minor))
+        .ignore(token(".")).map2(number(), |(major, minor), patch| (major, minor, patch));
+    named("version", core.map2(pre_and_build(), |(major, minor, patch), (pre, build)| {
+        Version { major, minor, patch, pre, build }
+    })).boxed()
+}
+
+/// Comparators separated by `,` in alternatives separated by `||`.
+pub fn version_req() -> FormatParser<VersionReq> {
+    let alternative = separated1(comparator(), spaces().ignore(token(",")).boxed())
+        .map(|comparators| comparators.into_iter().flatten().collect());
+    named("version requirement", separated1(alternative.boxed(), spaces().ignore(token("||")).boxed())
+        .map(|alternatives| VersionReq { alternatives })).boxed()
+}
+
+fn spaces() -> FormatParser<()> {
+    Chop::while_con(char::is_whitespace).err_into::<Located<ParseError>>().map(|_| ()).boxed()
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-'
+}
+
+/// A non-empty run of chars matching `predicate` with its location.
+fn raw(description: &'static str, predicate: fn(char) -> bool) -> FormatParser<Located<String>> {
+    spanned(chars1(description, predicate))
+}
+
+/// Checks the digits of a numeric identifier, which must not have leading zeros.
+fn numeric(digits: &Located<String>) -> Result<u64, Located<ParseError>> {
+    let invalid = |message: String| {
+        let range = digits.source_range();
+        Err(range.start.clone().locate(range.end.clone(), ParseError::custom(message)))
+    };
+    if digits.target().len() > 1 && digits.target().starts_with('0') {
+        return invalid(format!("numeric identifier `{}` has a leading zero", digits.target()));
+    }
+    digits.target().parse().or_else(|_| invalid(format!("numeric identifier `{}` is too large", digits.target())))
+}
+
+fn lift<T: Clone + 'static>(result: Result<T, Located<ParseError>>) -> FormatParser<T> {
+    match result {
+        Ok(value) => Succeed::with(value).boxed(),
+        Err(error) => fail(error),
+    }
+}
+
+fn number() -> FormatParser<u64> {
+    raw("digit", |c| c.is_ascii_digit()).flat_map(|digits| lift(numeric(&digits))).boxed()
+}
+
+fn pre_and_build() -> FormatParser<(Vec<Identifier>, Vec<String>)> {
+    let pre_identifier = raw("pre-release identifier", is_identifier_char).flat_map(|identifier| {
+        if identifier.target().chars().all(|c| c.is_ascii_digit()) {
+            lift(numeric(&identifier).map(Identifier::Numeric))
+        } else {
+            Succeed::with(Identifier::AlphaNumeric(identifier.into_target())).boxed()
+        }
+    });
+    let build_identifier = raw("build identifier", is_identifier_char).map(Located::into_target);
+    let pre = after("-", separated1(pre_identifier.boxed(), token(".").boxed()));
+    let build = after("+", separated1(build_identifier.boxed(), token(".").boxed()));
+    pre.map2(build, |pre, build| (pre.unwrap_or_default(), build.unwrap_or_default())).boxed()
+}
+
+/// A version number or a wildcard.
+#[derive(Debug, Clone)]
+enum Part {
+    Number(u64),
+    Wildcard,
+}
+
+fn part() -> FormatParser<Located<Part>> {
+    let wildcard = spanned(satisfy("wildcard", |c| c == '*' || c == 'x' || c == 'X').map(|_| Part::Wildcard));
+    let number = raw("digit", |c| c.is_ascii_digit())
+        .flat_map(|digits| lift(numeric(&digits).map(|number| digits.map(|_| Part::Number(number)))));
+    number.or(wildcard).boxed()
+}
+
+/// One comparator, or none for `*`.
+fn comparator() -> FormatParser<Option<Comparator>> {
+    let op = token(">=").map(|_| Op::GreaterEq)
+        .or(token("<=").map(|_| Op::LessEq))
+        .or(token(">").map(|_| Op::Greater))
+        .or(token("<").map(|_| Op::Less))
+        .or(token("=").map(|_| Op::Exact))
+        .or(token("~").map(|_| Op::Tilde))
+        .or(token("^").map(|_| Op::Caret))
+        .optional();
+    let parts = part()
+        .map2(after(".", part()), |major, minor| (major, minor))
+        .map2(after(".", part()), |(major, minor), patch| (major, minor, patch));
+    let version = spanned(parts.map2(pre_and_build(), |parts, (pre, _)| (parts, pre)));
+    spaces().map2(spanned(op), |_, op| op).ignore(spaces()).map2(version, |op, version| (op, version))
+        .flat_map(|(op, version)| lift(build_comparator(op, version)))
+        .boxed()
+}
+
+type Parts = (Located<Part>, Option<Located<Part>>, Option<Located<Part>>);
+
+fn build_comparator(op: Located<Option<Op>>, version: Located<(Parts, Vec<Identifier>)>) -> Result<Option<Comparator>, Located<ParseError>> {
+    let error = |at: &Located<Part>, message: &str| {
+        let range = at.source_range();
+        Err(range.start.clone().locate(range.end.clone(), ParseError::custom(message)))
+    };
+    let ((major, minor, patch), pre) = version.target().clone();
+    let mut numbers = vec![];
+    let mut wildcard = None;
+    for part in [Some(&major), minor.as_ref(), patch.as_ref()].iter().flatten() {
+        match (part.target(), &wildcard) {
+            (Part::Number(_), Some(_)) => return error(part, "a version number cannot follow a wildcard"),
+            (Part::Number(number), None) => numbers.push(*number),
+            (Part::Wildcard, _) => wildcard = Some(*part),
+        }
+    }
+    if !pre.is_empty() && numbers.len() < 3 {
+        let range = version.source_range();
+        return Err(range.start.clone().locate(range.end.clone(), ParseError::custom("a pre-release requires a major, minor and patch version")));
+    }
+    let op = match (*op.target(), wildcard) {
+        (Some(_), Some(wildcard)) if numbers.is_empty() => return error(wildcard, "`*` cannot have an operator"),
+        (None, Some(_)) if numbers.is_empty() => return Ok(None),
+        (None, Some(_)) => Op::Wildcard,
+        (Some(op), _) => op,
+        (None, None) => Op::Caret,
+    };
+    Ok(Some(Comparator { op, major: numbers[0], minor: numbers.get(1).copied(), patch: numbers.get(2).copied(), pre }))
+}
+
+#[cfg(test)]
+mod test {
+    use std::cmp::Ordering;
+
+    use crate::formats::semver::{self, Identifier, Op, Version};
+
+    fn version(input: &str) -> Version {
+        semver::parse(input).unwrap()
+    }
+
+    #[test]
+    fn versions() {
+        let parsed = version("1.20.3-rc.1.x-y+build.007");
+        assert_eq!((1, 20, 3), (parsed.major(), parsed.minor(), parsed.patch()));
+        let pre = vec![Identifier::AlphaNumeric(String::from("rc")), Identifier::Numeric(1), Identifier::AlphaNumeric(String::from("x-y"))];
+        assert_eq!(pre, parsed.pre());
+        assert_eq!(vec![String::from("build"), String::from("007")], parsed.build());
+        assert_eq!("1.20.3-rc.1.x-y+build.007", parsed.to_string());
+        assert_eq!(Version::new(0, 0, 0), version("0.0.0"));
+
+        let error = semver::parse("1.2.3-alpha.01").unwrap_err();
+        assert_eq!("numeric identifier `01` has a leading zero", error.target().to_string());
+        assert_eq!(12..14, error.source_range().start.byte_offset()..error.source_range().end.byte_offset());
+
+        let error = semver::parse("1.2.99999999999999999999").unwrap_err();
+        assert_eq!("numeric identifier `99999999999999999999` is too large", error.target().to_string());
+        assert_eq!(4, error.source_range().start.byte_offset());
+
+        assert!(semver::parse("1.2").is_err());
+        assert!(semver::parse("1.2.3-").is_err());
+        assert!(semver::parse("1.2.3-a..b").is_err());
+        assert!(semver::parse("1.2.3+").is_err());
+        assert!(semver::parse("v1.2.3").is_err());
+    }
+
+    #[test]
+    fn precedence() {
+        let ordered = ["1.0.0-alpha", "1.0.0-alpha.1", "1.0.0-alpha.beta", "1.0.0-beta", "1.0.0-beta.2", "1.0.0-beta.11", "1.0.0-rc.1", "1.0.0", "1.0.1", "1.1.0", "2.0.0"];
+        for pair in ordered.windows(2) {
+            assert!(version(pair[0]) < version(pair[1]), "{} < {}", pair[0], pair[1]);
+        }
+        assert_eq!(Ordering::Equal, version("1.0.0+a").cmp_precedence(&version("1.0.0+b")));
+        assert_ne!(version("1.0.0+a"), version("1.0.0+b"));
+    }
+
+    #[test]
+    fn requirements() {
+        let cases = [
+            ("^1.2.3", &["1.2.3", "1.9.0"][..], &["1.2.2", "2.0.0", "1.3.0-rc.1"][..]),
+            ("^0.2.3", &["0.2.3", "0.2.9"], &["0.3.0", "0.2.2"]),
+            ("^0.0.3", &["0.0.3"], &["0.0.4"]),
+            ("1.2", &["1.2.0", "1.7.1"], &["2.0.0", "1.1.9"]),
+            ("~1.2.3", &["1.2.3", "1.2.8"], &["1.3.0", "1.2.2"]),
+            ("~1", &["1.0.0", "1.9.9"], &["2.0.0"]),
+            ("=1.2", &["1.2.0", "1.2.9"], &["1.3.0"]),
+            (">=1.0, <2.0", &["1.0.0", "1.99.0"], &["2.0.0", "0.9.0", "2.0.0-alpha"]),
+            ("<=1.2", &["1.2.9", "0.1.0"], &["1.3.0"]),
+            (">1.2", &["1.3.0"], &["1.2.9"]),
+            ("1.*", &["1.0.0", "1.4.2"], &["2.0.0"]),
+            ("1.2.x", &["1.2.7"], &["1.3.0"]),
+            ("*", &["0.0.1", "42.0.0"], &["1.0.0-rc.1"]),
+            ("<1.0 || >=3.1.4-rc.1", &["0.5.0", "3.1.4-rc.2", "3.1.4", "4.0.0"], &["2.0.0", "3.1.5-rc.1"]),
+            (">=1.0.0-rc.1", &["1.0.0-rc.1", "1.0.0-rc.2", "1.0.0", "1.1.0"], &["1.0.0-beta", "1.1.0-rc.1"]),
+        ];
+        for (requirement, matching, others) in cases.iter() {
+            let parsed = semver::parse_req(*requirement).unwrap();
+            for version in matching.iter() {
+                assert!(parsed.matches(&semver::parse(*version).unwrap()), "{} matches {}", requirement, version);
+            }
+            for version in others.iter() {
+                assert!(!parsed.matches(&semver::parse(*version).unwrap()), "{} doesn't match {}", requirement, version);
+            }
+        }
+
+        let parsed = semver::parse_req(" >= 1.0 ,<2 || 3.x || *").unwrap();
+        assert_eq!(3, parsed.alternatives().len());
+        assert_eq!(Op::GreaterEq, parsed.alternatives()[0][0].op());
+        assert_eq!(None, parsed.alternatives()[0][1].minor());
+        assert_eq!(">=1.0, <2 || 3.* || *", parsed.to_string());
+    }
+
+    #[test]
+    fn requirement_errors() {
+        let error = semver::parse_req(">=1.0, <2.00").unwrap_err();
+        assert_eq!("numeric identifier `00` has a leading zero", error.target().to_string());
+        assert_eq!(10, error.source_range().start.byte_offset());
+
+        let error = semver::parse_req("1.*.3").unwrap_err();
+        assert_eq!("a version number cannot follow a wildcard", error.target().to_string());
+        assert_eq!(4, error.source_range().start.byte_offset());
+
+        let error = semver::parse_req("^1.2-rc.1").unwrap_err();
+        assert_eq!("a pre-release requires a major, minor and patch version", error.target().to_string());
+
+        let error = semver::parse_req(">=*").unwrap_err();
+        assert_eq!("`*` cannot have an operator", error.target().to_string());
+
+        assert!(semver::parse_req("").is_err());
+        assert!(semver::parse_req(">=1.0,").is_err());
+        assert!(semver::parse_req("1.0 ||").is_err());
+    }
+}
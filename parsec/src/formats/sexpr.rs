@@ -0,0 +1,462 @@
+//! S-expressions as read by Lisp-like languages.
+//!
+//! ```
+//! use parsec::formats::sexpr::{self, Datum};
+//!
+//! let data = sexpr::parse("(define (square x) (* x x)) ; squares").unwrap();
+//! let define = data[0].target().as_list().unwrap();
+//! assert_eq!(Some("define"), define[0].target().as_symbol());
+//! assert_eq!(19, define[1].source_range().end.column());
+//! ```
+//!
+//! Lists are read with an explicit stack instead of recursion, and data are dropped the same
+//! way, so the nesting of the input is only limited by memory. Comparing, cloning and debug
+//! printing data still recurse.
+
+use std::collections::HashSet;
+use std::mem;
+
+use crate::error::ParseError;
+use crate::formats::{chars1, fail, FormatParser, satisfy, spanned, until};
+use crate::parser::{from_fn, named, Parser, Succeed};
+use crate::text::location::{Located, Location};
+use crate::text::text_parser::{end, TextParser, TextState, token};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Datum {
+    Symbol(String),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    List(Vec<Located<Datum>>),
+    /// `(a b . c)`, the items before the dot and the tail after it.
+    DottedList(Vec<Located<Datum>>, Box<Located<Datum>>),
+    /// A datum after one of the quote shorthands.
+    Quoted(Quote, Box<Located<Datum>>),
+    /// `#n=datum`
+    Labeled(u32, Box<Located<Datum>>),
+    /// `#n#`, the datum with label `n` in the same top-level datum.
+    Reference(u32),
+}
+
+impl Datum {
+    pub fn as_symbol(&self) -> Option<&str> {
+        match self {
+            Datum::Symbol(symbol) => Some(symbol),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Datum::Integer(integer) => Some(*integer),
+            _ => None,
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Datum::Float(float) => Some(*float),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Datum::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// The items of a proper list.
+    pub fn as_list(&self) -> Option<&[Located<Datum>]> {
+        match self {
+            Datum::List(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    fn take_children(&mut self, into: &mut Vec<Datum>) {
+        let mut take = |datum: &mut Located<Datum>| into.push(mem::replace(datum.target_mut(), Datum::Integer(0)));
+        match self {
+            Datum::List(items) => items.iter_mut().for_each(&mut take),
+            Datum::DottedList(items, tail) => {
+                items.iter_mut().for_each(&mut take);
+                take(tail);
+            }
+            Datum::Quoted(_, datum) | Datum::Labeled(_, datum) => take(datum),
+            _ => {}
+        }
+    }
+}
+
+/// Drops nested data one after another instead of recursively.
+impl Drop for Datum {
+    fn drop(&mut self) {
+        let mut pending = vec![];
+        self.take_children(&mut pending);
+        while let Some(mut datum) = pending.pop() {
+            datum.take_children(&mut pending);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Quote {
+    /// `'datum`
+    Quote,
+    /// `` `datum ``
+    Quasiquote,
+    /// `,datum`
+    Unquote,
+    /// `,@datum`
+    UnquoteSplicing,
+}
+
+impl Quote {
+    /// The symbol the shorthand stands for.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Quote::Quote => "quote",
+            Quote::Quasiquote => "quasiquote",
+            Quote::Unquote => "unquote",
+            Quote::UnquoteSplicing => "unquote-splicing",
+        }
+    }
+}
+
+/// Reads all data of `input`.
+pub fn parse(input: impl Into<String>) -> Result<Vec<Located<Datum>>, Located<ParseError>> {
+    data().pars(input)
+}
+
+/// Data separated by whitespace and comments, up to the end of the input.
+pub fn data() -> FormatParser<Vec<Located<Datum>>> {
+    until(datum(), atmosphere().ignore(end()).boxed())
+}
+
+/// One datum with the whitespace and comments before it.
+pub fn datum() -> FormatParser<Located<Datum>> {
+    let atmosphere = atmosphere();
+    let lexeme = lexeme();
+    named("datum", from_fn(move |state| read(&atmosphere, &lexeme, state))).boxed()
+}
+
+/// Whitespace, `;` line comments and nested `#| |#` block comments.
+fn atmosphere() -> FormatParser<()> {
+    from_fn(|mut state: TextState| {
+        loop {
+            let mut after = state.clone();
+            match state.peek() {
+                Some(c) if c.is_whitespace() => state.advance(),
+                Some(';') => {
+                    while !matches!(state.peek(), None | Some('\n')) {
+                        state.advance();
+                    }
+                }
+                Some('#') if { after.advance(); after.peek() == Some('|') } => {
+                    state = block_comment(state)?;
+                }
+                _ => return Ok((state, ())),
+            }
+        }
+    }).boxed()
+}
+
+fn block_comment(mut state: TextState) -> Result<TextState, Located<ParseError>> {
+    let start = state.location().clone();
+    let mut depth = 0;
+    let mut previous = None;
+    loop {
+        let current = state.peek();
+        match (previous, current) {
+            (_, None) => return Err(state.locate(start, ParseError::custom("unclosed block comment"))),
+            (Some('#'), Some('|')) => {
+                depth += 1;
+                previous = None;
+            }
+            (Some('|'), Some('#')) => {
+                depth -= 1;
+                previous = None;
+                if depth == 0 {
+                    state.advance();
+                    return Ok(state);
+                }
+            }
+            _ => previous = current,
+        }
+        state.advance();
+    }
+}
+
+/// What the reader sees of the input.
+#[derive(Debug, Clone)]
+enum Lexeme {
+    Open,
+    Close,
+    Dot,
+    Prefix(Quote),
+    Label(u32),
+    Datum(Datum),
+}
+
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || "()\";'`,".contains(c)
+}
+
+fn lexeme() -> FormatParser<Located<Lexeme>> {
+    let prefix = token(",@").map(|_| Quote::UnquoteSplicing)
+        .or(token(",").map(|_| Quote::Unquote))
+        .or(token("'").map(|_| Quote::Quote))
+        .or(token("`").map(|_| Quote::Quasiquote))
+        .map(Lexeme::Prefix);
+    let label = spanned(chars1("digit", |c| c.is_ascii_digit()))
+        .flat_map(|digits| match digits.target().parse::<u32>() {
+            Ok(label) => Succeed::with(label).boxed(),
+            Err(_) => fail(digits.map(|_| ParseError::custom("datum label is too large"))),
+        });
+    let label = token("#").map2(label, |_, label| label)
+        .map2(token("=").map(|_| true).or(token("#").map(|_| false)), |label, defines| {
+            if defines { Lexeme::Label(label) } else { Lexeme::Datum(Datum::Reference(label)) }
+        });
+    let lexeme = token("(").map(|_| Lexeme::Open)
+        .or(token(")").map(|_| Lexeme::Close))
+        .or(prefix)
+        .or(string().map(|string| Lexeme::Datum(Datum::String(string))))
+        .or(atom());
+    // `#` and a digit always start a label, so its errors are reported instead of reading the
+    // label as a symbol.
+    let lexeme = from_fn(move |state: TextState| {
+        let mut ahead = state.clone();
+        if ahead.next() == Some('#') && ahead.next().is_some_and(|c| c.is_ascii_digit()) {
+            label.do_pars(state)
+        } else {
+            lexeme.do_pars(state)
+        }
+    });
+    spanned(lexeme)
+}
+
+fn string() -> FormatParser<String> {
+    let escape = token("\\").map2(satisfy("escape character", |c| "\\\"ntr".contains(c)), |_, c| match c {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        c => c,
+    });
+    let character = satisfy("character", |c| c != '"' && c != '\\').or(escape);
+    token("\"").map2(until(character.boxed(), token("\"").boxed()), |_, chars| chars.into_iter().collect::<String>()).boxed()
+}
+
+/// Symbols, numbers and the dot of dotted lists.
+fn atom() -> FormatParser<Lexeme> {
+    let text = chars1("atom", |c| !is_delimiter(c));
+    spanned(text).flat_map(|text| {
+        let atom = text.target().as_str();
+        let unsigned = atom.strip_prefix(|c| c == '+' || c == '-').unwrap_or(atom);
+        let is_integer = !unsigned.is_empty() && unsigned.chars().all(|c| c.is_ascii_digit());
+        let is_numeric = atom.chars().any(|c| c.is_ascii_digit()) && atom.chars().all(|c| "0123456789+-.eE".contains(c));
+        let lexeme = match atom {
+            "." => Lexeme::Dot,
+            _ if is_integer => match atom.parse() {
+                Ok(integer) => Lexeme::Datum(Datum::Integer(integer)),
+                Err(_) => return fail(text.map(|_| ParseError::custom("integer does not fit in 64 bits"))),
+            },
+            _ if is_numeric => match atom.parse() {
+                Ok(float) => Lexeme::Datum(Datum::Float(float)),
+                Err(_) => Lexeme::Datum(Datum::Symbol(String::from(atom))),
+            },
+            _ => Lexeme::Datum(Datum::Symbol(String::from(atom))),
+        };
+        Succeed::with(lexeme).boxed()
+    }).boxed()
+}
+
+/// A list or prefix that is not complete yet.
+enum Frame {
+    List { start: Location, items: Vec<Located<Datum>>, tail: Option<Located<Datum>>, dotted: bool },
+    Prefix { start: Location, quote: Quote },
+    Label { start: Location, label: u32 },
+}
+
+fn custom<T>(located: &Located<T>, message: String) -> Located<ParseError> {
+    let range = located.source_range();
+    range.start.clone().locate(range.end.clone(), ParseError::custom(message))
+}
+
+fn read(atmosphere: &FormatParser<()>, lexeme: &FormatParser<Located<Lexeme>>, state: TextState)
+        -> Result<(TextState, Located<Datum>), Located<ParseError>> {
+    let mut frames = vec![];
+    let mut labels = HashSet::new();
+    let mut state = state;
+    loop {
+        state = atmosphere.do_pars(state)?.0;
+        if state.peek().is_none() {
+            return Err(state.locate_at_exactly(match frames.last() {
+                Some(Frame::List { .. }) => ParseError::expected_token(")"),
+                _ => ParseError::expected_rule("datum"),
+            }));
+        }
+        let (next, lexeme) = lexeme.do_pars(state)?;
+        state = next;
+        let range = lexeme.source_range().clone();
+        let datum = match lexeme.target() {
+            Lexeme::Open => {
+                frames.push(Frame::List { start: range.start, items: vec![], tail: None, dotted: false });
+                continue;
+            }
+            Lexeme::Prefix(quote) => {
+                frames.push(Frame::Prefix { start: range.start, quote: *quote });
+                continue;
+            }
+            Lexeme::Label(label) => {
+                if !labels.insert(*label) {
+                    return Err(custom(&lexeme, format!("datum label #{}= is defined twice", label)));
+                }
+                frames.push(Frame::Label { start: range.start, label: *label });
+                continue;
+            }
+            Lexeme::Dot => match frames.last_mut() {
+                Some(Frame::List { items, dotted, .. }) if !items.is_empty() && !*dotted => {
+                    *dotted = true;
+                    continue;
+                }
+                _ => return Err(custom(&lexeme, String::from("unexpected `.`"))),
+            },
+            Lexeme::Close => match frames.pop() {
+                Some(Frame::List { start, items, tail, dotted }) => match tail {
+                    Some(tail) => start.locate(range.end, Datum::DottedList(items, Box::new(tail))),
+                    None if dotted => return Err(custom(&lexeme, String::from("expected datum after `.`"))),
+                    None => start.locate(range.end, Datum::List(items)),
+                },
+                _ => return Err(custom(&lexeme, String::from("unexpected `)`"))),
+            },
+            Lexeme::Datum(Datum::Reference(label)) if !labels.contains(label) =>
+                return Err(custom(&lexeme, format!("datum label #{}# is not defined", label))),
+            Lexeme::Datum(_) => lexeme.map(|lexeme| match lexeme {
+                Lexeme::Datum(datum) => datum,
+                _ => unreachable!("matched a datum"),
+            }),
+        };
+        let mut datum = datum;
+        loop {
+            match frames.last_mut() {
+                None => return Ok((state, datum)),
+                Some(Frame::List { tail: Some(_), .. }) => return Err(custom(&datum, String::from("expected `)`"))),
+                Some(Frame::List { items, tail, dotted, .. }) => {
+                    if *dotted {
+                        *tail = Some(datum);
+                    } else {
+                        items.push(datum);
+                    }
+                    break;
+                }
+                Some(_) => {
+                    let end = datum.source_range().end.clone();
+                    datum = match frames.pop() {
+                        Some(Frame::Prefix { start, quote }) => start.locate(end, Datum::Quoted(quote, Box::new(datum))),
+                        Some(Frame::Label { start, label }) => start.locate(end, Datum::Labeled(label, Box::new(datum))),
+                        _ => unreachable!("the last frame is a prefix or label"),
+                    };
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use crate::formats::sexpr::{self, Datum, Quote};
+    use crate::text::location::Location;
+
+    fn show(datum: &Datum) -> String {
+        let items = |items: &[crate::text::location::Located<Datum>]| items.iter().map(|item| show(item.target())).collect::<Vec<_>>().join(" ");
+        match datum {
+            Datum::Symbol(symbol) => symbol.clone(),
+            Datum::Integer(integer) => integer.to_string(),
+            Datum::Float(float) => format!("{:?}", float),
+            Datum::String(string) => format!("{:?}", string),
+            Datum::List(list) => format!("({})", items(list)),
+            Datum::DottedList(list, tail) => format!("({} . {})", items(list), show(tail.target())),
+            Datum::Quoted(quote, datum) => format!("({} {})", quote.symbol(), show(datum.target())),
+            Datum::Labeled(label, datum) => format!("#{}={}", label, show(datum.target())),
+            Datum::Reference(label) => format!("#{}#", label),
+        }
+    }
+
+    fn read(input: &str) -> Vec<String> {
+        sexpr::parse(input).expect("valid data").iter().map(|datum| show(datum.target())).collect()
+    }
+
+    #[test]
+    fn reads_atoms_lists_and_shorthands() {
+        assert_eq!(vec!["foo", "-12", "1.5", "-", "...", "1+", "\"a\\nb\""], read("foo -12 1.5 - ... 1+ \"a\\nb\""));
+        assert_eq!(vec!["(a (b c) ())", "(a b . c)", "((1 . 2) . 3)"], read("(a (b c) ()) (a b . c) ((1 . 2) . 3)"));
+        assert_eq!(vec!["(quote x)", "(quasiquote (a (unquote b) (unquote-splicing c)))", "(quote (quote ()))"], read("'x `(a ,b ,@c) ''()"));
+        assert_eq!(vec!["#0=(a . #0#)", "(#1=x #1#)"], read("#0=(a . #0#) (#1=x #1#)"));
+        assert_eq!(vec!["(a b)"], read("; line\n(a #| block #| nested |# |# b) ; end"));
+        assert_eq!(Vec::<String>::new(), read("  ; nothing\n"));
+    }
+
+    #[test]
+    fn data_know_their_location() {
+        let data = sexpr::parse("(a\n  'b . \"c\")").expect("valid data");
+        let (items, tail) = match data[0].target() {
+            Datum::DottedList(items, tail) => (items, tail),
+            other => panic!("expected a dotted list, got {:?}", other),
+        };
+        assert_eq!(Location::new(0, 1, 1), data[0].source_range().start);
+        assert_eq!(Location::new(14, 12, 2), data[0].source_range().end);
+        assert_eq!(Location::new(5, 3, 2), items[1].source_range().start);
+        match items[1].target() {
+            Datum::Quoted(Quote::Quote, quoted) => assert_eq!(Location::new(6, 4, 2), quoted.source_range().start),
+            other => panic!("expected a quote, got {:?}", other),
+        }
+        assert_eq!(Location::new(10, 8, 2), tail.source_range().start);
+    }
+
+    #[test]
+    fn reports_malformed_data() {
+        let error = |input: &str| sexpr::parse(input).map(|_| ()).map_err(|error| (error.target().to_string(), error.source_range().start.column()));
+        assert_eq!(Err((String::from("expected `)`"), 7)), error("(a (b)"));
+        assert_eq!(Err((String::from("unexpected `)`"), 3)), error("a )"));
+        assert_eq!(Err((String::from("unexpected `.`"), 2)), error("(. a)"));
+        assert_eq!(Err((String::from("expected datum after `.`"), 5)), error("(a .)"));
+        assert_eq!(Err((String::from("expected `)`"), 8)), error("(a . b c)"));
+        assert_eq!(Err((String::from("datum label #2# is not defined"), 7)), error("(#1=a #2#)"));
+        assert_eq!(Err((String::from("datum label #1= is defined twice"), 7)), error("(#1=a #1=b)"));
+        assert_eq!(Err((String::from("datum label is too large"), 2)), error("#99999999999=x"));
+        assert_eq!(Err((String::from("expected `#` or `=`"), 4)), error("(#1 a)"));
+        assert_eq!(Err((String::from("unclosed block comment"), 3)), error("a #| b"));
+        assert_eq!(Err((String::from("integer does not fit in 64 bits"), 1)), error("99999999999999999999"));
+        assert_eq!(Err((String::from("expected datum"), 2)), error("'"));
+    }
+
+    #[test]
+    fn deep_nesting_on_small_stack() {
+        let depth = 100_000;
+        let input = format!("{}x{}", "('".repeat(depth), ")".repeat(depth));
+        let depth_read = thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(move || {
+                // The data are dropped at the end of the thread, on the small stack as well.
+                let data = sexpr::parse(input).expect("valid data");
+                let mut datum = data[0].target();
+                let mut depth = 0;
+                while let Some(items) = datum.as_list() {
+                    depth += 1;
+                    datum = match items[0].target() {
+                        Datum::Quoted(_, quoted) => quoted.target(),
+                        other => other,
+                    };
+                }
+                depth
+            })
+            .expect("thread spawned")
+            .join()
+            .expect("no stack overflow");
+        assert_eq!(depth, depth_read);
+    }
+}
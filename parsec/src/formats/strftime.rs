@@ -0,0 +1,672 @@
+//! `strftime`-style format strings compiled into parsers and formatters.
+//!
+//! ```
+//! use chrono::NaiveDate;
+//! use parsec::formats::strftime::DateFormat;
+//!
+//! let format = DateFormat::compile("%d.%m.%Y").unwrap();
+//! assert_eq!(Ok(NaiveDate::from_ymd_opt(1996, 8, 14).unwrap()), format.parse_date("14.08.1996"));
+//!
+//! let error = format.parse_date("14.13.1996").unwrap_err();
+//! assert_eq!("expected `%m` (month 01-12)", error.target().to_string());
+//! assert_eq!(4, error.source_range().start.column());
+//! ```
+//!
+//! The specifiers are the common ones of chrono: `%Y %y %G %V %m %b %h %B %d %e %j %a %A %u %w
+//! %H %k %I %l %M %S %f %.f %p %P %z %:z %s`, the shorthands `%D %F %T %R` and `%% %n %t`.
+//! The padding of numbers can be changed with `-` (none), `_` (spaces) or `0` (zeros) after the
+//! `%`, which only affects formatting. Whitespace in the format matches any amount of whitespace.
+//! `%[` and `%]` enclose an optional section, which is skipped if it doesn't match and only
+//! formatted if all its fields are available.
+
+use chrono::format::Parsed;
+use chrono::{DateTime, Datelike, FixedOffset, Locale, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Timelike, Utc, Weekday};
+
+use crate::error::ParseError;
+use crate::formats::{chars1, fail, FormatParser, satisfy, spanned, until};
+use crate::parser::{from_fn, lazy, Parser, Succeed};
+use crate::text::location::Located;
+use crate::text::text_parser::{Chop, end, TextParser, TextState, token};
+
+const WEEKDAYS: [Weekday; 7] = [Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri, Weekday::Sat, Weekday::Sun];
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Spec {
+    Year,
+    YearMod100,
+    IsoYear,
+    IsoWeek,
+    Month,
+    ShortMonthName,
+    MonthName,
+    Day,
+    Ordinal,
+    ShortWeekdayName,
+    WeekdayName,
+    /// `%u`, 1 for Monday to 7 for Sunday.
+    WeekdayFromMonday,
+    /// `%w`, 0 for Sunday to 6 for Saturday.
+    WeekdayFromSunday,
+    Hour,
+    Hour12,
+    Minute,
+    Second,
+    /// `%f`, the fraction of the second as digits without a dot.
+    Fraction,
+    /// `%.f`, a dot and the fraction of the second, or nothing.
+    DotFraction,
+    UpperAmPm,
+    LowerAmPm,
+    Offset,
+    ColonOffset,
+    Timestamp,
+}
+
+impl Spec {
+    /// The specifier and what it stands for, as used in errors.
+    fn describe(self) -> &'static str {
+        match self {
+            Spec::Year => "`%Y` (year)",
+            Spec::YearMod100 => "`%y` (year 00-99)",
+            Spec::IsoYear => "`%G` (ISO week year)",
+            Spec::IsoWeek => "`%V` (ISO week 01-53)",
+            Spec::Month => "`%m` (month 01-12)",
+            Spec::ShortMonthName => "`%b` (abbreviated month name)",
+            Spec::MonthName => "`%B` (month name)",
+            Spec::Day => "`%d` (day 01-31)",
+            Spec::Ordinal => "`%j` (day of year 001-366)",
+            Spec::ShortWeekdayName => "`%a` (abbreviated weekday name)",
+            Spec::WeekdayName => "`%A` (weekday name)",
+            Spec::WeekdayFromMonday => "`%u` (weekday 1-7)",
+            Spec::WeekdayFromSunday => "`%w` (weekday 0-6)",
+            Spec::Hour => "`%H` (hour 00-23)",
+            Spec::Hour12 => "`%I` (hour 01-12)",
+            Spec::Minute => "`%M` (minute 00-59)",
+            Spec::Second => "`%S` (second 00-60)",
+            Spec::Fraction => "`%f` (fraction of a second)",
+            Spec::DotFraction => "`%.f` (fraction of a second)",
+            Spec::UpperAmPm => "`%p` (AM or PM)",
+            Spec::LowerAmPm => "`%P` (am or pm)",
+            Spec::Offset => "`%z` (offset +hhmm)",
+            Spec::ColonOffset => "`%:z` (offset +hh:mm)",
+            Spec::Timestamp => "`%s` (UNIX timestamp)",
+        }
+    }
+
+    /// The most digits and the allowed values of numeric specifiers.
+    fn digits(self) -> Option<(usize, i64, i64)> {
+        match self {
+            Spec::YearMod100 => Some((2, 0, 99)),
+            Spec::IsoWeek => Some((2, 1, 53)),
+            Spec::Month => Some((2, 1, 12)),
+            Spec::Day => Some((2, 1, 31)),
+            Spec::Ordinal => Some((3, 1, 366)),
+            Spec::WeekdayFromMonday => Some((1, 1, 7)),
+            Spec::WeekdayFromSunday => Some((1, 0, 6)),
+            Spec::Hour => Some((2, 0, 23)),
+            Spec::Hour12 => Some((2, 1, 12)),
+            Spec::Minute => Some((2, 0, 59)),
+            Spec::Second => Some((2, 0, 60)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Padding {
+    /// Zeros, or spaces for `%e`, `%k` and `%l`.
+    Default,
+    None,
+    Space,
+    Zero,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Item {
+    Literal(String),
+    /// Any whitespace when parsing, the whitespace of the format when formatting.
+    Space(String),
+    Field(Spec, Padding),
+    Optional(Vec<Item>),
+}
+
+/// The names of months, weekdays and the halves of the day in a locale.
+#[derive(Debug, Clone)]
+struct Names {
+    months: Vec<String>,
+    short_months: Vec<String>,
+    weekdays: Vec<String>,
+    short_weekdays: Vec<String>,
+    am_pm: Vec<String>,
+}
+
+impl Names {
+    fn new(locale: Locale) -> Self {
+        let name = |time: DateTime<Utc>, format: &str| time.format_localized(format, locale).to_string();
+        let at = |month, day, hour| Utc.with_ymd_and_hms(2001, month, day, hour, 0, 0).single().expect("valid date");
+        // 2001 starts on a Monday.
+        let months = |format| (1..=12).map(|month| name(at(month, 1, 0), format)).collect();
+        let weekdays = |format| (1..=7).map(|day| name(at(1, day, 0), format)).collect();
+        Names {
+            months: months("%B"),
+            short_months: months("%b"),
+            weekdays: weekdays("%A"),
+            short_weekdays: weekdays("%a"),
+            am_pm: vec![name(at(1, 1, 0), "%p"), name(at(1, 1, 12), "%p")],
+        }
+    }
+}
+
+/// A compiled format string.
+#[derive(Debug, Clone)]
+pub struct DateFormat {
+    items: Vec<Item>,
+    names: Names,
+}
+
+impl DateFormat {
+    /// Compiles `format`. Unknown specifiers and unbalanced optional sections are located in
+    /// the format string.
+    pub fn compile(format: &str) -> Result<Self, Located<ParseError>> {
+        let items = items(end().boxed()).pars(format)?;
+        Ok(DateFormat { items, names: Names::new(Locale::POSIX) })
+    }
+
+    /// Uses the names of months and weekdays of `locale`, English by default.
+    pub fn with_locale(self, locale: Locale) -> Self {
+        DateFormat { names: Names::new(locale), ..self }
+    }
+
+    /// A parser for the fields of the format, which are checked for conflicts but not whether
+    /// they form a valid date.
+    pub fn parser(&self) -> FormatParser<Parsed> {
+        spanned(sequence(&self.items, &self.names)).flat_map(|fields| {
+            let mut parsed = Parsed::new();
+            for field in fields.target() {
+                let (spec, value) = *field.target();
+                if let Err(error) = apply(&mut parsed, spec, value) {
+                    let range = field.source_range();
+                    return fail(range.start.clone().locate(range.end.clone(), ParseError::custom(format!("{} conflicts with an earlier field: {}", spec.describe(), error))));
+                }
+            }
+            Succeed::with(parsed).boxed()
+        }).boxed()
+    }
+
+    /// Parses the whole input into its fields.
+    pub fn parse(&self, input: impl Into<String>) -> Result<Parsed, Located<ParseError>> {
+        self.parser().ignore(end()).pars(input)
+    }
+
+    pub fn parse_date(&self, input: impl Into<String>) -> Result<NaiveDate, Located<ParseError>> {
+        self.parse_to(input, |parsed| parsed.to_naive_date())
+    }
+
+    pub fn parse_time(&self, input: impl Into<String>) -> Result<NaiveTime, Located<ParseError>> {
+        self.parse_to(input, |parsed| parsed.to_naive_time())
+    }
+
+    pub fn parse_date_time(&self, input: impl Into<String>) -> Result<NaiveDateTime, Located<ParseError>> {
+        self.parse_to(input, |parsed| parsed.to_naive_datetime_with_offset(0))
+    }
+
+    /// Parses a date and time with an offset, which the format has to contain.
+    pub fn parse_date_time_with_offset(&self, input: impl Into<String>) -> Result<DateTime<FixedOffset>, Located<ParseError>> {
+        self.parse_to(input, |parsed| parsed.to_datetime())
+    }
+
+    /// Parses the input and converts the fields, failing over the whole input if they don't
+    /// describe a valid value.
+    fn parse_to<T, F>(&self, input: impl Into<String>, convert: F) -> Result<T, Located<ParseError>>
+        where F: Fn(&Parsed) -> chrono::ParseResult<T>
+    {
+        let parsed = spanned(self.parser()).ignore(end()).pars(input)?;
+        convert(parsed.target()).map_err(|error| {
+            let range = parsed.source_range();
+            range.start.clone().locate(range.end.clone(), ParseError::custom(error.to_string()))
+        })
+    }
+
+    /// Formats `time`, or returns `None` if the format has an offset outside of an optional
+    /// section.
+    pub fn format(&self, time: &NaiveDateTime) -> Option<String> {
+        let mut formatted = String::new();
+        write_items(&mut formatted, &self.items, &self.names, time, None).then_some(formatted)
+    }
+
+    pub fn format_with_offset(&self, time: &DateTime<FixedOffset>) -> String {
+        let mut formatted = String::new();
+        write_items(&mut formatted, &self.items, &self.names, &time.naive_local(), Some(*time.offset()));
+        formatted
+    }
+}
+
+/// The items of a format string up to `terminator`.
+fn items<T: 'static>(terminator: FormatParser<T>) -> FormatParser<Vec<Item>> {
+    until(lazy(item).boxed(), terminator).map(|items| items.into_iter().flatten().collect()).boxed()
+}
+
+/// One item of a format string, or the items a shorthand stands for. An optional section is an
+/// item too.
+fn item() -> FormatParser<Vec<Item>> {
+    let padding = satisfy("padding", |c| "-_0".contains(c)).optional().map(|padding| match padding {
+        Some('-') => Padding::None,
+        Some('_') => Padding::Space,
+        Some('0') => Padding::Zero,
+        _ => Padding::Default,
+    });
+    let name = token(":z").or(token(".f")).or(satisfy("specifier", |c| c.is_ascii_alphabetic()).map(String::from));
+    let specifier = spanned(padding.map2(name, |padding, name| (padding, name))).flat_map(|specifier| {
+        let (padding, name) = specifier.target().clone();
+        let field = |spec| vec![Item::Field(spec, padding)];
+        let space_padded = |spec| vec![Item::Field(spec, if padding == Padding::Default { Padding::Space } else { padding })];
+        // The shorthands stand for their fields with a separator after all but the last one.
+        let fields = |specs: &[Spec], separator: &str| {
+            let mut items = vec![];
+            for spec in specs {
+                if !items.is_empty() {
+                    items.push(Item::Literal(String::from(separator)));
+                }
+                items.push(Item::Field(*spec, padding));
+            }
+            items
+        };
+        let item = match name.as_str() {
+            "Y" => field(Spec::Year),
+            "y" => field(Spec::YearMod100),
+            "G" => field(Spec::IsoYear),
+            "V" => field(Spec::IsoWeek),
+            "m" => field(Spec::Month),
+            "b" | "h" => field(Spec::ShortMonthName),
+            "B" => field(Spec::MonthName),
+            "d" => field(Spec::Day),
+            "e" => space_padded(Spec::Day),
+            "j" => field(Spec::Ordinal),
+            "a" => field(Spec::ShortWeekdayName),
+            "A" => field(Spec::WeekdayName),
+            "u" => field(Spec::WeekdayFromMonday),
+            "w" => field(Spec::WeekdayFromSunday),
+            "H" => field(Spec::Hour),
+            "k" => space_padded(Spec::Hour),
+            "I" => field(Spec::Hour12),
+            "l" => space_padded(Spec::Hour12),
+            "M" => field(Spec::Minute),
+            "S" => field(Spec::Second),
+            "f" => field(Spec::Fraction),
+            ".f" => field(Spec::DotFraction),
+            "p" => field(Spec::UpperAmPm),
+            "P" => field(Spec::LowerAmPm),
+            "z" => field(Spec::Offset),
+            ":z" => field(Spec::ColonOffset),
+            "s" => field(Spec::Timestamp),
+            "D" => fields(&[Spec::Month, Spec::Day, Spec::YearMod100], "/"),
+            "F" => fields(&[Spec::Year, Spec::Month, Spec::Day], "-"),
+            "T" => fields(&[Spec::Hour, Spec::Minute, Spec::Second], ":"),
+            "R" => fields(&[Spec::Hour, Spec::Minute], ":"),
+            "n" => vec![Item::Space(String::from("\n"))],
+            "t" => vec![Item::Space(String::from("\t"))],
+            _ => {
+                let range = specifier.source_range();
+                return fail(range.start.clone().locate(range.end.clone(), ParseError::custom(format!("unknown specifier `%{}`", name))));
+            }
+        };
+        Succeed::with(item).boxed()
+    });
+    let optional = token("%[").map2(items(token("%]").boxed()), |_, items| vec![Item::Optional(items)]);
+    let percent = token("%%").map(|_| vec![Item::Literal(String::from("%"))]);
+    let space = chars1("whitespace", char::is_whitespace).map(|space| vec![Item::Space(space)]);
+    let literal = chars1("literal", |c| c != '%' && !c.is_whitespace()).map(|text| vec![Item::Literal(text)]);
+    percent.or(optional).or(token("%").map2(specifier, |_, item| item)).or(space).or(literal).boxed()
+}
+
+/// A parsed field: the specifier with a value that is ready for `apply`.
+type Field = Located<(Spec, i64)>;
+
+fn sequence(items: &[Item], names: &Names) -> FormatParser<Vec<Field>> {
+    let mut parser: FormatParser<Vec<Field>> = Succeed::with(vec![]).boxed();
+    for item in items {
+        let next = match item {
+            Item::Literal(literal) => token(literal).map(|_| vec![]).boxed(),
+            Item::Space(_) => Chop::while_con(char::is_whitespace).err_into::<Located<ParseError>>().map(|_| vec![]).boxed(),
+            Item::Field(spec, padding) => spanned(field(*spec, *padding, names)).map(|field| vec![field]).boxed(),
+            Item::Optional(items) => sequence(items, names).optional().map(Option::unwrap_or_default).boxed(),
+        };
+        parser = parser.map2(next, |mut fields, next| {
+            fields.extend(next);
+            fields
+        }).boxed();
+    }
+    parser
+}
+
+/// The value of one specifier.
+fn field(spec: Spec, padding: Padding, names: &Names) -> FormatParser<(Spec, i64)> {
+    let expected = move |state: &TextState| Err(state.locate_at_exactly(ParseError::expected_rule(spec.describe())));
+    let parser = match spec {
+        Spec::ShortMonthName => name(spec, names.short_months.clone(), 1),
+        Spec::MonthName => name(spec, names.months.clone(), 1),
+        Spec::ShortWeekdayName => name(spec, names.short_weekdays.clone(), 0),
+        Spec::WeekdayName => name(spec, names.weekdays.clone(), 0),
+        Spec::UpperAmPm | Spec::LowerAmPm => name(spec, names.am_pm.clone(), 0),
+        Spec::Fraction => fraction(spec),
+        Spec::DotFraction => token(".").map2(fraction(spec), |_, nanos| nanos).optional().map(|nanos| nanos.unwrap_or(0)).boxed(),
+        Spec::Offset | Spec::ColonOffset => from_fn(move |mut state: TextState| {
+            let start = state.clone();
+            let sign = match state.peek() {
+                Some('+') => 1,
+                Some('-') => -1,
+                _ => return expected(&start),
+            };
+            state.advance();
+            let mut digits = vec![];
+            while digits.len() < 4 {
+                if digits.len() == 2 && state.peek() == Some(':') {
+                    state.advance();
+                }
+                match state.peek().and_then(|c| c.to_digit(10)) {
+                    Some(digit) => digits.push(i64::from(digit)),
+                    None => return expected(&start),
+                }
+                state.advance();
+            }
+            let (hours, minutes) = (digits[0] * 10 + digits[1], digits[2] * 10 + digits[3]);
+            if hours > 23 || minutes > 59 {
+                return expected(&start);
+            }
+            Ok((state, sign * (hours * 3600 + minutes * 60)))
+        }).boxed(),
+        Spec::Year | Spec::IsoYear | Spec::Timestamp => number(spec, if spec == Spec::Timestamp { 19 } else { 4 }, i64::MIN, i64::MAX, true),
+        _ => {
+            let (digits, min, max) = spec.digits().expect("numeric specifier");
+            let number = number(spec, digits, min, max, false);
+            if padding == Padding::Space {
+                Chop::while_con(|c| c == ' ').map2(number, |_, value| value).boxed()
+            } else {
+                number
+            }
+        }
+    };
+    parser.map(move |value| (spec, value)).boxed()
+}
+
+/// Up to `max_digits` digits with a value in `min..=max`. Signed numbers may have more digits.
+fn number(spec: Spec, max_digits: usize, min: i64, max: i64, signed: bool) -> FormatParser<i64> {
+    from_fn(move |mut state: TextState| {
+        let start = state.clone();
+        let expected = || Err(start.locate_at_exactly(ParseError::expected_rule(spec.describe())));
+        let sign = match state.peek() {
+            Some('-') if signed => -1,
+            Some('+') if signed => 1,
+            _ => 0,
+        };
+        if sign != 0 {
+            state.advance();
+        }
+        let max_digits = if sign != 0 { 18 } else { max_digits };
+        let mut value: i64 = 0;
+        let mut count = 0;
+        while let Some(digit) = state.peek().and_then(|c| c.to_digit(10)) {
+            if count == max_digits {
+                break;
+            }
+            value = match value.checked_mul(10).and_then(|value| value.checked_add(i64::from(digit))) {
+                Some(value) => value,
+                None => return expected(),
+            };
+            count += 1;
+            state.advance();
+        }
+        let value = if sign < 0 { -value } else { value };
+        if count == 0 || value < min || value > max {
+            return expected();
+        }
+        Ok((state, value))
+    }).boxed()
+}
+
+/// Digits of a fraction of a second, as nanoseconds.
+fn fraction(spec: Spec) -> FormatParser<i64> {
+    from_fn(move |mut state: TextState| {
+        let start = state.clone();
+        let mut digits = String::new();
+        while let Some(c) = state.peek().filter(char::is_ascii_digit) {
+            digits.push(c);
+            state.advance();
+        }
+        if digits.is_empty() {
+            return Err(start.locate_at_exactly(ParseError::expected_rule(spec.describe())));
+        }
+        let nanos: String = digits.chars().chain(std::iter::repeat('0')).take(9).collect();
+        Ok((state, nanos.parse().expect("nine digits")))
+    }).boxed()
+}
+
+/// One of `names`, ignoring case, as its index plus `first`. The longest name that matches wins.
+fn name(spec: Spec, names: Vec<String>, first: i64) -> FormatParser<i64> {
+    from_fn(move |state: TextState| {
+        let mut by_length: Vec<(usize, &String)> = names.iter().enumerate().collect();
+        by_length.sort_by_key(|(_, name)| std::cmp::Reverse(name.chars().count()));
+        for (index, name) in by_length {
+            let mut after = state.clone();
+            let matches = name.chars().all(|expected| match after.peek() {
+                Some(c) if c.to_lowercase().eq(expected.to_lowercase()) => {
+                    after.advance();
+                    true
+                }
+                _ => false,
+            });
+            if matches && !name.is_empty() {
+                return Ok((after, index as i64 + first));
+            }
+        }
+        Err(state.locate_at_exactly(ParseError::expected_rule(spec.describe())))
+    }).boxed()
+}
+
+fn apply(parsed: &mut Parsed, spec: Spec, value: i64) -> chrono::ParseResult<()> {
+    match spec {
+        Spec::Year => parsed.set_year(value),
+        Spec::YearMod100 => parsed.set_year_mod_100(value),
+        Spec::IsoYear => parsed.set_isoyear(value),
+        Spec::IsoWeek => parsed.set_isoweek(value),
+        Spec::Month | Spec::ShortMonthName | Spec::MonthName => parsed.set_month(value),
+        Spec::Day => parsed.set_day(value),
+        Spec::Ordinal => parsed.set_ordinal(value),
+        Spec::ShortWeekdayName | Spec::WeekdayName => parsed.set_weekday(WEEKDAYS[value as usize]),
+        Spec::WeekdayFromMonday => parsed.set_weekday(WEEKDAYS[value as usize - 1]),
+        Spec::WeekdayFromSunday => parsed.set_weekday(WEEKDAYS[(value as usize + 6) % 7]),
+        Spec::Hour => parsed.set_hour(value),
+        Spec::Hour12 => parsed.set_hour12(value),
+        Spec::UpperAmPm | Spec::LowerAmPm => parsed.set_ampm(value == 1),
+        Spec::Minute => parsed.set_minute(value),
+        Spec::Second => parsed.set_second(value),
+        Spec::Fraction | Spec::DotFraction => parsed.set_nanosecond(value),
+        Spec::Offset | Spec::ColonOffset => parsed.set_offset(value),
+        Spec::Timestamp => parsed.set_timestamp(value),
+    }
+}
+
+/// Writes `items` and returns whether all their fields were available.
+fn write_items(out: &mut String, items: &[Item], names: &Names, time: &NaiveDateTime, offset: Option<FixedOffset>) -> bool {
+    for item in items {
+        match item {
+            Item::Literal(literal) => out.push_str(literal),
+            Item::Space(space) => out.push_str(space),
+            Item::Field(spec, padding) => {
+                if !write_field(out, *spec, *padding, names, time, offset) {
+                    return false;
+                }
+            }
+            Item::Optional(items) => {
+                let mut section = String::new();
+                if write_items(&mut section, items, names, time, offset) {
+                    out.push_str(&section);
+                }
+            }
+        }
+    }
+    true
+}
+
+fn write_field(out: &mut String, spec: Spec, padding: Padding, names: &Names, time: &NaiveDateTime, offset: Option<FixedOffset>) -> bool {
+    let (hour12_pm, hour12) = time.hour12();
+    let number = |width: usize, value: i64| match padding {
+        Padding::None => value.to_string(),
+        Padding::Space => format!("{:>width$}", value, width = width),
+        Padding::Default | Padding::Zero => format!("{:0width$}", value, width = width),
+    };
+    let nanos = time.nanosecond() % 1_000_000_000;
+    let text = match spec {
+        Spec::Year if (0..10_000).contains(&time.year()) => number(4, time.year().into()),
+        Spec::Year => format!("{:+}", time.year()),
+        Spec::YearMod100 => number(2, time.year().rem_euclid(100).into()),
+        Spec::IsoYear => number(4, time.iso_week().year().into()),
+        Spec::IsoWeek => number(2, time.iso_week().week().into()),
+        Spec::Month => number(2, time.month().into()),
+        Spec::ShortMonthName => names.short_months[time.month0() as usize].clone(),
+        Spec::MonthName => names.months[time.month0() as usize].clone(),
+        Spec::Day => number(2, time.day().into()),
+        Spec::Ordinal => number(3, time.ordinal().into()),
+        Spec::ShortWeekdayName => names.short_weekdays[time.weekday().num_days_from_monday() as usize].clone(),
+        Spec::WeekdayName => names.weekdays[time.weekday().num_days_from_monday() as usize].clone(),
+        Spec::WeekdayFromMonday => time.weekday().number_from_monday().to_string(),
+        Spec::WeekdayFromSunday => time.weekday().num_days_from_sunday().to_string(),
+        Spec::Hour => number(2, time.hour().into()),
+        Spec::Hour12 => number(2, hour12.into()),
+        Spec::Minute => number(2, time.minute().into()),
+        // Leap seconds, see `iso8601::time`.
+        Spec::Second => number(2, (time.second() + time.nanosecond() / 1_000_000_000).into()),
+        Spec::Fraction => format!("{:09}", nanos),
+        Spec::DotFraction if nanos == 0 => String::new(),
+        Spec::DotFraction => format!(".{:09}", nanos).trim_end_matches('0').to_string(),
+        Spec::UpperAmPm => names.am_pm[hour12_pm as usize].clone(),
+        Spec::LowerAmPm => names.am_pm[hour12_pm as usize].to_lowercase(),
+        Spec::Offset | Spec::ColonOffset => match offset {
+            Some(offset) => {
+                let seconds = offset.local_minus_utc();
+                let sign = if seconds < 0 { '-' } else { '+' };
+                let (hours, minutes) = (seconds.abs() / 3600, seconds.abs() / 60 % 60);
+                let colon = if spec == Spec::ColonOffset { ":" } else { "" };
+                format!("{}{:02}{}{:02}", sign, hours, colon, minutes)
+            }
+            None => return false,
+        },
+        Spec::Timestamp => match offset {
+            Some(offset) => (*time - offset).and_utc().timestamp().to_string(),
+            None => time.and_utc().timestamp().to_string(),
+        },
+    };
+    out.push_str(&text);
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{FixedOffset, Locale, NaiveDate, TimeZone, Timelike};
+
+    use crate::formats::strftime::DateFormat;
+
+    #[test]
+    fn numeric_fields() {
+        let format = DateFormat::compile("%d.%m.%Y %H:%M:%S%.f").unwrap();
+        assert_eq!(Ok(NaiveDate::from_ymd_opt(1996, 8, 14).unwrap().and_hms_milli_opt(7, 5, 9, 250).unwrap()), format.parse_date_time("14.08.1996 07:05:09.25"));
+        assert_eq!(Ok(NaiveDate::from_ymd_opt(1996, 8, 4).unwrap().and_hms_opt(7, 5, 9).unwrap()), format.parse_date_time("4.8.1996   7:5:9"));
+
+        let error = format.parse_date_time("14.13.1996 07:05:09").unwrap_err();
+        assert_eq!("expected `%m` (month 01-12)", error.target().to_string());
+        assert_eq!(3, error.source_range().start.byte_offset());
+
+        let error = format.parse_date_time("31.02.1996 07:05:09").unwrap_err();
+        assert_eq!(0, error.source_range().start.byte_offset());
+        assert_eq!(19, error.source_range().end.byte_offset());
+    }
+
+    #[test]
+    fn names_and_locales() {
+        let format = DateFormat::compile("%A, %e. %B %Y, %l:%M %p").unwrap();
+        let time = NaiveDate::from_ymd_opt(2021, 3, 5).unwrap().and_hms_opt(15, 4, 0).unwrap();
+        assert_eq!(Some(String::from("Friday,  5. March 2021,  3:04 PM")), format.format(&time));
+        assert_eq!(Ok(time), format.parse_date_time("friday, 5. MARCH 2021, 3:04 pm"));
+
+        let german = DateFormat::compile("%A, %-d. %B %Y").unwrap().with_locale(Locale::de_DE);
+        assert_eq!(Some(String::from("Freitag, 5. März 2021")), german.format(&time));
+        assert_eq!(Ok(time.date()), german.parse_date("Freitag, 5. März 2021"));
+
+        let error = german.parse_date("Freitag, 5. March 2021").unwrap_err();
+        assert_eq!("expected `%B` (month name)", error.target().to_string());
+        assert_eq!(13, error.source_range().start.column());
+
+        let error = german.parse_date("Montag, 5. März 2021").unwrap_err();
+        assert_eq!(0, error.source_range().start.byte_offset());
+    }
+
+    #[test]
+    fn optional_sections_and_shorthands() {
+        let format = DateFormat::compile("%F%[T%T%[%:z%]%]").unwrap();
+        let date = NaiveDate::from_ymd_opt(2020, 2, 29).unwrap();
+        assert_eq!(Ok(date), format.parse_date("2020-02-29"));
+        assert_eq!(Ok(date.and_hms_opt(23, 59, 1).unwrap()), format.parse_date_time("2020-02-29T23:59:01"));
+        let offset = FixedOffset::west_opt(2 * 3600 + 30 * 60).unwrap();
+        let time = offset.with_ymd_and_hms(2020, 2, 29, 23, 59, 1).unwrap();
+        assert_eq!(Ok(time), format.parse_date_time_with_offset("2020-02-29T23:59:01-02:30"));
+
+        assert_eq!(Some(String::from("2020-02-29T23:59:01")), format.format(&date.and_hms_opt(23, 59, 1).unwrap()));
+        assert_eq!("2020-02-29T23:59:01-02:30", format.format_with_offset(&time));
+        assert_eq!(None, DateFormat::compile("%T %z").unwrap().format(&date.and_hms_opt(0, 0, 0).unwrap()));
+
+        // The shorthand fields are required, only the section around them is optional.
+        let error = format.parse_date("2020-02").unwrap_err();
+        assert_eq!("expected `-`", error.target().to_string());
+        assert_eq!(7, error.source_range().start.byte_offset());
+        assert!(DateFormat::compile("%T").unwrap().parse_time("23:59").is_err());
+    }
+
+    #[test]
+    fn whitespace() {
+        let format = DateFormat::compile("%Y%n%m%t%d  %H:%M").unwrap();
+        let time = NaiveDate::from_ymd_opt(2021, 3, 5).unwrap().and_hms_opt(15, 0, 0).unwrap();
+        assert_eq!(Some(String::from("2021\n03\t05  15:00")), format.format(&time));
+        assert_eq!(Ok(time), format.parse_date_time("2021 03\n 05\t15:00"));
+    }
+
+    #[test]
+    fn conflicting_fields() {
+        let format = DateFormat::compile("%a %d.%m.%Y (%u)").unwrap();
+        assert_eq!(Ok(NaiveDate::from_ymd_opt(2021, 3, 5).unwrap()), format.parse_date("Fri 05.03.2021 (5)"));
+        let error = format.parse_date("Fri 05.03.2021 (4)").unwrap_err();
+        assert!(error.target().to_string().starts_with("`%u` (weekday 1-7) conflicts with an earlier field"));
+        assert_eq!(16, error.source_range().start.byte_offset());
+    }
+
+    #[test]
+    fn format_errors() {
+        let error = DateFormat::compile("%Y-%m-%Q").unwrap_err();
+        assert_eq!("unknown specifier `%Q`", error.target().to_string());
+        assert_eq!(6..8, error.source_range().start.byte_offset()..error.source_range().end.byte_offset());
+
+        assert!(DateFormat::compile("%[%Y").is_err());
+        assert!(DateFormat::compile("%Y%]").is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let offset = FixedOffset::east_opt(5 * 3600 + 45 * 60).unwrap();
+        let time = offset.with_ymd_and_hms(2009, 12, 31, 4, 30, 12).unwrap().with_nanosecond(123_456_000).unwrap();
+        for format in &["%d.%m.%Y", "%Y-%j", "%G-W%V-%u", "%A %-d %B %y, %I%P", "%D %R"] {
+            let compiled = DateFormat::compile(format).unwrap();
+            let formatted = compiled.format_with_offset(&time);
+            assert_eq!(Ok(time.date_naive()), compiled.parse_date(formatted.as_str()), "{}", format);
+        }
+        for format in &["%Y%m%dT%H%M%S%.f%z", "%b %e %Y %T%.f %:z"] {
+            let compiled = DateFormat::compile(format).unwrap();
+            let formatted = compiled.format_with_offset(&time);
+            assert_eq!(Ok(time), compiled.parse_date_time_with_offset(formatted.as_str()), "{}", format);
+        }
+        let timestamp = DateFormat::compile("%s").unwrap();
+        assert_eq!("1262213112", timestamp.format_with_offset(&time));
+        assert_eq!(Ok(time.naive_utc().with_nanosecond(0).unwrap()), timestamp.parse_date_time("1262213112"));
+        let error = timestamp.parse_date_time("9999999999999999999").unwrap_err();
+        assert_eq!(0, error.source_range().start.byte_offset());
+        assert!(error.target().to_string().starts_with("expected `%s`"));
+    }
+}
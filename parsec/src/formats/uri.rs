@@ -0,0 +1,480 @@
+//! URIs and relative references as specified by RFC 3986.
+//!
+//! ```
+//! use parsec::formats::uri;
+//!
+//! let base = uri::parse("http://example.com/docs/guide/index.html?page=2").unwrap();
+//! let reference = uri::parse_reference("../api/%7Euser#top").unwrap();
+//! assert_eq!("http://example.com/docs/api/~user#top", base.resolve(&reference).normalize().to_string());
+//! ```
+//!
+//! Components are kept as written, with their percent-encodings. `percent_decode` decodes them
+//! and `Uri::normalize` applies the syntax- and scheme-based normalizations of section 6.
+
+use std::fmt::{Display, Formatter};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::error::ParseError;
+use crate::formats::{after, chars1_with, fail, FormatParser, spanned};
+use crate::parser::{from_fn, named, Parser, Succeed};
+use crate::text::location::Located;
+use crate::text::text_parser::{Chop, end, TextParser, TextState, token};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Uri {
+    scheme: Option<String>,
+    authority: Option<Authority>,
+    path: String,
+    query: Option<String>,
+    fragment: Option<String>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Authority {
+    userinfo: Option<String>,
+    host: Host,
+    port: Option<u16>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Host {
+    Ipv4(Ipv4Addr),
+    Ipv6(Ipv6Addr),
+    /// `v1.fe80::a+en1`, an address of a future IP version.
+    IpFuture(String),
+    /// A registered name, usually looked up with DNS.
+    RegName(String),
+}
+
+impl Uri {
+    /// The scheme, which only relative references don't have.
+    pub fn scheme(&self) -> Option<&str> {
+        self.scheme.as_deref()
+    }
+
+    pub fn authority(&self) -> Option<&Authority> {
+        self.authority.as_ref()
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+
+    pub fn fragment(&self) -> Option<&str> {
+        self.fragment.as_deref()
+    }
+
+    /// Resolves `reference` with this URI as base, see section 5.2.
+    pub fn resolve(&self, reference: &Uri) -> Uri {
+        if reference.scheme.is_some() {
+            return Uri { path: remove_dot_segments(&reference.path), ..reference.clone() };
+        }
+        let (authority, path, query) = if reference.authority.is_some() {
+            (reference.authority.clone(), remove_dot_segments(&reference.path), reference.query.clone())
+        } else if reference.path.is_empty() {
+            (self.authority.clone(), self.path.clone(), reference.query.clone().or_else(|| self.query.clone()))
+        } else if reference.path.starts_with('/') {
+            (self.authority.clone(), remove_dot_segments(&reference.path), reference.query.clone())
+        } else {
+            (self.authority.clone(), remove_dot_segments(&self.merge(&reference.path)), reference.query.clone())
+        };
+        Uri { scheme: self.scheme.clone(), authority, path, query, fragment: reference.fragment.clone() }
+    }
+
+    fn merge(&self, path: &str) -> String {
+        if self.authority.is_some() && self.path.is_empty() {
+            return format!("/{}", path);
+        }
+        match self.path.rfind('/') {
+            Some(slash) => format!("{}{}", &self.path[..=slash], path),
+            None => String::from(path),
+        }
+    }
+
+    /// The URI with lower case scheme and host, upper case percent-encodings, decoded unreserved
+    /// chars and without dot segments. Default ports of well-known schemes are dropped and an
+    /// empty path after an authority becomes `/`.
+    pub fn normalize(&self) -> Uri {
+        let scheme = self.scheme.as_ref().map(|scheme| scheme.to_ascii_lowercase());
+        let authority = self.authority.as_ref().map(|authority| Authority {
+            userinfo: authority.userinfo.as_deref().map(normalize_encoding),
+            host: match &authority.host {
+                Host::RegName(name) => Host::RegName(normalize_encoding(&name.to_ascii_lowercase())),
+                Host::IpFuture(address) => Host::IpFuture(address.to_ascii_lowercase()),
+                host => host.clone(),
+            },
+            port: authority.port.filter(|port| Some(*port) != scheme.as_deref().and_then(default_port)),
+        });
+        let mut path = remove_dot_segments(&normalize_encoding(&self.path));
+        if authority.is_some() && path.is_empty() {
+            path = String::from("/");
+        }
+        Uri {
+            scheme,
+            authority,
+            path,
+            query: self.query.as_deref().map(normalize_encoding),
+            fragment: self.fragment.as_deref().map(normalize_encoding),
+        }
+    }
+}
+
+impl Authority {
+    pub fn userinfo(&self) -> Option<&str> {
+        self.userinfo.as_deref()
+    }
+
+    pub fn host(&self) -> &Host {
+        &self.host
+    }
+
+    /// The port, if one is given. An empty port is the same as none.
+    pub fn port(&self) -> Option<u16> {
+        self.port
+    }
+}
+
+impl Display for Uri {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(scheme) = &self.scheme {
+            write!(f, "{}:", scheme)?;
+        }
+        if let Some(authority) = &self.authority {
+            write!(f, "//{}", authority)?;
+        }
+        f.write_str(&self.path)?;
+        if let Some(query) = &self.query {
+            write!(f, "?{}", query)?;
+        }
+        if let Some(fragment) = &self.fragment {
+            write!(f, "#{}", fragment)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Authority {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(userinfo) = &self.userinfo {
+            write!(f, "{}@", userinfo)?;
+        }
+        write!(f, "{}", self.host)?;
+        if let Some(port) = self.port {
+            write!(f, ":{}", port)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Host {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Host::Ipv4(address) => write!(f, "{}", address),
+            Host::Ipv6(address) => write!(f, "[{}]", address),
+            Host::IpFuture(address) => write!(f, "[{}]", address),
+            Host::RegName(name) => f.write_str(name),
+        }
+    }
+}
+
+/// Parses an absolute URI, which has a scheme and may have a fragment.
+pub fn parse(input: impl Into<String>) -> Result<Uri, Located<ParseError>> {
+    uri().ignore(end()).pars(input)
+}
+
+/// Parses a URI or a relative reference.
+pub fn parse_reference(input: impl Into<String>) -> Result<Uri, Located<ParseError>> {
+    uri_reference().ignore(end()).pars(input)
+}
+
+/// A URI with scheme.
+pub fn uri() -> FormatParser<Uri> {
+    named("URI", scheme().ignore(token(":")).flat_map(|scheme| rest(Some(scheme)))).boxed()
+}
+
+/// A URI or a relative reference.
+pub fn uri_reference() -> FormatParser<Uri> {
+    named("URI reference", scheme().ignore(token(":")).optional().flat_map(rest)).boxed()
+}
+
+/// Decodes the percent-encodings of a component. Other chars are kept as UTF-8.
+pub fn percent_decode(component: &str) -> Vec<u8> {
+    let bytes = component.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        match (bytes[index], hex_byte(component.get(index + 1..index + 3))) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    decoded
+}
+
+/// The byte of two hex digits.
+fn hex_byte(hex: Option<&str>) -> Option<u8> {
+    hex.filter(|hex| hex.chars().all(|c| c.is_ascii_hexdigit())).and_then(|hex| u8::from_str_radix(hex, 16).ok())
+}
+
+fn is_unreserved(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "-._~".contains(c)
+}
+
+fn is_sub_delim(c: char) -> bool {
+    "!$&'()*+,;=".contains(c)
+}
+
+fn is_pchar(c: char) -> bool {
+    is_unreserved(c) || is_sub_delim(c) || c == ':' || c == '@'
+}
+
+fn scheme() -> FormatParser<String> {
+    chars1_with("scheme", |c| c.is_ascii_alphabetic(), |c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        .boxed()
+}
+
+/// Chars for which `allowed` is true and percent-encodings. A `%` must start a
+/// percent-encoding.
+fn component(allowed: fn(char) -> bool) -> FormatParser<String> {
+    from_fn(move |mut state: TextState| {
+        let mut component = String::new();
+        loop {
+            match state.peek() {
+                Some('%') => {
+                    let start = state.location().clone();
+                    component.push('%');
+                    state.advance();
+                    for _ in 0..2 {
+                        match state.peek() {
+                            Some(c) if c.is_ascii_hexdigit() => {
+                                component.push(c);
+                                state.advance();
+                            }
+                            _ => return Err(state.locate(start, ParseError::custom("`%` must be followed by two hex digits"))),
+                        }
+                    }
+                }
+                Some(c) if allowed(c) => {
+                    component.push(c);
+                    state.advance();
+                }
+                _ => return Ok((state, component)),
+            }
+        }
+    }).boxed()
+}
+
+/// Everything after the scheme.
+fn rest(scheme: Option<String>) -> FormatParser<Uri> {
+    let relative = scheme.is_none();
+    after("//", authority())
+        .flat_map(move |authority| {
+            let has_authority = authority.is_some();
+            path(has_authority, relative).map(move |path| (authority.clone(), path))
+        })
+        .map2(after("?", component(|c| is_pchar(c) || c == '/' || c == '?')), |(authority, path), query| (authority, path, query))
+        .map2(after("#", component(|c| is_pchar(c) || c == '/' || c == '?')), move |(authority, path, query), fragment| Uri {
+            scheme: scheme.clone(),
+            authority,
+            path,
+            query,
+            fragment,
+        })
+        .boxed()
+}
+
+fn path(has_authority: bool, relative: bool) -> FormatParser<String> {
+    spanned(component(|c| is_pchar(c) || c == '/')).flat_map(move |path| {
+        let text = path.target();
+        let message = if has_authority && !text.is_empty() && !text.starts_with('/') {
+            "the path after an authority must start with `/`"
+        } else if relative && !text.starts_with('/') && text.split('/').next().is_some_and(|segment| segment.contains(':')) {
+            "the first segment of a relative path must not contain `:`"
+        } else {
+            return Succeed::with(text.clone()).boxed();
+        };
+        fail(path.map(|_| ParseError::custom(message)))
+    }).boxed()
+}
+
+fn authority() -> FormatParser<Authority> {
+    let userinfo = component(|c| is_unreserved(c) || is_sub_delim(c) || c == ':').ignore(token("@")).optional();
+    let port = spanned(Chop::while_con(|c: char| c.is_ascii_digit()).err_into::<Located<ParseError>>()).flat_map(|digits| match digits.target().as_str() {
+        "" => Succeed::with(None).boxed(),
+        port => match port.parse() {
+            Ok(port) => Succeed::with(Some(port)).boxed(),
+            Err(_) => fail(digits.map(|_| ParseError::custom("port out of range"))),
+        },
+    });
+    (userinfo, host(), after(":", port.boxed()))
+        .map(|(userinfo, host, port)| Authority { userinfo, host, port: port.flatten() })
+        .boxed()
+}
+
+fn host() -> FormatParser<Host> {
+    let future = (token("v"), Chop::while_con(|c: char| c.is_ascii_hexdigit()), token("."), component(|c| is_unreserved(c) || is_sub_delim(c) || c == ':'))
+        .map(|(v, version, dot, address)| Host::IpFuture(format!("{}{}{}{}", v, version, dot, address)));
+    let ipv6 = spanned(Chop::while_con(|c: char| c.is_ascii_hexdigit() || c == ':' || c == '.').err_into::<Located<ParseError>>()).flat_map(|address| {
+        match address.target().parse() {
+            Ok(address) => Succeed::with(Host::Ipv6(address)).boxed(),
+            Err(_) => fail(address.map(|_| ParseError::custom("invalid IPv6 address"))),
+        }
+    });
+    let literal = after("[", future.or(ipv6).ignore(token("]")).boxed());
+    // A reg-name may be empty, so it would hide the errors of a malformed literal.
+    named("host", literal.flat_map(|literal| match literal {
+        Some(host) => Succeed::with(host).boxed(),
+        None => component(|c| is_unreserved(c) || is_sub_delim(c)).map(|name| match name.parse() {
+            Ok(address) => Host::Ipv4(address),
+            Err(_) => Host::RegName(name),
+        }).boxed(),
+    })).boxed()
+}
+
+/// Section 5.2.4.
+fn remove_dot_segments(path: &str) -> String {
+    let mut input = path;
+    let mut output = String::new();
+    while !input.is_empty() {
+        if let Some(rest) = input.strip_prefix("../").or_else(|| input.strip_prefix("./")) {
+            input = rest;
+        } else if input.starts_with("/./") {
+            input = &input[2..];
+        } else if input == "/." {
+            input = "/";
+        } else if input.starts_with("/../") || input == "/.." {
+            input = if input == "/.." { "/" } else { &input[3..] };
+            output.truncate(output.rfind('/').unwrap_or(0));
+        } else if input == "." || input == ".." {
+            input = "";
+        } else {
+            let end = input[1..].find('/').map_or(input.len(), |slash| slash + 1);
+            output.push_str(&input[..end]);
+            input = &input[end..];
+        }
+    }
+    output
+}
+
+/// Upper case percent-encodings and decoded unreserved chars.
+fn normalize_encoding(component: &str) -> String {
+    let mut normalized = String::with_capacity(component.len());
+    let mut rest = component;
+    while let Some(percent) = rest.find('%') {
+        normalized.push_str(&rest[..percent]);
+        let encoded = &rest[percent + 1..];
+        match hex_byte(encoded.get(..2)) {
+            Some(byte) if is_unreserved(byte as char) => normalized.push(byte as char),
+            Some(_) => normalized.push_str(&format!("%{}", encoded[..2].to_ascii_uppercase())),
+            None => {
+                normalized.push('%');
+                rest = encoded;
+                continue;
+            }
+        }
+        rest = &encoded[2..];
+    }
+    normalized.push_str(rest);
+    normalized
+}
+
+fn default_port(scheme: &str) -> Option<u16> {
+    match scheme {
+        "http" | "ws" => Some(80),
+        "https" | "wss" => Some(443),
+        "ftp" => Some(21),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use crate::formats::uri::{self, Host};
+    use crate::text::location::Location;
+
+    #[test]
+    fn parses_components() {
+        let parsed = uri::parse("https://user:pw@Example.com:8443/a/b%20c?q=1&r=/x?#frag").expect("valid URI");
+        assert_eq!(Some("https"), parsed.scheme());
+        let authority = parsed.authority().expect("authority");
+        assert_eq!(Some("user:pw"), authority.userinfo());
+        assert_eq!(&Host::RegName(String::from("Example.com")), authority.host());
+        assert_eq!(Some(8443), authority.port());
+        assert_eq!("/a/b%20c", parsed.path());
+        assert_eq!(b"/a/b c".to_vec(), uri::percent_decode(parsed.path()));
+        assert_eq!(Some("q=1&r=/x?"), parsed.query());
+        assert_eq!(Some("frag"), parsed.fragment());
+
+        let host = |input: &str| uri::parse(input).expect("valid URI").authority().expect("authority").host().clone();
+        assert_eq!(Host::Ipv4(Ipv4Addr::new(192, 168, 0, 1)), host("http://192.168.0.1/"));
+        assert_eq!(Host::RegName(String::from("192.168.0.01")), host("http://192.168.0.01/"));
+        assert_eq!(Host::Ipv6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)), host("http://[fe80::1]:80/"));
+        assert_eq!(Host::IpFuture(String::from("v1.fe80::a+en1")), host("http://[v1.fe80::a+en1]"));
+
+        for input in ["mailto:John.Doe@example.com", "urn:oasis:names:specification:docbook:dtd:xml:4.1.2", "file:///etc/hosts", "tel:+1-816-555-1212", "foo://:0"] {
+            assert_eq!(input, uri::parse(input).expect("valid URI").to_string());
+        }
+        for input in ["//host", "/absolute", "relative/path", "", "?query", "#fragment", "./a:b"] {
+            assert_eq!(input, uri::parse_reference(input).expect("valid reference").to_string());
+        }
+    }
+
+    #[test]
+    fn reports_invalid_components() {
+        let error = |input: &str| uri::parse_reference(input).map(|_| ()).map_err(|error| (error.target().to_string(), error.source_range().start.column()));
+        assert_eq!(Err((String::from("`%` must be followed by two hex digits"), 14)), error("http://a/b?c=%zz"));
+        assert_eq!(Err((String::from("port out of range"), 16)), error("http://example:65536/"));
+        assert_eq!(Err((String::from("invalid IPv6 address"), 9)), error("http://[fe80:::1]/"));
+        assert_eq!(Err((String::from("the path after an authority must start with `/`"), 14)), error("http://host:8x"));
+        assert_eq!(Err((String::from("the first segment of a relative path must not contain `:`"), 1)), error("1a:b/c"));
+        assert_eq!(Location::new(8, 9, 1), uri::parse_reference("http://a b").unwrap_err().source_range().start);
+        assert!(uri::parse("relative/path").is_err());
+    }
+
+    /// The examples of RFC 3986, section 5.4.
+    #[test]
+    fn resolves_references() {
+        let base = uri::parse("http://a/b/c/d;p?q").expect("valid base");
+        let examples = [
+            ("g:h", "g:h"), ("g", "http://a/b/c/g"), ("./g", "http://a/b/c/g"), ("g/", "http://a/b/c/g/"),
+            ("/g", "http://a/g"), ("//g", "http://g"), ("?y", "http://a/b/c/d;p?y"), ("g?y", "http://a/b/c/g?y"),
+            ("#s", "http://a/b/c/d;p?q#s"), ("g#s", "http://a/b/c/g#s"), ("g?y#s", "http://a/b/c/g?y#s"),
+            (";x", "http://a/b/c/;x"), ("g;x", "http://a/b/c/g;x"), ("g;x?y#s", "http://a/b/c/g;x?y#s"),
+            ("", "http://a/b/c/d;p?q"), (".", "http://a/b/c/"), ("./", "http://a/b/c/"), ("..", "http://a/b/"),
+            ("../", "http://a/b/"), ("../g", "http://a/b/g"), ("../..", "http://a/"), ("../../", "http://a/"),
+            ("../../g", "http://a/g"),
+            ("../../../g", "http://a/g"), ("../../../../g", "http://a/g"), ("/./g", "http://a/g"),
+            ("/../g", "http://a/g"), ("g.", "http://a/b/c/g."), (".g", "http://a/b/c/.g"), ("g..", "http://a/b/c/g.."),
+            ("..g", "http://a/b/c/..g"), ("./../g", "http://a/b/g"), ("./g/.", "http://a/b/c/g/"),
+            ("g/./h", "http://a/b/c/g/h"), ("g/../h", "http://a/b/c/h"), ("g;x=1/./y", "http://a/b/c/g;x=1/y"),
+            ("g;x=1/../y", "http://a/b/c/y"), ("g?y/./x", "http://a/b/c/g?y/./x"), ("g?y/../x", "http://a/b/c/g?y/../x"),
+            ("g#s/./x", "http://a/b/c/g#s/./x"), ("g#s/../x", "http://a/b/c/g#s/../x"), ("http:g", "http:g"),
+        ];
+        for (reference, expected) in examples.iter() {
+            let reference = uri::parse_reference(*reference).expect("valid reference");
+            assert_eq!(*expected, base.resolve(&reference).to_string(), "resolves {}", reference);
+        }
+    }
+
+    #[test]
+    fn normalizes() {
+        let normalized = |input: &str| uri::parse(input).expect("valid URI").normalize().to_string();
+        assert_eq!("example://a/b/c/%7Bfoo%7D", normalized("eXAMPLE://a/./b/../b/%63/%7bfoo%7d"));
+        assert_eq!("http://www.example.com/", normalized("HTTP://www.Example.com:80"));
+        assert_eq!("https://example.com:8443/~user?a=%2F", normalized("https://EXAMPLE.com:8443/%7Euser?a=%2f"));
+        assert_eq!("http://[2001:db8::1]/", normalized("http://[2001:0DB8:0:0:0:0:0:1]/"));
+        assert_eq!("http://%C3%A9xample.com/", normalized("http://%c3%a9xAMPLE.com/"));
+    }
+}
@@ -0,0 +1,370 @@
+use std::fmt::Write;
+
+use crate::parser::Parser;
+
+/// The structure of a parser as reported by `Parser::describe`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Description {
+    Sequence(Vec<Description>),
+    Choice(Vec<Description>),
+    Repetition { inner: Box<Description>, min: usize, max: Option<usize> },
+    Token(String),
+    /// A class of input that is not a fixed token, like a number or whitespace.
+    Terminal(String),
+    /// A reference to a named production of the grammar.
+    Rule(String),
+    Empty,
+    /// A parser that can not describe itself, e.g. a closure or a value dependent parser.
+    Opaque,
+}
+
+impl Description {
+    /// A sequence with nested sequences flattened and empty parts removed.
+    pub fn sequence(parts: Vec<Description>) -> Self {
+        let mut flattened = vec![];
+        for part in parts {
+            match part {
+                Description::Sequence(inner) => flattened.extend(inner),
+                Description::Empty => {}
+                part => flattened.push(part),
+            }
+        }
+        match flattened.len() {
+            0 => Description::Empty,
+            1 => flattened.pop().expect("one element"),
+            _ => Description::Sequence(flattened),
+        }
+    }
+
+    /// A choice with nested choices flattened.
+    pub fn choice(alternatives: Vec<Description>) -> Self {
+        let mut flattened = vec![];
+        for alternative in alternatives {
+            match alternative {
+                Description::Choice(inner) => flattened.extend(inner),
+                alternative => flattened.push(alternative),
+            }
+        }
+        if flattened.len() == 1 {
+            flattened.pop().expect("one element")
+        } else {
+            Description::Choice(flattened)
+        }
+    }
+
+    pub fn repetition(inner: Description, min: usize, max: Option<usize>) -> Self {
+        Description::Repetition { inner: Box::new(inner), min, max }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Production {
+    pub name: String,
+    pub description: Description,
+}
+
+/// The named productions of a parser together with the description of the parser itself.
+///
+/// Productions are created by `named` parsers. A recursive grammar can only be described if
+/// every cycle passes through a `named` parser, otherwise describing it never terminates.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct Grammar {
+    start: Option<Description>,
+    productions: Vec<Production>,
+}
+
+impl Grammar {
+    pub fn of<P: Parser + ?Sized>(parser: &P) -> Self {
+        let mut grammar = Grammar::default();
+        let start = parser.describe(&mut grammar);
+        grammar.start = Some(start);
+        grammar
+    }
+
+    pub fn start(&self) -> &Description {
+        self.start.as_ref().unwrap_or(&Description::Empty)
+    }
+
+    pub fn productions(&self) -> &[Production] {
+        &self.productions
+    }
+
+    /// Used by named parsers. Returns `false` if the rule is already known, either because it
+    /// was described before or because it is being described right now.
+    pub fn reserve(&mut self, name: &str) -> bool {
+        if self.productions.iter().any(|production| production.name == name) {
+            return false;
+        }
+        self.productions.push(Production { name: String::from(name), description: Description::Empty });
+        true
+    }
+
+    pub fn define(&mut self, name: &str, description: Description) {
+        if let Some(production) = self.productions.iter_mut().find(|production| production.name == name) {
+            production.description = description;
+        }
+    }
+
+    /// All productions as ISO 14977 style EBNF. The start description gets its own `start`
+    /// production unless it is a reference to a named rule.
+    pub fn to_ebnf(&self) -> String {
+        let mut ebnf = String::new();
+        if !matches!(self.start(), Description::Rule(_)) {
+            writeln!(ebnf, "start = {} ;", ebnf_expression(self.start(), false)).expect("write to string");
+        }
+        for production in &self.productions {
+            writeln!(ebnf, "{} = {} ;", production.name, ebnf_expression(&production.description, false))
+                .expect("write to string");
+        }
+        ebnf
+    }
+
+    /// All productions as railroad diagrams in the Graphviz DOT language, one cluster per
+    /// production.
+    pub fn to_dot(&self) -> String {
+        let mut dot = DotWriter::default();
+        dot.line("digraph grammar {");
+        dot.line("    rankdir=LR;");
+        dot.line("    node [fontname=\"monospace\"];");
+        if !matches!(self.start(), Description::Rule(_)) {
+            dot.production("start", self.start());
+        }
+        for production in &self.productions {
+            dot.production(&production.name, &production.description);
+        }
+        dot.line("}");
+        dot.output
+    }
+}
+
+fn ebnf_expression(description: &Description, nested: bool) -> String {
+    let parenthesize = |expression: String| if nested { format!("( {} )", expression) } else { expression };
+    match description {
+        Description::Sequence(parts) => parenthesize(parts.iter()
+            .map(|part| ebnf_expression(part, true))
+            .collect::<Vec<_>>()
+            .join(" , ")),
+        Description::Choice(alternatives) => parenthesize(alternatives.iter()
+            .map(|alternative| ebnf_expression(alternative, true))
+            .collect::<Vec<_>>()
+            .join(" | ")),
+        Description::Repetition { inner, min, max } => {
+            let inner = ebnf_expression(inner, false);
+            let mut parts = vec![inner.clone(); *min];
+            match max {
+                None => parts.push(format!("{{ {} }}", inner)),
+                Some(max) => parts.extend(vec![format!("[ {} ]", inner); max.saturating_sub(*min)]),
+            }
+            if parts.len() == 1 {
+                parts.pop().expect("one element")
+            } else {
+                parenthesize(parts.join(" , "))
+            }
+        }
+        Description::Token(token) => {
+            let parts = ebnf_terminals(token);
+            if parts.len() == 1 { parts.join("") } else { parenthesize(parts.join(" , ")) }
+        }
+        Description::Terminal(terminal) => format!("? {} ?", terminal),
+        Description::Rule(rule) => rule.clone(),
+        Description::Empty => String::from("\"\""),
+        Description::Opaque => String::from("? opaque ?"),
+    }
+}
+
+/// EBNF terminals can't escape anything, so a token is split into terminals quoted with
+/// whichever quote they don't contain, and control characters become special sequences.
+fn ebnf_terminals(token: &str) -> Vec<String> {
+    fn quote(run: &str) -> String {
+        if run.contains('"') {
+            format!("'{}'", run)
+        } else {
+            format!("\"{}\"", run)
+        }
+    }
+
+    let mut parts = vec![];
+    let mut run = String::new();
+    for c in token.chars() {
+        if c.is_control() {
+            if !run.is_empty() {
+                parts.push(quote(&run));
+                run.clear();
+            }
+            parts.push(format!("? U+{:04X} ?", c as u32));
+            continue;
+        }
+        let conflict = match c {
+            '"' => run.contains('\''),
+            '\'' => run.contains('"'),
+            _ => false,
+        };
+        if conflict {
+            parts.push(quote(&run));
+            run.clear();
+        }
+        run.push(c);
+    }
+    if !run.is_empty() || parts.is_empty() {
+        parts.push(quote(&run));
+    }
+    parts
+}
+
+#[derive(Default)]
+struct DotWriter {
+    output: String,
+    next_node: usize,
+}
+
+impl DotWriter {
+    fn line(&mut self, line: &str) {
+        self.output.push_str(line);
+        self.output.push('\n');
+    }
+
+    fn node(&mut self, attributes: &str) -> usize {
+        let id = self.next_node;
+        self.next_node += 1;
+        self.line(&format!("        n{} [{}];", id, attributes));
+        id
+    }
+
+    fn point(&mut self) -> usize {
+        self.node("shape=point, width=0.05")
+    }
+
+    fn edge(&mut self, from: usize, to: usize) {
+        self.line(&format!("        n{} -> n{};", from, to));
+    }
+
+    fn back_edge(&mut self, from: usize, to: usize) {
+        self.line(&format!("        n{} -> n{} [style=dashed, constraint=false];", from, to));
+    }
+
+    fn production(&mut self, name: &str, description: &Description) {
+        let cluster = self.next_node;
+        self.line(&format!("    subgraph cluster_{} {{", cluster));
+        self.line(&format!("        label=\"{}\";", dot_escape(name)));
+        let entry = self.node("shape=circle, label=\"\", width=0.15");
+        let exit = self.description(description, entry);
+        let end = self.node("shape=doublecircle, label=\"\", width=0.1");
+        self.edge(exit, end);
+        self.line("    }");
+    }
+
+    fn description(&mut self, description: &Description, entry: usize) -> usize {
+        match description {
+            Description::Sequence(parts) => parts.iter()
+                .fold(entry, |entry, part| self.description(part, entry)),
+            Description::Choice(alternatives) => {
+                let join = self.point();
+                for alternative in alternatives {
+                    let exit = self.description(alternative, entry);
+                    self.edge(exit, join);
+                }
+                join
+            }
+            Description::Repetition { inner, min, max } => {
+                let mut exit = entry;
+                for _ in 0..*min {
+                    exit = self.description(inner, exit);
+                }
+                match max {
+                    None => {
+                        let loop_head = self.point();
+                        self.edge(exit, loop_head);
+                        let loop_exit = self.description(inner, loop_head);
+                        self.back_edge(loop_exit, loop_head);
+                        loop_head
+                    }
+                    Some(max) => {
+                        for _ in *min..*max {
+                            let join = self.point();
+                            let optional_exit = self.description(inner, exit);
+                            self.edge(optional_exit, join);
+                            self.edge(exit, join);
+                            exit = join;
+                        }
+                        exit
+                    }
+                }
+            }
+            Description::Token(token) => {
+                let node = self.node(&format!("shape=box, style=rounded, label=\"{}\"", dot_escape(token)));
+                self.edge(entry, node);
+                node
+            }
+            Description::Terminal(terminal) => {
+                let node = self.node(&format!("shape=box, style=\"rounded,dashed\", label=\"{}\"", dot_escape(terminal)));
+                self.edge(entry, node);
+                node
+            }
+            Description::Rule(rule) => {
+                let node = self.node(&format!("shape=box, label=\"{}\"", dot_escape(rule)));
+                self.edge(entry, node);
+                node
+            }
+            Description::Empty => entry,
+            Description::Opaque => {
+                let node = self.node("shape=box, style=dotted, label=\"?\"");
+                self.edge(entry, node);
+                node
+            }
+        }
+    }
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::adapter::BoxedParser;
+    use crate::error::ParseError;
+    use crate::grammar::{Description, Grammar};
+    use crate::parser::{lazy, named, Parser};
+    use crate::text::location::Located;
+    use crate::text::text_parser::{integer, TextParser, TextState, token, whitespace};
+
+    #[test]
+    fn describes_sequences_and_choices() {
+        let sum = (integer(), whitespace(), token("+").or(token("-")), whitespace(), integer());
+        assert_eq!(Description::Sequence(vec![
+            Description::Terminal(String::from("integer")),
+            Description::repetition(Description::Terminal(String::from("whitespace")), 0, None),
+            Description::Choice(vec![Description::Token(String::from("+")), Description::Token(String::from("-"))]),
+            Description::repetition(Description::Terminal(String::from("whitespace")), 0, None),
+            Description::Terminal(String::from("integer")),
+        ]), Grammar::of(&sum).start().clone());
+    }
+
+    #[test]
+    fn recursive_rules_become_productions() {
+        fn nesting<'a>() -> BoxedParser<'a, usize, TextState, Located<ParseError>> {
+            named("nesting", token("(")
+                .map2(lazy(nesting).many(), |_, children| 1 + children.into_iter().max().unwrap_or(0))
+                .ignore(token(")")))
+                .boxed()
+        }
+
+        let list = named("list", nesting().many().ignore(token(";").optional()));
+        assert_eq!(Ok(vec![2, 1]), list.pars("(()())();"));
+
+        let grammar = Grammar::of(&list);
+        assert_eq!("list = { nesting } , [ \";\" ] ;\nnesting = \"(\" , { nesting } , \")\" ;\n", grammar.to_ebnf());
+
+        let dot = grammar.to_dot();
+        assert!(dot.starts_with("digraph grammar {"));
+        assert!(dot.contains("label=\"list\""));
+        assert!(dot.contains("label=\"nesting\""));
+        assert!(dot.contains("style=dashed"));
+    }
+
+    #[test]
+    fn tokens_become_valid_terminals() {
+        let quotes = named("quotes", token("'\"'").ignore(token("a\nb")));
+        assert_eq!(r#"quotes = ( "'" , '"' , "'" ) , ( "a" , ? U+000A ? , "b" ) ;"#.to_owned() + "\n", Grammar::of(&quotes).to_ebnf());
+    }
+}
@@ -1,6 +1,16 @@
-mod parser;
-mod adapter;
-mod text;
+pub mod parser;
+pub mod adapter;
+pub mod bytes;
+pub mod context;
+pub mod cst;
+pub mod error;
+pub mod formats;
+pub mod grammar;
+pub mod limits;
+pub mod sequence;
+pub mod text;
+pub mod trace;
+pub mod trampoline;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,214 @@
+use std::cell::{Cell, RefCell};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::text::location::{Located, Location};
+
+/// The limit that stopped a parse.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LimitExceeded {
+    Steps(u64),
+    Depth(usize),
+    InputLength(usize),
+    Cancelled,
+}
+
+impl Display for LimitExceeded {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LimitExceeded::Steps(max) => write!(f, "parsing took more than {} steps", max),
+            LimitExceeded::Depth(max) => write!(f, "rules are nested deeper than {}", max),
+            LimitExceeded::InputLength(max) => write!(f, "input is longer than {} bytes", max),
+            LimitExceeded::Cancelled => write!(f, "parsing was cancelled"),
+        }
+    }
+}
+
+impl Error for LimitExceeded {}
+
+/// A flag to stop a running parse from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct Cancellation {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Cancellation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Bounds on the work a parse of untrusted input may do.
+///
+/// A step is one consumed character, so backtracking over the same input counts again. The depth
+/// is the nesting of `named` rules.
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    max_steps: Option<u64>,
+    max_depth: Option<usize>,
+    max_input_length: Option<usize>,
+    cancellation: Option<Cancellation>,
+}
+
+impl Limits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_steps(self, max_steps: u64) -> Self {
+        Self { max_steps: Some(max_steps), ..self }
+    }
+
+    pub fn with_max_depth(self, max_depth: usize) -> Self {
+        Self { max_depth: Some(max_depth), ..self }
+    }
+
+    pub fn with_max_input_length(self, max_input_length: usize) -> Self {
+        Self { max_input_length: Some(max_input_length), ..self }
+    }
+
+    pub fn with_cancellation(self, cancellation: Cancellation) -> Self {
+        Self { cancellation: Some(cancellation), ..self }
+    }
+}
+
+/// The limits of one parse together with the work done so far, shared by all clones of a state.
+///
+/// Once a limit is exceeded the state pretends to be at the end of the input, so every parser
+/// fails or stops soon after.
+#[derive(Debug)]
+pub(crate) struct Budget {
+    limits: Limits,
+    steps: Cell<u64>,
+    exceeded: RefCell<Option<Located<LimitExceeded>>>,
+}
+
+impl Budget {
+    pub(crate) fn new(limits: Limits, input_length: usize) -> Self {
+        let budget = Self { limits, steps: Cell::new(0), exceeded: RefCell::new(None) };
+        if let Some(max) = budget.limits.max_input_length {
+            if input_length > max {
+                budget.exceed(&Location::start(), LimitExceeded::InputLength(max));
+            }
+        }
+        budget
+    }
+
+    /// Whether parsing may go on at `location`.
+    pub(crate) fn check(&self, location: &Location) -> bool {
+        if self.exceeded.borrow().is_some() {
+            return false;
+        }
+        if self.limits.cancellation.as_ref().is_some_and(Cancellation::is_cancelled) {
+            self.exceed(location, LimitExceeded::Cancelled);
+            return false;
+        }
+        true
+    }
+
+    /// Counts one consumed character. Returns `false` if the step is not allowed anymore.
+    pub(crate) fn step(&self, location: &Location) -> bool {
+        let steps = self.steps.get() + 1;
+        self.steps.set(steps);
+        match self.limits.max_steps {
+            Some(max) if steps > max => {
+                self.exceed(location, LimitExceeded::Steps(max));
+                false
+            }
+            _ => true
+        }
+    }
+
+    pub(crate) fn check_depth(&self, depth: usize, location: &Location) {
+        if let Some(max) = self.limits.max_depth {
+            if depth > max {
+                self.exceed(location, LimitExceeded::Depth(max));
+            }
+        }
+    }
+
+    pub(crate) fn exceeded(&self) -> Option<Located<LimitExceeded>> {
+        self.exceeded.borrow().clone()
+    }
+
+    fn exceed(&self, location: &Location, limit: LimitExceeded) {
+        let mut exceeded = self.exceeded.borrow_mut();
+        if exceeded.is_none() {
+            *exceeded = Some(location.clone().locate(location.clone(), limit));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use crate::adapter::BoxedParser;
+    use crate::error::ParseError;
+    use crate::limits::{Cancellation, LimitExceeded, Limits};
+    use crate::parser::{lazy, named, Parser};
+    use crate::text::location::{Located, Location};
+    use crate::text::text_parser::{TextParser, TextState, token};
+
+    fn nesting<'a>() -> BoxedParser<'a, usize, TextState, Located<ParseError>> {
+        named("nesting", token("(")
+            .map2(lazy(nesting).many(), |_, children| 1 + children.into_iter().max().unwrap_or(0))
+            .ignore(token(")")))
+            .boxed()
+    }
+
+    fn limit_error<T>(offset: usize, limit: LimitExceeded) -> Result<T, Located<ParseError>> {
+        let location = Location::new(offset, offset + 1, 1);
+        Err(location.clone().locate(location, ParseError::LimitExceeded(limit)))
+    }
+
+    #[test]
+    fn within_limits() {
+        let limits = Limits::new().with_max_steps(100).with_max_depth(10).with_max_input_length(100);
+        assert_eq!(Ok(3), nesting().pars_limited("((()))", limits));
+    }
+
+    #[test]
+    fn too_deep() {
+        let input = format!("{}{}", "(".repeat(50), ")".repeat(50));
+        assert_eq!(limit_error(10, LimitExceeded::Depth(10)), nesting().pars_limited(input, Limits::new().with_max_depth(10)));
+    }
+
+    #[test]
+    fn too_many_steps() {
+        let limits = Limits::new().with_max_steps(5);
+        assert_eq!(limit_error(4, LimitExceeded::Steps(5)), nesting().pars_limited("((()))", limits));
+    }
+
+    #[test]
+    fn input_too_long() {
+        let limits = Limits::new().with_max_input_length(4);
+        assert_eq!(limit_error(0, LimitExceeded::InputLength(4)), nesting().pars_limited("((()))", limits));
+    }
+
+    #[test]
+    fn cancelled_from_other_thread() {
+        let cancellation = Cancellation::new();
+        let handle = cancellation.clone();
+        thread::spawn(move || handle.cancel()).join().expect("cancelling thread finished");
+        let limits = Limits::new().with_cancellation(cancellation);
+        assert_eq!(limit_error(0, LimitExceeded::Cancelled), nesting().pars_limited("()", limits));
+    }
+
+    #[test]
+    fn limits_survive_swallowed_errors() {
+        let limits = Limits::new().with_max_steps(3);
+        let parser = nesting().many();
+        assert_eq!(limit_error(2, LimitExceeded::Steps(3)), parser.pars_limited("()()()", limits));
+    }
+}
@@ -1,6 +1,10 @@
 use std::marker::PhantomData;
+use std::rc::Rc;
 
-use crate::adapter::{FlatMap, Flatten, Map, Map2};
+use crate::adapter::{BoxedParser, ErrInto, FlatMap, Flatten, FromFn, Ignore, Keep, Lazy, Map, Map2, Named, Optional, Or, Repeat};
+use crate::error::{IntoError, JoinError, Merge};
+use crate::grammar::{Description, Grammar};
+use crate::trampoline::{Continuation, StackParser, Step};
 
 pub trait Parser {
     type Value;
@@ -9,8 +13,15 @@ pub trait Parser {
 
     fn do_pars(&self, state: Self::State) -> Result<(Self::State, Self::Value), Self::Error>;
 
-    fn flat_map<P: Parser<State=Self::State, Error=Self::Error>, F>(self, f: F) -> FlatMap<Self, F>
+    /// The structure of this parser, used to export grammars. Parsers that can't tell what they
+    /// accept are `Description::Opaque`.
+    fn describe(&self, _grammar: &mut Grammar) -> Description {
+        Description::Opaque
+    }
+
+    fn flat_map<P: Parser<State=Self::State>, F>(self, f: F) -> FlatMap<Self, F>
         where F: Fn(Self::Value) -> P,
+              Self::Error: JoinError<P::Error>,
               Self: Sized
     {
         FlatMap::new(self, f)
@@ -25,33 +36,179 @@ pub trait Parser {
 
     fn map2<T2, P, F>(self, parser: P, f: F) -> Map2<Self, P, F>
         where F: Fn(Self::Value, P::Value) -> T2,
-              P: Parser<State=Self::State, Error=Self::Error>,
+              P: Parser<State=Self::State>,
+              Self::Error: JoinError<P::Error>,
               Self: Sized
     {
         Map2::new(self, parser, f)
     }
 
-    fn keep<T, P, F>(self, arg_parser: P) -> Map2<Self, P, fn(Self::Value, <P as Parser>::Value) -> T>
-        where P: Parser<State=Self::State, Error=Self::Error>,
-            F: Fn(P::Value) -> T,
-            Self: Parser<Value=F> + Sized
+    fn keep<T, P, F>(self, arg_parser: P) -> Keep<Self, P, T>
+        where P: Parser<State=Self::State>,
+              Self::Error: JoinError<P::Error>,
+              F: Fn(P::Value) -> T,
+              Self: Parser<Value=F> + Sized
     {
         self.map2(arg_parser, |func: Self::Value, arg: P::Value| func(arg))
     }
 
-    fn ignore<P>(self, ignore_parser: P) -> Map2<Self, P, fn(Self::Value, <P as Parser>::Value) ->Self::Value>
-        where P: Parser<State=Self::State, Error=Self::Error>,
+    fn ignore<P>(self, ignore_parser: P) -> Ignore<Self, P>
+        where P: Parser<State=Self::State>,
+              Self::Error: JoinError<P::Error>,
               Self: Sized
     {
         self.map2(ignore_parser, |value: Self::Value, _: P::Value| value)
     }
 
     fn flatten<P: Parser>(self) -> Flatten<Self>
-        where Self: Parser<Value=P, State=P::State, Error=P::Error>,
+        where Self: Parser<Value=P, State=P::State>,
+              Self::Error: JoinError<P::Error>,
               Self: Sized
     {
         Flatten::new(self)
     }
+
+    /// Tries `self` first and `other` on the same input if `self` fails. If both fail, their
+    /// errors are merged.
+    fn or<P>(self, other: P) -> Or<Self, P>
+        where P: Parser<Value=Self::Value, State=Self::State>,
+              Self::Error: JoinError<P::Error>,
+              <Self::Error as JoinError<P::Error>>::Joined: Merge,
+              Self::State: Clone,
+              Self: Sized
+    {
+        Or::new(self, other)
+    }
+
+    /// Applies this parser as often as possible. The parser must consume input whenever it
+    /// succeeds, otherwise the repetition never ends.
+    fn many(self) -> Repeat<Self>
+        where Self::State: Clone,
+              Self: Sized
+    {
+        Repeat::new(self, 0, None)
+    }
+
+    /// Like `many`, but fails if the parser does not succeed at least once.
+    fn many1(self) -> Repeat<Self>
+        where Self::State: Clone,
+              Self: Sized
+    {
+        Repeat::new(self, 1, None)
+    }
+
+    fn optional(self) -> Optional<Self>
+        where Self::State: Clone,
+              Self: Sized
+    {
+        Optional::new(self)
+    }
+
+    fn err_into<E>(self) -> ErrInto<Self, E>
+        where Self::Error: IntoError<E>,
+              Self: Sized
+    {
+        ErrInto::new(self)
+    }
+
+    /// Erases the type of this parser, e.g. to store it next to other parsers or to return it
+    /// from a function without spelling out the nested adapter types.
+    fn boxed<'a>(self) -> BoxedParser<'a, Self::Value, Self::State, Self::Error>
+        where Self: Sized + 'a
+    {
+        BoxedParser::new(self)
+    }
+}
+
+impl<P: Parser + ?Sized> Parser for &P {
+    type Value = P::Value;
+    type State = P::State;
+    type Error = P::Error;
+
+    fn do_pars(&self, state: Self::State) -> Result<(Self::State, Self::Value), Self::Error> {
+        (**self).do_pars(state)
+    }
+
+    fn describe(&self, grammar: &mut Grammar) -> Description {
+        (**self).describe(grammar)
+    }
+}
+
+impl<P: Parser + ?Sized> Parser for Box<P> {
+    type Value = P::Value;
+    type State = P::State;
+    type Error = P::Error;
+
+    fn do_pars(&self, state: Self::State) -> Result<(Self::State, Self::Value), Self::Error> {
+        (**self).do_pars(state)
+    }
+
+    fn describe(&self, grammar: &mut Grammar) -> Description {
+        (**self).describe(grammar)
+    }
+}
+
+impl<P: Parser + ?Sized> Parser for Rc<P> {
+    type Value = P::Value;
+    type State = P::State;
+    type Error = P::Error;
+
+    fn do_pars(&self, state: Self::State) -> Result<(Self::State, Self::Value), Self::Error> {
+        (**self).do_pars(state)
+    }
+
+    fn describe(&self, grammar: &mut Grammar) -> Description {
+        (**self).describe(grammar)
+    }
+}
+
+impl<'d, P: StackParser<'d> + ?Sized> StackParser<'d> for &P {
+    fn bounce<'a>(&'a self, state: Self::State, continuation: Continuation<'a, Self>) -> Step<'a>
+        where 'd: 'a
+    {
+        (**self).bounce(state, continuation)
+    }
+}
+
+pub type ParserFn<S, V, E> = fn(S) -> Result<(S, V), E>;
+
+impl<S, V, E> Parser for ParserFn<S, V, E> {
+    type Value = V;
+    type State = S;
+    type Error = E;
+
+    fn do_pars(&self, state: Self::State) -> Result<(Self::State, Self::Value), Self::Error> {
+        self(state)
+    }
+}
+
+impl<'d, S: 'd, V: 'd, E: 'd> StackParser<'d> for ParserFn<S, V, E> {
+    fn bounce<'a>(&'a self, state: Self::State, continuation: Continuation<'a, Self>) -> Step<'a>
+        where 'd: 'a
+    {
+        Step::more(move || continuation(self(state)))
+    }
+}
+
+/// Turns a function or closure into a parser. Recursive grammars can be written as plain
+/// functions that refer to themselves through `from_fn`.
+pub fn from_fn<F, S, V, E>(f: F) -> FromFn<F, S, V, E>
+    where F: Fn(S) -> Result<(S, V), E>
+{
+    FromFn::new(f)
+}
+
+/// Gives a parser a name. Named parsers become separate productions when the grammar is
+/// exported.
+pub fn named<P: Parser>(name: &str, parser: P) -> Named<P> {
+    Named::new(String::from(name), parser)
+}
+
+/// Builds the parser on first use. This allows recursive grammars built from functions
+/// returning parsers. The built parser has to be `'static`, so dropping a deeply nested grammar
+/// doesn't overflow the stack.
+pub fn lazy<P: Parser + 'static, F: Fn() -> P>(build: F) -> Lazy<F, P> {
+    Lazy::new(build)
 }
 
 pub struct Succeed<S, T: Clone, E> {
@@ -62,7 +219,7 @@ pub struct Succeed<S, T: Clone, E> {
 
 impl<S, T: Clone, E> Succeed<S, T, E> {
     pub fn with(value: T) -> Self {
-        Self { value, _state: PhantomData::default(), _error: PhantomData::default() }
+        Self { value, _state: PhantomData, _error: PhantomData }
     }
 }
 
@@ -74,11 +231,30 @@ impl<T: Clone, S, E> Parser for Succeed<S, T, E> {
     fn do_pars(&self, state: Self::State) -> Result<(Self::State, T), Self::Error> {
         Ok((state, self.value.clone()))
     }
+
+    fn describe(&self, _grammar: &mut Grammar) -> Description {
+        Description::Empty
+    }
+}
+
+impl<'d, T: Clone + 'd, S: 'd, E: 'd> StackParser<'d> for Succeed<S, T, E> {
+    fn bounce<'a>(&'a self, state: Self::State, continuation: Continuation<'a, Self>) -> Step<'a>
+        where 'd: 'a
+    {
+        Step::more(move || continuation(self.do_pars(state)))
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::parser::{Parser, Succeed};
+    use std::convert::Infallible;
+    use std::rc::Rc;
+
+    use crate::adapter::BoxedParser;
+    use crate::error::ParseError;
+    use crate::parser::{from_fn, Parser, ParserFn, Succeed};
+    use crate::text::location::Located;
+    use crate::text::text_parser::{integer, TextParser, TextState, token};
 
     type Succ<T> = Succeed<(), T, ()>;
 
@@ -88,5 +264,47 @@ mod test {
         let (_, final_val) = p.do_pars(()).expect("parsing did succeed");
         assert_eq!(5, final_val)
     }
-}
 
+    #[test]
+    fn map2_lifts_error_of_second_parser() {
+        use std::convert::Infallible;
+
+        let p = Succeed::<(), _, ()>::with(2).map2(Succeed::<(), _, Infallible>::with(3), |a, b| a + b);
+        let (_, final_val) = p.do_pars(()).expect("parsing did succeed");
+        assert_eq!(5, final_val)
+    }
+
+    #[test]
+    fn equal_errors_combine() {
+        let p = Succeed::<(), _, String>::with(2).map2(Succeed::<(), _, String>::with(3), |a, b| a + b)
+            .flat_map(|v| Succeed::<(), _, String>::with(v + 1));
+        assert_eq!(Ok(((), 6)), p.do_pars(()));
+    }
+
+    #[test]
+    fn boxed_parsers_share_a_type() {
+        let parsers: Vec<BoxedParser<String, TextState, Located<ParseError>>> = vec![
+            token("a").boxed(),
+            integer().map(|i| i.to_string()).boxed(),
+            Rc::new(token("b")).boxed(),
+        ];
+        let results: Vec<_> = parsers.iter().map(|p| p.pars("42").ok()).collect();
+        assert_eq!(vec![None, Some(String::from("42")), None], results);
+    }
+
+    #[test]
+    fn recursive_function_parser() {
+        fn nesting(state: TextState) -> Result<(TextState, usize), Located<ParseError>> {
+            token("(")
+                .map2(from_fn(nesting), |_, depth| depth + 1)
+                .ignore(token(")"))
+                .or(Succeed::<_, _, Infallible>::with(0))
+                .do_pars(state)
+        }
+
+        let parser = nesting as ParserFn<TextState, usize, Located<ParseError>>;
+        assert_eq!(Ok(3), parser.pars("((()))"));
+        assert_eq!(Ok(2), token("[").map2(&parser, |_, depth| depth).pars("[(())"));
+        assert_eq!(Ok(0), Box::new(parser).pars(""));
+    }
+}
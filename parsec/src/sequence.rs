@@ -0,0 +1,266 @@
+use crate::error::JoinError;
+use crate::grammar::{Description, Grammar};
+use crate::parser::Parser;
+use crate::trampoline::{Continuation, resume, StackParser, Step};
+
+/// The error of a tuple of parsers: the errors of its parts joined from the right, see
+/// `JoinError`.
+macro_rules! joined_error {
+    ($last:ident) => { $last::Error };
+    ($first:ident $(, $rest:ident)+) => { <$first::Error as JoinError<joined_error!($($rest),+)>>::Joined };
+}
+
+/// The values of a tuple of parsers nested from the right, as pattern or expression.
+macro_rules! nested {
+    ($last:ident) => { $last };
+    ($first:ident $(, $rest:ident)+) => { ($first, nested!($($rest),+)) };
+}
+
+/// Runs `rest` after `first` succeeded and pairs their values, joining the errors of both.
+fn then_join<S, A, B, E1, E2, F>(first: Result<(S, A), E1>, rest: F) -> Result<(S, (A, B)), E1::Joined>
+    where E1: JoinError<E2>,
+          F: FnOnce(S) -> Result<(S, B), E2>
+{
+    let (state, a) = first.map_err(E1::join_first)?;
+    rest(state).map(|(state, b)| (state, (a, b))).map_err(E1::join_second)
+}
+
+macro_rules! pars_parts {
+    ($state:expr; $last:ident) => { $last.do_pars($state) };
+    ($state:expr; $first:ident $(, $rest:ident)+) => {
+        then_join($first.do_pars($state), |state| pars_parts!(state; $($rest),+))
+    };
+}
+
+/// Implements `Parser` for a tuple of parsers, running them one after another and collecting
+/// their values into a tuple. The `@bounds` rules collect the `JoinError` bound of every part
+/// with the parts after it.
+macro_rules! tuple_parser {
+    (@bounds [$first:ident $(, $others:ident)*] [$($bound:tt)*] $last:ident) => {
+        impl<$first: Parser $(, $others)*> Parser for ($first, $($others,)*)
+            where $($others: Parser<State=$first::State>,)*
+                  $($bound)*
+        {
+            type Value = ($first::Value, $($others::Value,)*);
+            type State = $first::State;
+            type Error = joined_error!($first $(, $others)*);
+
+            #[allow(non_snake_case)]
+            fn do_pars(&self, state: Self::State) -> Result<(Self::State, Self::Value), Self::Error> {
+                let ($first, $($others,)*) = self;
+                let (state, nested!($first $(, $others)*)) = pars_parts!(state; $first $(, $others)*)?;
+                Ok((state, ($first, $($others,)*)))
+            }
+
+            #[allow(non_snake_case)]
+            fn describe(&self, grammar: &mut Grammar) -> Description {
+                let ($first, $($others,)*) = self;
+                Description::sequence(vec![$first.describe(grammar), $($others.describe(grammar)),*])
+            }
+        }
+    };
+    (@bounds [$($all:ident),+] [$($bound:tt)*] $part:ident, $($rest:ident),+) => {
+        tuple_parser!(@bounds [$($all),+] [$($bound)* $part::Error: JoinError<joined_error!($($rest),+)>,] $($rest),+);
+    };
+    ($($part:ident),+) => {
+        tuple_parser!(@bounds [$($part),+] [] $($part),+);
+    };
+}
+
+type Then<'a, S, V, E> = Box<dyn FnOnce(Result<(S, V), E>) -> Step<'a> + 'a>;
+
+/// `then_join` on the trampoline: `first` and `rest` run their parser with the continuation they
+/// are given.
+fn bounce_then_join<'a, S: 'a, A: 'a, B: 'a, E1: JoinError<E2> + 'a, E2: 'a, F, R>(first: F, rest: R, continuation: Then<'a, S, (A, B), E1::Joined>) -> Step<'a>
+    where F: FnOnce(Then<'a, S, A, E1>) -> Step<'a>,
+          R: FnOnce(S, Then<'a, S, B, E2>) -> Step<'a> + 'a
+{
+    first(Box::new(move |result| match result {
+        Err(error) => resume(continuation, Err(error.join_first())),
+        Ok((state, a)) => rest(state, Box::new(move |result| {
+            resume(continuation, result.map(|(state, b)| (state, (a, b))).map_err(E1::join_second))
+        })),
+    }))
+}
+
+macro_rules! bounce_parts {
+    ($state:expr, $continuation:expr; $last:ident) => { $last.bounce($state, $continuation) };
+    ($state:expr, $continuation:expr; $first:ident $(, $rest:ident)+) => {
+        bounce_then_join(move |continuation| $first.bounce($state, continuation),
+                         move |state, continuation| bounce_parts!(state, continuation; $($rest),+),
+                         $continuation)
+    };
+}
+
+/// Runs a tuple of parsers on the trampoline, each one in the continuation of the one before
+/// it.
+macro_rules! tuple_stack_parser {
+    (@bounds [$first:ident $(, $others:ident)*] [$($bound:tt)*] $last:ident) => {
+        impl<'d, $first $(, $others)*> StackParser<'d> for ($first, $($others,)*)
+            where $first: StackParser<'d>,
+                  $first::State: 'd,
+                  $first::Value: 'd,
+                  $first::Error: 'd,
+                  $($others: StackParser<'d, State=$first::State>,
+                  $others::Value: 'd,
+                  $others::Error: 'd,)*
+                  $($bound)*
+        {
+            #[allow(non_snake_case)]
+            fn bounce<'a>(&'a self, state: Self::State, continuation: Continuation<'a, Self>) -> Step<'a>
+                where 'd: 'a
+            {
+                let ($first, $($others,)*) = self;
+                bounce_parts!(state, Box::new(move |result| {
+                    resume(continuation, result.map(|(state, nested!($first $(, $others)*))| (state, ($first, $($others,)*))))
+                }); $first $(, $others)*)
+            }
+        }
+    };
+    (@bounds [$($all:ident),+] [$($bound:tt)*] $part:ident, $($rest:ident),+) => {
+        tuple_stack_parser!(@bounds [$($all),+] [$($bound)* $part::Error: JoinError<joined_error!($($rest),+)>,] $($rest),+);
+    };
+    ($($part:ident),+) => {
+        tuple_stack_parser!(@bounds [$($part),+] [] $($part),+);
+    };
+}
+
+tuple_parser!(P1);
+tuple_parser!(P1, P2);
+tuple_parser!(P1, P2, P3);
+tuple_parser!(P1, P2, P3, P4);
+tuple_parser!(P1, P2, P3, P4, P5);
+tuple_parser!(P1, P2, P3, P4, P5, P6);
+tuple_parser!(P1, P2, P3, P4, P5, P6, P7);
+tuple_parser!(P1, P2, P3, P4, P5, P6, P7, P8);
+tuple_parser!(P1, P2, P3, P4, P5, P6, P7, P8, P9);
+tuple_parser!(P1, P2, P3, P4, P5, P6, P7, P8, P9, P10);
+tuple_parser!(P1, P2, P3, P4, P5, P6, P7, P8, P9, P10, P11);
+tuple_parser!(P1, P2, P3, P4, P5, P6, P7, P8, P9, P10, P11, P12);
+
+tuple_stack_parser!(P1);
+tuple_stack_parser!(P1, P2);
+tuple_stack_parser!(P1, P2, P3);
+tuple_stack_parser!(P1, P2, P3, P4);
+tuple_stack_parser!(P1, P2, P3, P4, P5);
+tuple_stack_parser!(P1, P2, P3, P4, P5, P6);
+tuple_stack_parser!(P1, P2, P3, P4, P5, P6, P7);
+tuple_stack_parser!(P1, P2, P3, P4, P5, P6, P7, P8);
+tuple_stack_parser!(P1, P2, P3, P4, P5, P6, P7, P8, P9);
+tuple_stack_parser!(P1, P2, P3, P4, P5, P6, P7, P8, P9, P10);
+tuple_stack_parser!(P1, P2, P3, P4, P5, P6, P7, P8, P9, P10, P11);
+tuple_stack_parser!(P1, P2, P3, P4, P5, P6, P7, P8, P9, P10, P11, P12);
+
+/// Sequences up to twelve parsers and builds a struct from the kept values.
+///
+/// Like Elm's parser pipelines, `|= field: parser` keeps the value of `parser` as `field` and
+/// `|. parser` runs `parser` but discards its value.
+///
+/// ```
+/// use parsec::pipeline;
+/// use parsec::text::text_parser::{integer, token, whitespace, TextParser};
+///
+/// #[derive(Debug, PartialEq)]
+/// struct Add {
+///     lhs: i64,
+///     rhs: i64,
+/// }
+///
+/// let add = pipeline!(Add {
+///     |= lhs: integer(),
+///     |. whitespace(),
+///     |. token("+"),
+///     |. whitespace(),
+///     |= rhs: integer(),
+/// });
+/// assert_eq!(Ok(Add { lhs: 2, rhs: 4 }), add.pars("2 + 4"));
+/// ```
+#[macro_export]
+macro_rules! pipeline {
+    (@munch [$($ctor:ident)::+]; [$($field:ident),*]; [$($pattern:tt),*]; [$($parser:expr),*];) => {
+        $crate::parser::Parser::map(($($parser,)*), |($($pattern,)*)| $($ctor)::+ { $($field),* })
+    };
+    (@munch [$($ctor:ident)::+]; [$($field:ident),*]; [$($pattern:tt),*]; [$($parser:expr),*];
+        |= $name:ident : $next:expr $(, $($rest:tt)*)?) => {
+        $crate::pipeline!(@munch [$($ctor)::+]; [$($field,)* $name]; [$($pattern,)* $name]; [$($parser,)* $next]; $($($rest)*)?)
+    };
+    (@munch [$($ctor:ident)::+]; [$($field:ident),*]; [$($pattern:tt),*]; [$($parser:expr),*];
+        |. $next:expr $(, $($rest:tt)*)?) => {
+        $crate::pipeline!(@munch [$($ctor)::+]; [$($field),*]; [$($pattern,)* _]; [$($parser,)* $next]; $($($rest)*)?)
+    };
+    ($($ctor:ident)::+ { $($parts:tt)* }) => {
+        $crate::pipeline!(@munch [$($ctor)::+]; []; []; []; $($parts)*)
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use crate::text::location::Location;
+    use crate::parser::Parser;
+    use crate::text::text_parser::{float, integer, TextParser, token, whitespace};
+    use crate::error::ParseError;
+
+    #[test]
+    fn tuples_yield_tuples() {
+        let pair = (integer(), token(","), float());
+        assert_eq!(Ok((1, String::from(","), 2.5)), pair.pars("1,2.5"));
+    }
+
+    #[test]
+    fn pipeline_builds_struct() {
+        #[derive(Debug, PartialEq)]
+        struct Point {
+            x: i64,
+            y: i64,
+            z: i64,
+        }
+
+        let point = pipeline!(Point {
+            |. token("("),
+            |= x: integer(),
+            |. token(","),
+            |. whitespace(),
+            |= y: integer(),
+            |. token(","),
+            |. whitespace(),
+            |= z: integer(),
+            |. token(")"),
+        });
+        assert_eq!(Ok(Point { x: 1, y: 2, z: 3 }), point.pars("(1, 2, 3)"));
+
+        let loc = Location::new(4, 5, 1);
+        assert_eq!(Err(loc.clone().locate(loc, ParseError::expected_rule("integer"))), point.pars("(1, x, 3)"));
+    }
+
+    #[test]
+    fn pipeline_with_single_part() {
+        #[derive(Debug, PartialEq)]
+        struct One {
+            a: i64,
+        }
+
+        let one = pipeline!(One { |= a: integer() });
+        assert_eq!(Ok(One { a: 7 }), one.pars("7"));
+        assert_eq!(Ok(One { a: 7 }), one.pars_stack_safe("7"));
+    }
+
+    #[test]
+    fn sequences_may_start_with_infallible_parsers() {
+        #[derive(Debug, PartialEq)]
+        struct Padded {
+            a: i64,
+        }
+
+        let number = whitespace().map2(integer(), |_, number| number);
+        assert_eq!(Ok(5), number.pars(" 5"));
+        let pair = (whitespace(), integer());
+        assert_eq!(Ok((String::from("  "), 5)), pair.pars("  5"));
+        assert_eq!(Ok((String::from("  "), 5)), pair.pars_stack_safe("  5"));
+
+        let padded = pipeline!(Padded { |. whitespace(), |= a: integer() });
+        assert_eq!(Ok(Padded { a: 7 }), padded.pars(" 7"));
+        assert_eq!(Ok(Padded { a: 7 }), padded.pars_stack_safe(" 7"));
+        let loc = Location::new(1, 2, 1);
+        assert_eq!(Err(loc.clone().locate(loc, ParseError::expected_rule("integer"))), padded.pars(" x"));
+    }
+}
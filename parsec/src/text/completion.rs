@@ -0,0 +1,146 @@
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::rc::Rc;
+
+use crate::error::Expectation;
+use crate::parser::Parser;
+use crate::text::location::Location;
+use crate::text::text_parser::TextState;
+use crate::trace::{Trace, TraceNode};
+
+/// Something that could come next at the cursor of `TextParser::completions`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Completion {
+    pub expectation: Expectation,
+    /// Where the completion starts. The input from here to the cursor is `typed`.
+    pub start: Location,
+    /// The part of a token that is already typed, empty for completions that start at the cursor.
+    pub typed: String,
+    /// How many alternatives expected the completion.
+    pub count: usize,
+}
+
+/// The expectations of the parsers that ran into the end of the input. Shared by all clones of
+/// a state.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Expectations {
+    expected: Rc<RefCell<Vec<(Expectation, Location)>>>,
+}
+
+impl Expectations {
+    pub(crate) fn record(&self, expectation: Expectation, start: Location) {
+        self.expected.borrow_mut().push((expectation, start));
+    }
+}
+
+/// Runs `parser` over the input up to `cursor` and collects the tokens that were cut off by the
+/// cursor, the rules of `Number` parsers and the named rules that failed at the cursor.
+///
+/// Completions that continue a partly typed token come first, then tokens before rules and
+/// finally the ones most alternatives expected. A cursor inside a char has no completions.
+pub(crate) fn complete<P>(parser: &P, input: &str, cursor: &Location) -> Vec<Completion>
+    where P: Parser<State=TextState> + ?Sized
+{
+    let end = cursor.byte_offset().min(input.len());
+    if !input.is_char_boundary(end) {
+        return vec![];
+    }
+    let prefix = &input[..end];
+    let expectations = Expectations::default();
+    let trace = Trace::new();
+    let state = TextState::new(prefix).with_expectations(expectations.clone()).with_trace(trace.clone());
+    // Only the expectations gathered on the way matter, not whether the prefix parses.
+    let _ = parser.do_pars(state);
+
+    let mut expected = expectations.expected.take();
+    collect_failed_rules(&trace.roots(), end, &mut expected);
+    let mut completions: Vec<Completion> = vec![];
+    for (expectation, start) in expected {
+        match completions.iter_mut().find(|completion| completion.expectation == expectation && completion.start == start) {
+            Some(completion) => completion.count += 1,
+            None => {
+                let typed = String::from(&prefix[start.byte_offset()..]);
+                completions.push(Completion { expectation, start, typed, count: 1 });
+            }
+        }
+    }
+    completions.sort_by_key(|completion| (
+        Reverse(completion.typed.len()),
+        matches!(completion.expectation, Expectation::Rule(_)),
+        Reverse(completion.count),
+        completion.expectation.clone(),
+    ));
+    completions
+}
+
+fn collect_failed_rules(nodes: &[TraceNode], cursor: usize, expected: &mut Vec<(Expectation, Location)>) {
+    for node in nodes {
+        if !node.success && node.start.byte_offset() == cursor {
+            expected.push((Expectation::Rule(node.rule.clone()), node.start.clone()));
+        }
+        collect_failed_rules(&node.children, cursor, expected);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::Expectation;
+    use crate::parser::{named, Parser};
+    use crate::text::completion::Completion;
+    use crate::text::location::Location;
+    use crate::text::text_parser::{Chop, integer, TextParser, token, whitespace};
+
+    fn expectations(completions: Vec<Completion>) -> Vec<(Expectation, String)> {
+        completions.into_iter().map(|completion| (completion.expectation, completion.typed)).collect()
+    }
+
+    #[test]
+    fn completes_tokens_and_rules_at_the_cursor() {
+        let name = named("name", Chop::while_con(char::is_alphabetic).described_as("letter"));
+        let value = named("value", integer().map(|_| ()).or(token("true").map(|_| ())).or(token("false").map(|_| ())));
+        let statement = token("let").ignore(whitespace()).ignore(name).ignore(whitespace()).ignore(token("="))
+            .ignore(whitespace()).ignore(value)
+            .or(token("print").ignore(whitespace()).ignore(token("(")).map(|_| String::new()));
+        let program = statement.ignore(token(";")).ignore(whitespace()).many();
+
+        let input = "let x = 1; let y = t";
+        let completions = program.completions(input, &Location::new(20, 21, 1));
+        assert_eq!(vec![(Expectation::Token(String::from("true")), String::from("t"))], expectations(completions));
+
+        let completions = program.completions(input, &Location::new(19, 20, 1));
+        assert_eq!(vec![
+            (Expectation::Token(String::from("false")), String::new()),
+            (Expectation::Token(String::from("true")), String::new()),
+            (Expectation::Rule(String::from("integer")), String::new()),
+            (Expectation::Rule(String::from("value")), String::new()),
+        ], expectations(completions));
+
+        let completions = program.completions(input, &Location::new(13, 14, 1));
+        assert_eq!(vec![(Expectation::Token(String::from("let")), String::from("le"))], expectations(completions));
+    }
+
+    #[test]
+    fn ranks_partly_typed_and_common_completions_first() {
+        let keyword = token("for").or(token("fn")).or(token("if"));
+        let twice = token("(").or(token("[")).or(token("("));
+        let parser = keyword.or(twice);
+
+        let completions = parser.completions("f", &Location::new(1, 2, 1));
+        assert_eq!(vec![
+            (Expectation::Token(String::from("fn")), String::from("f")),
+            (Expectation::Token(String::from("for")), String::from("f")),
+        ], expectations(completions));
+
+        let completions = parser.completions("f", &Location::new(0, 1, 1));
+        assert_eq!(2, completions[0].count);
+        assert_eq!(Expectation::Token(String::from("(")), completions[0].expectation);
+        assert_eq!(5, completions.len());
+    }
+
+    #[test]
+    fn cursor_inside_a_char_has_no_completions() {
+        let parser = token("é").or(token("è"));
+        assert_eq!(Vec::<Completion>::new(), parser.completions("é", &Location::new(1, 2, 1)));
+        assert_eq!(2, parser.completions("é", &Location::new(0, 1, 1)).len());
+    }
+}
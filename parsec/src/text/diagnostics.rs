@@ -0,0 +1,147 @@
+use std::fmt::{Display, Write};
+
+use crate::text::location::{ColumnMode, Columns, Located};
+use crate::text::source_map::SourceMap;
+use crate::trace::{location_json, push_json_string};
+
+/// Writes `errors` as JSON Lines, one object per line:
+///
+/// `{"file":"a.txt","message":"expected integer","start":{"offset":4,"line":1,"column":5},"end":{...}}`
+///
+/// `file` is `null` for locations whose source is not in `sources`. Locations parsed without
+/// position tracking are resolved through `sources`.
+pub fn json_lines<'e, T: Display + 'e>(errors: impl IntoIterator<Item=&'e Located<T>>, sources: &SourceMap) -> String {
+    let mut json = String::new();
+    for error in errors {
+        let range = error.source_range();
+        json.push_str("{\"file\":");
+        match sources.get(range.start.source()) {
+            Some(source) => push_json_string(source.name(), &mut json),
+            None => json.push_str("null"),
+        }
+        json.push_str(",\"message\":");
+        push_json_string(&error.target().to_string(), &mut json);
+        json.push_str(",\"start\":");
+        location_json(&sources.resolve(&range.start), &mut json);
+        json.push_str(",\"end\":");
+        location_json(&sources.resolve(&range.end), &mut json);
+        json.push_str("}\n");
+    }
+    json
+}
+
+/// Writes `errors` as a SARIF 2.1.0 log with a single run of the tool `tool_name`. Every error
+/// becomes a result of level `error` whose region has line/column and byte offsets.
+///
+/// SARIF only knows columns counting UTF-16 code units or code points, without expanding tabs.
+/// Columns are counted in UTF-16 code units if `sources` counts them that way and in code points
+/// otherwise. They can only be counted for locations whose source is in `sources`, the regions of
+/// other locations have lines only.
+pub fn sarif<'e, T: Display + 'e>(errors: impl IntoIterator<Item=&'e Located<T>>, sources: &SourceMap, tool_name: &str) -> String {
+    let mut json = String::new();
+    json.push_str("{\"$schema\":\"https://json.schemastore.org/sarif-2.1.0.json\",\"version\":\"2.1.0\",\"runs\":[{");
+    json.push_str("\"tool\":{\"driver\":{\"name\":");
+    push_json_string(tool_name, &mut json);
+    json.push_str("}},");
+    let columns = match sources.columns().mode() {
+        ColumnMode::Utf16 => {
+            json.push_str("\"columnKind\":\"utf16CodeUnits\",");
+            Columns::new().with_mode(ColumnMode::Utf16)
+        }
+        ColumnMode::Scalar | ColumnMode::Utf8 | ColumnMode::Grapheme => {
+            json.push_str("\"columnKind\":\"unicodeCodePoints\",");
+            Columns::new()
+        }
+    };
+    json.push_str("\"results\":[");
+    for (index, error) in errors.into_iter().enumerate() {
+        if index > 0 {
+            json.push(',');
+        }
+        sarif_result(error, sources, &columns, &mut json);
+    }
+    json.push_str("]}]}");
+    json
+}
+
+fn sarif_result<T: Display>(error: &Located<T>, sources: &SourceMap, columns: &Columns, json: &mut String) {
+    let range = error.source_range();
+    let (start, end) = (&range.start, &range.end);
+    json.push_str("{\"level\":\"error\",\"message\":{\"text\":");
+    push_json_string(&error.target().to_string(), json);
+    json.push_str("},\"locations\":[{\"physicalLocation\":{");
+    let source = sources.get(start.source());
+    if let Some(source) = source {
+        json.push_str("\"artifactLocation\":{\"uri\":");
+        push_json_string(source.name(), json);
+        json.push_str("},");
+    }
+    json.push_str("\"region\":{");
+    match source {
+        Some(source) => {
+            for (location, prefix) in [(start, "start"), (end, "end")] {
+                let (line, column) = source.lines().position(source.text(), location.byte_offset(), columns);
+                write!(json, "\"{}Line\":{},\"{}Column\":{},", prefix, line, prefix, column).expect("write to string");
+            }
+        }
+        None if start.is_tracked() => {
+            write!(json, "\"startLine\":{},\"endLine\":{},", start.row(), end.row()).expect("write to string");
+        }
+        None => {}
+    }
+    write!(json, "\"byteOffset\":{},\"byteLength\":{}}}}}}}]}}",
+           start.byte_offset(), end.byte_offset().saturating_sub(start.byte_offset())).expect("write to string");
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::ParseError;
+    use crate::parser::Parser;
+    use crate::text::diagnostics::{json_lines, sarif};
+    use crate::text::location::{ColumnMode, Columns, Location};
+    use crate::text::source_map::SourceMap;
+    use crate::text::text_parser::{integer, TextParser, token};
+
+    #[test]
+    fn writes_json_lines() {
+        let mut sources = SourceMap::new();
+        let id = sources.add("sum \"1\".txt", "1+\n+");
+        let sum = integer().ignore(token("+")).ignore(token("\n")).map2(integer(), |lhs, rhs| lhs + rhs);
+        let error = sum.pars_with(sources.state(id).expect("source")).unwrap_err();
+        let anonymous = Location::new(0, 1, 1).locate(Location::new(1, 2, 1), ParseError::custom("bad\tinput"));
+
+        assert_eq!("{\"file\":\"sum \\\"1\\\".txt\",\"message\":\"expected integer\",\
+                    \"start\":{\"offset\":3,\"line\":2,\"column\":1},\"end\":{\"offset\":3,\"line\":2,\"column\":1}}\n\
+                    {\"file\":null,\"message\":\"bad\\tinput\",\
+                    \"start\":{\"offset\":0,\"line\":1,\"column\":1},\"end\":{\"offset\":1,\"line\":1,\"column\":2}}\n",
+                   json_lines([&error, &anonymous], &sources));
+    }
+
+    #[test]
+    fn writes_sarif() {
+        let mut sources = SourceMap::new();
+        let id = sources.add("numbers.txt", "1,x");
+        let pair = integer().ignore(token(",")).map2(integer(), |a, b| (a, b));
+        let state = sources.state(id).expect("source").with_position_tracking(false);
+        let error = pair.pars_with(state).unwrap_err();
+
+        assert_eq!("{\"$schema\":\"https://json.schemastore.org/sarif-2.1.0.json\",\"version\":\"2.1.0\",\"runs\":[{\
+                    \"tool\":{\"driver\":{\"name\":\"parsec\"}},\"columnKind\":\"unicodeCodePoints\",\"results\":[{\
+                    \"level\":\"error\",\"message\":{\"text\":\"expected integer\"},\"locations\":[{\"physicalLocation\":{\
+                    \"artifactLocation\":{\"uri\":\"numbers.txt\"},\"region\":{\"startLine\":1,\"startColumn\":3,\
+                    \"endLine\":1,\"endColumn\":3,\"byteOffset\":2,\"byteLength\":0}}}]}]}]}",
+                   sarif([&error], &sources, "parsec"));
+    }
+
+    #[test]
+    fn sarif_counts_code_points() {
+        let mut sources = SourceMap::new().with_columns(Columns::new().with_mode(ColumnMode::Utf8).with_tab_width(4));
+        let id = sources.add("tab.txt", "é\tx");
+        let error = token("é\t").ignore(integer()).pars_with(sources.state(id).expect("source")).unwrap_err();
+        assert_eq!(Location::new(3, 5, 1).with_source(id), error.source_range().start);
+
+        let log = sarif([&error], &sources, "parsec");
+        assert!(log.contains("\"columnKind\":\"unicodeCodePoints\""));
+        assert!(log.contains("\"region\":{\"startLine\":1,\"startColumn\":3,\"endLine\":1,\"endColumn\":3,"));
+    }
+}
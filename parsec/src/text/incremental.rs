@@ -0,0 +1,443 @@
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::cst::{node, CstEvent, Node, SyntaxKind, SyntaxNode};
+use crate::grammar::{Description, Grammar};
+use crate::parser::Parser;
+use crate::text::location::Location;
+use crate::text::text_parser::TextState;
+use crate::trampoline::{Continuation, resume, StackParser, Step};
+
+/// Replaces the bytes in `range` of a text with `replacement`, like an editor does when the
+/// user types.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TextEdit {
+    range: Range<usize>,
+    replacement: String,
+}
+
+impl TextEdit {
+    pub fn new(range: Range<usize>, replacement: impl Into<String>) -> Self {
+        Self { range, replacement: replacement.into() }
+    }
+
+    pub fn insert(offset: usize, text: impl Into<String>) -> Self {
+        Self::new(offset..offset, text)
+    }
+
+    pub fn delete(range: Range<usize>) -> Self {
+        Self::new(range, "")
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+
+    pub fn replacement(&self) -> &str {
+        &self.replacement
+    }
+
+    /// The edited text. Panics if the range is not on char boundaries of `text`.
+    pub fn apply(&self, text: &str) -> String {
+        let mut edited = String::from(text);
+        edited.replace_range(self.range(), &self.replacement);
+        edited
+    }
+}
+
+/// A successful parse of a `reusable` node. Offsets are relative to the start of the node, the
+/// locations in the value are the ones of the parse that recorded it, which started at `start`.
+#[derive(Clone)]
+struct MemoEntry {
+    start: Location,
+    len: usize,
+    examined: usize,
+    value: Rc<dyn Any>,
+    events: Rc<[CstEvent]>,
+}
+
+/// The `reusable` nodes of one parse by kind and start offset.
+#[derive(Clone, Default)]
+pub(crate) struct MemoTable {
+    entries: HashMap<(SyntaxKind, usize), MemoEntry>,
+}
+
+impl MemoTable {
+    /// The entries that are still valid after `edit`. Nodes that examined only input before the
+    /// edit keep their offset, nodes that start after it are moved by the change in length.
+    fn after_edit(&self, edit: &TextEdit) -> Self {
+        let entries = self.entries.iter().filter_map(|(&(kind, start), entry)| {
+            if start + entry.examined <= edit.range.start {
+                Some(((kind, start), entry.clone()))
+            } else if start >= edit.range.end {
+                let moved = start - edit.range.end + edit.range.start + edit.replacement.len();
+                Some(((kind, moved), entry.clone()))
+            } else {
+                None
+            }
+        }).collect();
+        Self { entries }
+    }
+}
+
+impl Debug for MemoTable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MemoTable").field("entries", &self.entries.len()).finish()
+    }
+}
+
+/// Where a `reusable` node started, see `TextState::begin_memo`.
+pub(crate) struct MemoStart {
+    location: Location,
+    events: usize,
+    outer_examined: usize,
+}
+
+/// The memo tables of an incremental parse. Shared by all clones of its state.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Reuse {
+    previous: Rc<MemoTable>,
+    recorded: Rc<RefCell<MemoTable>>,
+    examined: Rc<Cell<usize>>,
+    reused: Rc<Cell<usize>>,
+}
+
+impl Reuse {
+    fn new(previous: MemoTable) -> Self {
+        Self { previous: Rc::new(previous), ..Self::default() }
+    }
+
+    /// Notes that the parse looked at the input up to `end`.
+    pub(crate) fn examine(&self, end: usize) {
+        self.examined.set(self.examined.get().max(end));
+    }
+
+    pub(crate) fn begin(&self, location: Location, events: usize) -> MemoStart {
+        let outer_examined = self.examined.replace(location.byte_offset());
+        MemoStart { location, events, outer_examined }
+    }
+
+    /// Records the node that started at `start` if it succeeded, and adds the input it examined
+    /// to the enclosing node.
+    pub(crate) fn finish<V: Clone + 'static>(&self, start: MemoStart, kind: SyntaxKind, success: Option<(usize, &V, Vec<CstEvent>)>) {
+        let examined = self.examined.get();
+        let offset = start.location.byte_offset();
+        if let Some((end, value, events)) = success {
+            let events = events.iter().map(|event| event.moved(offset, 0)).collect();
+            let entry = MemoEntry {
+                start: start.location,
+                len: end - offset,
+                examined: examined - offset,
+                value: Rc::new(value.clone()),
+                events,
+            };
+            self.recorded.borrow_mut().entries.insert((kind, offset), entry);
+        }
+        self.examined.set(examined.max(start.outer_examined));
+    }
+
+    /// The value, length and events of the node of `kind` at `offset` in the previous parse, if
+    /// it can be reused, together with the location the node started at in that parse.
+    pub(crate) fn reuse<V: Clone + 'static>(&self, kind: SyntaxKind, offset: usize) -> Option<(V, usize, Rc<[CstEvent]>, Location)> {
+        let entry = self.previous.entries.get(&(kind, offset))?;
+        let value = entry.value.downcast_ref::<V>()?.clone();
+        self.examine(offset + entry.examined);
+        self.reused.set(self.reused.get() + 1);
+        self.recorded.borrow_mut().entries.insert((kind, offset), entry.clone());
+        Some((value, entry.len, entry.events.clone(), entry.start.clone()))
+    }
+}
+
+/// The result of `TextParser::pars_incremental`, which can be updated after an edit with
+/// `TextParser::repars`.
+#[derive(Debug)]
+pub struct Incremental<V, E> {
+    text: Rc<String>,
+    result: Result<(V, SyntaxNode), E>,
+    memo: MemoTable,
+    reused_nodes: usize,
+}
+
+impl<V, E> Incremental<V, E> {
+    pub(crate) fn parse<P>(parser: &P, text: Rc<String>, previous: MemoTable) -> Self
+        where P: Parser<Value=V, State=TextState, Error=E> + ?Sized
+    {
+        let reuse = Reuse::new(previous);
+        let state = TextState::from_source(text.clone(), Default::default()).with_cst().with_reuse(reuse.clone());
+        let result = parser.do_pars(state).map(|(state, value)| {
+            let tree = state.syntax_tree().expect("state records a syntax tree");
+            (value, tree)
+        });
+        let memo = reuse.recorded.borrow().clone();
+        Self { text, result, memo, reused_nodes: reuse.reused.get() }
+    }
+
+    pub(crate) fn memo_after(&self, edit: &TextEdit) -> MemoTable {
+        self.memo.after_edit(edit)
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The value and syntax tree, the same as `TextParser::pars_cst` returns for the text.
+    pub fn result(&self) -> &Result<(V, SyntaxNode), E> {
+        &self.result
+    }
+
+    pub fn into_result(self) -> Result<(V, SyntaxNode), E> {
+        self.result
+    }
+
+    /// How many `reusable` nodes were taken from the previous parse instead of parsed again.
+    pub fn reused_nodes(&self) -> usize {
+        self.reused_nodes
+    }
+}
+
+/// Values of `reusable` nodes. When an incremental parse reuses a node that moved, the locations
+/// in its value are moved to where a fresh parse of the edited text would put them.
+pub trait Relocate {
+    /// Replaces every location in this value with `relocate(location)`. The default does
+    /// nothing, which is right for values without locations.
+    fn relocate(&mut self, _relocate: &mut dyn FnMut(&Location) -> Location) {}
+}
+
+macro_rules! no_locations {
+    ($($t:ty),*) => {
+        $(impl Relocate for $t {})*
+    };
+}
+
+no_locations!((), bool, char, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, String, &'static str);
+
+impl Relocate for Location {
+    fn relocate(&mut self, relocate: &mut dyn FnMut(&Location) -> Location) {
+        *self = relocate(self);
+    }
+}
+
+impl<T: Relocate> Relocate for Option<T> {
+    fn relocate(&mut self, relocate: &mut dyn FnMut(&Location) -> Location) {
+        if let Some(value) = self {
+            value.relocate(relocate);
+        }
+    }
+}
+
+impl<T: Relocate> Relocate for Vec<T> {
+    fn relocate(&mut self, relocate: &mut dyn FnMut(&Location) -> Location) {
+        self.iter_mut().for_each(|value| value.relocate(relocate));
+    }
+}
+
+impl<T: Relocate + ?Sized> Relocate for Box<T> {
+    fn relocate(&mut self, relocate: &mut dyn FnMut(&Location) -> Location) {
+        (**self).relocate(relocate);
+    }
+}
+
+macro_rules! tuple_relocate {
+    ($($t:ident),*) => {
+        impl<$($t: Relocate),*> Relocate for ($($t,)*) {
+            #[allow(non_snake_case)]
+            fn relocate(&mut self, relocate: &mut dyn FnMut(&Location) -> Location) {
+                let ($($t,)*) = self;
+                $($t.relocate(relocate);)*
+            }
+        }
+    };
+}
+
+tuple_relocate!(A, B);
+tuple_relocate!(A, B, C);
+tuple_relocate!(A, B, C, D);
+
+pub struct Reusable<P> {
+    kind: SyntaxKind,
+    node: Node<P>,
+}
+
+/// A `cst::node` that incremental parses take from the previous parse if the edit did not change
+/// the input it examined.
+///
+/// The value must only depend on that input, so it must not read the context. Locations in the
+/// value are moved with the node, see `Relocate`. A reused node also doesn't count towards
+/// limits and doesn't show up in traces. Nodes are identified by `kind` and start offset, so two
+/// reusable nodes of the same kind must not start at the same offset.
+pub fn reusable<P>(kind: SyntaxKind, parser: P) -> Reusable<P>
+    where P: Parser<State=TextState>,
+          P::Value: Clone + Relocate + 'static
+{
+    Reusable { kind, node: node(kind, parser) }
+}
+
+impl<P> Parser for Reusable<P>
+    where P: Parser<State=TextState>,
+          P::Value: Clone + Relocate + 'static
+{
+    type Value = P::Value;
+    type State = TextState;
+    type Error = P::Error;
+
+    fn do_pars(&self, mut state: Self::State) -> Result<(Self::State, Self::Value), Self::Error> {
+        if let Some(value) = state.reuse_node(self.kind) {
+            return Ok((state, value));
+        }
+        let memo = state.begin_memo();
+        finish_memo(self.kind, memo, self.node.do_pars(state))
+    }
+
+    fn describe(&self, grammar: &mut Grammar) -> Description {
+        self.node.describe(grammar)
+    }
+}
+
+impl<'d, P> StackParser<'d> for Reusable<P>
+    where P: StackParser<'d, State=TextState>,
+          P::Value: Clone + Relocate + 'static,
+          P::Error: 'd
+{
+    fn bounce<'a>(&'a self, mut state: Self::State, continuation: Continuation<'a, Self>) -> Step<'a>
+        where 'd: 'a
+    {
+        if let Some(value) = state.reuse_node(self.kind) {
+            return resume(continuation, Ok((state, value)));
+        }
+        let memo = state.begin_memo();
+        self.node.bounce(state, Box::new(move |result| {
+            resume(continuation, finish_memo(self.kind, memo, result))
+        }))
+    }
+}
+
+fn finish_memo<V: Clone + 'static, E>(kind: SyntaxKind, memo: Option<(Reuse, MemoStart)>,
+                                      result: Result<(TextState, V), E>) -> Result<(TextState, V), E> {
+    if let Some((reuse, start)) = memo {
+        let success = result.as_ref().ok().map(|(state, value)| {
+            (state.location().byte_offset(), value, state.cst_events_since(start.events))
+        });
+        reuse.finish(start, kind, success);
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use std::fmt::Debug;
+
+    use crate::adapter::BoxedParser;
+    use crate::cst::{leaf, SyntaxKind};
+    use crate::error::ParseError;
+    use crate::formats::spanned;
+    use crate::parser::{lazy, Parser};
+    use crate::text::incremental::{reusable, TextEdit};
+    use crate::text::location::Located;
+    use crate::text::text_parser::{end, integer, TextParser, TextState, token, whitespace};
+
+    const NUMBER: SyntaxKind = SyntaxKind("NUMBER");
+    const PLUS: SyntaxKind = SyntaxKind("PLUS");
+    const PAREN: SyntaxKind = SyntaxKind("PAREN");
+    const SEMICOLON: SyntaxKind = SyntaxKind("SEMICOLON");
+    const SUM: SyntaxKind = SyntaxKind("SUM");
+    const GROUP: SyntaxKind = SyntaxKind("GROUP");
+    const STATEMENT: SyntaxKind = SyntaxKind("STATEMENT");
+
+    fn sum<'a>() -> BoxedParser<'a, i64, TextState, Located<ParseError>> {
+        let group = reusable(GROUP, leaf(PAREN, token("(")).ignore(whitespace())
+            .map2(lazy(sum), |_, value| value)
+            .ignore(leaf(PAREN, token(")"))));
+        let term = leaf(NUMBER, integer()).or(group).ignore(whitespace());
+        reusable(SUM, term.map2(leaf(PLUS, token("+")).ignore(whitespace()).map2(lazy(sum), |_, rhs| rhs).optional(),
+                                |lhs, rhs| lhs + rhs.unwrap_or(0)))
+            .boxed()
+    }
+
+    fn program<'a>() -> BoxedParser<'a, Vec<i64>, TextState, Located<ParseError>> {
+        reusable(STATEMENT, sum().ignore(leaf(SEMICOLON, token(";"))).ignore(whitespace())).many()
+            .ignore(end())
+            .boxed()
+    }
+
+    /// Statements that keep where they were found.
+    fn located_program<'a>() -> BoxedParser<'a, Vec<Located<i64>>, TextState, Located<ParseError>> {
+        reusable(STATEMENT, spanned(sum()).ignore(leaf(SEMICOLON, token(";"))).ignore(whitespace())).many()
+            .ignore(end())
+            .boxed()
+    }
+
+    /// Compares `repars` with a fresh parse of the edited text and returns the number of
+    /// reused nodes.
+    fn assert_same_as_fresh_parse(text: &str, edit: &TextEdit) -> usize {
+        assert_parser_matches_fresh_parse(&program(), text, edit)
+    }
+
+    fn assert_parser_matches_fresh_parse<V: Debug + PartialEq>(parser: &BoxedParser<V, TextState, Located<ParseError>>,
+                                                                text: &str, edit: &TextEdit) -> usize {
+        let incremental = parser.repars(&parser.pars_incremental(text), edit);
+        let edited = edit.apply(text);
+        assert_eq!(edited, incremental.text());
+        match (incremental.result(), parser.pars_cst(edited.as_str())) {
+            (Ok((value, tree)), Ok((fresh_value, fresh_tree))) => {
+                assert_eq!(&fresh_value, value, "value after {:?} on {:?}", edit, text);
+                assert_eq!(fresh_tree.debug_tree(), tree.debug_tree(), "tree after {:?} on {:?}", edit, text);
+            }
+            (Err(error), Err(fresh_error)) => assert_eq!(&fresh_error, error, "error after {:?} on {:?}", edit, text),
+            (result, fresh) => panic!("{:?} on {:?} gave {:?} instead of {:?}", edit, text, result, fresh),
+        }
+        incremental.reused_nodes()
+    }
+
+    #[test]
+    fn matches_fresh_parse_after_every_small_edit() {
+        let text = "1 + (2 +3);\n(4);  5+ 67 ;\n";
+        for offset in 0..=text.len() {
+            for insertion in ["", "8", " ", "(", ")", "+", ";", "\n"] {
+                assert_same_as_fresh_parse(text, &TextEdit::insert(offset, insertion));
+            }
+            if offset < text.len() {
+                assert_same_as_fresh_parse(text, &TextEdit::delete(offset..offset + 1));
+                assert_same_as_fresh_parse(text, &TextEdit::new(offset..text.len(), "9;"));
+            }
+        }
+    }
+
+    #[test]
+    fn moves_locations_of_reused_values() {
+        let text = "1 + (2 +3);\n(4);  5+ 67 ;\n";
+        let parser = located_program();
+        for offset in 0..=text.len() {
+            for insertion in ["", "8", " ", "\n", "\n\n"] {
+                assert_parser_matches_fresh_parse(&parser, text, &TextEdit::insert(offset, insertion));
+            }
+            if offset < text.len() {
+                assert_parser_matches_fresh_parse(&parser, text, &TextEdit::delete(offset..offset + 1));
+            }
+        }
+        assert_eq!(2, assert_parser_matches_fresh_parse(&parser, "(1 + 2);\n3 + 4;\n5;\n", &TextEdit::new(0..8, "7;\n\n")));
+    }
+
+    #[test]
+    fn reuses_nodes_outside_the_edit() {
+        let text = "(1 + 2);\n3 + 4;\n5;\n";
+        // Only the last statement examined the input around the edit.
+        assert_eq!(2, assert_same_as_fresh_parse(text, &TextEdit::insert(16, "6")));
+        // Statements after an edit are moved, their locations follow the new line.
+        assert_eq!(2, assert_same_as_fresh_parse(text, &TextEdit::new(0..8, "7;\n\n")));
+
+        let parser = program();
+        let mut parsed = parser.pars_incremental("");
+        let typed = "12 + (3+4);\n5;";
+        for (offset, c) in typed.char_indices() {
+            parsed = parser.repars(&parsed, &TextEdit::insert(offset, c));
+            let fresh = parser.pars_cst(&typed[..offset + 1]);
+            assert_eq!(fresh.as_ref().map(|(value, tree)| (value, tree.debug_tree())),
+                       parsed.result().as_ref().map(|(value, tree)| (value, tree.debug_tree())));
+        }
+        assert_eq!(Some(&vec![19, 5]), parsed.result().as_ref().ok().map(|(value, _)| value));
+    }
+}
@@ -1,8 +1,88 @@
 use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
 use std::ops::{Range};
 
+use unicode_segmentation::GraphemeCursor;
+
+use crate::text::incremental::Relocate;
+use crate::text::source_map::SourceId;
+
+/// What one column of a location counts.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum ColumnMode {
+    /// Unicode scalar values, i.e. Rust `char`s.
+    #[default]
+    Scalar,
+    /// UTF-16 code units, as used by the language server protocol.
+    Utf16,
+    /// UTF-8 bytes.
+    Utf8,
+    /// Extended grapheme clusters, what a user perceives as one character.
+    Grapheme,
+}
+
+/// How columns and lines are counted. `\n`, `\r\n` and a lone `\r` all end a line. A tab
+/// moves to the next multiple of the tab width in every mode.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Columns {
+    mode: ColumnMode,
+    tab_width: usize,
+}
+
+impl Columns {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_mode(self, mode: ColumnMode) -> Self {
+        Self { mode, ..self }
+    }
+
+    pub fn with_tab_width(self, tab_width: usize) -> Self {
+        Self { tab_width: tab_width.max(1), ..self }
+    }
+
+    pub fn mode(&self) -> ColumnMode {
+        self.mode
+    }
+
+    pub fn tab_width(&self) -> usize {
+        self.tab_width
+    }
+
+    /// The column after the char `c` at `byte_offset` of `text`, starting from `column`. `None`
+    /// if `c` ends the line.
+    pub fn advance(&self, text: &str, byte_offset: usize, c: char, column: usize) -> Option<usize> {
+        match c {
+            '\n' => None,
+            '\r' if text[byte_offset + 1..].starts_with('\n') => Some(column),
+            '\r' => None,
+            '\t' => Some((column - 1) / self.tab_width * self.tab_width + self.tab_width + 1),
+            c => Some(column + match self.mode {
+                ColumnMode::Scalar => 1,
+                ColumnMode::Utf16 => c.len_utf16(),
+                ColumnMode::Utf8 => c.len_utf8(),
+                ColumnMode::Grapheme => {
+                    let mut cursor = GraphemeCursor::new(byte_offset, text.len(), true);
+                    usize::from(cursor.is_boundary(text, 0).unwrap_or(true))
+                }
+            }),
+        }
+    }
+}
+
+impl Default for Columns {
+    fn default() -> Self {
+        Self { mode: ColumnMode::Scalar, tab_width: 1 }
+    }
+}
+
+/// A position in a source. Row and column start at 1, they are 0 if the parse state did not
+/// track them, see `SourceMap::resolve`.
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Location {
+    source: SourceId,
     byte_offset: usize,
     column: usize,
     row: usize,
@@ -11,28 +91,50 @@ pub struct Location {
 impl Location {
     pub fn new(byte_offset: usize, column: usize, row: usize) -> Self {
         Location {
+            source: SourceId::default(),
             byte_offset,
             column,
             row
         }
     }
 
+    pub fn with_source(self, source: SourceId) -> Self {
+        Self { source, ..self }
+    }
+
     pub fn new_line(&self, offset_increment: usize) -> Self {
         Self {
             byte_offset: self.byte_offset + offset_increment,
             column: 1,
             row: self.row + 1,
+            ..self.clone()
         }
     }
 
     pub fn increment(&self, offset_increment: usize) -> Self {
+        self.increment_to(offset_increment, self.column + 1)
+    }
+
+    pub fn increment_to(&self, offset_increment: usize, column: usize) -> Self {
+        Self {
+            byte_offset: self.byte_offset + offset_increment,
+            column,
+            ..self.clone()
+        }
+    }
+
+    /// Moves forward without updating row and column.
+    pub fn increment_offset(&self, offset_increment: usize) -> Self {
         Self {
             byte_offset: self.byte_offset + offset_increment,
-            column: self.column + 1,
-            row: self.row,
+            ..self.clone()
         }
     }
 
+    pub fn source(&self) -> SourceId {
+        self.source
+    }
+
     pub fn byte_offset(&self) -> usize {
         self.byte_offset
     }
@@ -45,6 +147,10 @@ impl Location {
         self.row
     }
 
+    pub fn is_tracked(&self) -> bool {
+        self.row > 0
+    }
+
     pub fn locate<T>(self, end: Location, target: T) -> Located<T> {
         Located {
             source_range: self..end,
@@ -54,6 +160,7 @@ impl Location {
 
     pub fn start() -> Self {
         Self {
+            source: SourceId::default(),
             byte_offset: 0,
             column: 1,
             row: 1
@@ -69,13 +176,25 @@ impl Default for Location {
 
 impl PartialOrd for Location {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.byte_offset.partial_cmp(&other.byte_offset)
+        Some(self.cmp(other))
     }
 }
 
+/// Locations of different sources are ordered by their source id.
 impl Ord for Location {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.byte_offset.cmp(&other.byte_offset)
+        self.source.cmp(&other.source).then(self.byte_offset.cmp(&other.byte_offset))
+    }
+}
+
+/// Prints `row:column`, or the byte offset if row and column were not tracked.
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_tracked() {
+            write!(f, "{}:{}", self.row, self.column)
+        } else {
+            write!(f, "@{}", self.byte_offset)
+        }
     }
 }
 
@@ -88,11 +207,41 @@ pub struct Located<T> {
 }
 
 impl<T> Located<T> {
-    fn source_range(&self) -> &SourceRange {
+    pub fn source_range(&self) -> &SourceRange {
         &self.source_range
     }
 
-    fn target(&self) -> &T {
+    pub fn target(&self) -> &T {
         &self.target
     }
-}
\ No newline at end of file
+
+    pub fn target_mut(&mut self) -> &mut T {
+        &mut self.target
+    }
+
+    pub fn into_target(self) -> T {
+        self.target
+    }
+
+    pub fn map<U, F: FnOnce(T) -> U>(self, f: F) -> Located<U> {
+        Located {
+            source_range: self.source_range,
+            target: f(self.target)
+        }
+    }
+}
+
+impl<T: Relocate> Relocate for Located<T> {
+    fn relocate(&mut self, relocate: &mut dyn FnMut(&Location) -> Location) {
+        self.source_range = relocate(&self.source_range.start)..relocate(&self.source_range.end);
+        self.target.relocate(relocate);
+    }
+}
+
+impl<T: Display> Display for Located<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.source_range.start, self.target)
+    }
+}
+
+impl<T: Error> Error for Located<T> {}
@@ -1,11 +1,15 @@
-mod location;
-mod text_parser;
+pub mod completion;
+pub mod diagnostics;
+pub mod incremental;
+pub mod location;
+pub mod source_map;
+pub mod text_parser;
 
 
 #[cfg(test)]
 mod test {
     use crate::parser::{Parser, Succeed};
-    use crate::text::location::Location;
+    use crate::text::location::{Located, Location};
     use crate::text::text_parser::{Number, whitespace, TextParser, Token};
 
     #[test]
@@ -31,7 +35,7 @@ mod test {
             String::from("+"),
             ParsError::ExpectedToken(String::from("+")),
         );
-        let add_parser = Succeed::with(|lhs: i64| move |rhs: i64| Add { lhs, rhs })
+        let add_parser = Succeed::<_, _, Located<ParsError>>::with(|lhs: i64| move |rhs: i64| Add { lhs, rhs })
             .keep(number.clone())
             .ignore(whitespace())
             .ignore(plus)
@@ -44,4 +48,32 @@ mod test {
         let loc = Location::new(4, 5, 1);
         assert_eq!(Err(loc.clone().locate(loc, ParsError::ExpectedInteger)), add_parser.pars("34 +"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn mixed_error_types() {
+        #[derive(Clone, Debug, Eq, PartialEq)]
+        enum AppError {
+            ExpectedKeyword,
+            Library(String),
+        }
+
+        impl From<String> for AppError {
+            fn from(message: String) -> Self {
+                AppError::Library(message)
+            }
+        }
+
+        let library_token = Token::new(String::from("x"), String::from("expected x"));
+        let parser = Token::new(String::from("let"), AppError::ExpectedKeyword)
+            .ignore(whitespace())
+            .map2(library_token.err_into(), |_, name| name);
+        assert_eq!(Ok(String::from("x")), parser.pars("let x"));
+
+        let loc = Location::new(4, 5, 1);
+        assert_eq!(Err(loc.clone().locate(Location::new(5, 6, 1), AppError::Library(String::from("expected x")))),
+                   parser.pars("let y"));
+
+        let converted = Token::new(String::from("x"), String::from("expected x")).err_into::<Located<AppError>>();
+        assert_eq!(Ok(String::from("x")), converted.pars("x"));
+    }
+}
@@ -0,0 +1,262 @@
+use std::rc::Rc;
+
+use std::fmt::Display;
+
+use crate::text::location::{Columns, Located, Location};
+use crate::text::text_parser::TextState;
+
+/// Identifies a source registered in a `SourceMap`. Inputs that don't come from a source map
+/// all share the default id, which is never assigned to a registered source.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+pub struct SourceId(u32);
+
+/// The byte offsets at which the lines of a text start. Lines end with `\n`, `\r\n` or `\r`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LineTable {
+    line_starts: Vec<usize>,
+}
+
+impl LineTable {
+    pub fn new(text: &str) -> Self {
+        let mut line_starts = vec![0];
+        let bytes = text.as_bytes();
+        for (index, byte) in bytes.iter().enumerate() {
+            match byte {
+                b'\n' => line_starts.push(index + 1),
+                b'\r' if bytes.get(index + 1) != Some(&b'\n') => line_starts.push(index + 1),
+                _ => {}
+            }
+        }
+        Self { line_starts }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// The byte offset at which `row` starts, rows start at 1.
+    pub fn line_start(&self, row: usize) -> Option<usize> {
+        row.checked_sub(1).and_then(|index| self.line_starts.get(index).copied())
+    }
+
+    /// The row containing `byte_offset`.
+    pub fn row(&self, byte_offset: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= byte_offset)
+    }
+
+    /// Row and column of `byte_offset` in `text`, which must be the text the table was built
+    /// from. Columns are counted like a parse state with the same `columns` counts them.
+    pub fn position(&self, text: &str, byte_offset: usize, columns: &Columns) -> (usize, usize) {
+        let row = self.row(byte_offset);
+        let line_start = self.line_starts[row - 1];
+        let end = byte_offset.min(text.len());
+        let column = text.get(line_start..end).map_or(1, |line| line.char_indices()
+            .fold(1, |column, (index, c)| columns.advance(text, line_start + index, c, column).unwrap_or(column)));
+        (row, column)
+    }
+
+    /// The text of `row` without its line break.
+    pub fn line<'t>(&self, text: &'t str, row: usize) -> Option<&'t str> {
+        let start = self.line_start(row)?;
+        let end = self.line_start(row + 1).unwrap_or(text.len());
+        Some(text[start..end].trim_end_matches(['\n', '\r']))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Source {
+    name: String,
+    text: Rc<String>,
+    lines: LineTable,
+}
+
+impl Source {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn lines(&self) -> &LineTable {
+        &self.lines
+    }
+}
+
+/// The named sources of one compilation. Locations carry the id of their source, so errors from
+/// different sources can be told apart and printed with the source name.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    sources: Vec<Source>,
+    columns: Columns,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts columns as configured by `columns`, both in the states created by `state` and when
+    /// resolving and rendering locations.
+    pub fn with_columns(self, columns: Columns) -> Self {
+        Self { columns, ..self }
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, text: impl Into<String>) -> SourceId {
+        let text = text.into();
+        let lines = LineTable::new(&text);
+        self.sources.push(Source { name: name.into(), text: Rc::new(text), lines });
+        SourceId(self.sources.len() as u32)
+    }
+
+    pub fn columns(&self) -> Columns {
+        self.columns
+    }
+
+    pub fn get(&self, id: SourceId) -> Option<&Source> {
+        let index = id.0.checked_sub(1)?;
+        self.sources.get(index as usize)
+    }
+
+    /// A fresh parse state for the source with `id`.
+    pub fn state(&self, id: SourceId) -> Option<TextState> {
+        self.get(id).map(|source| TextState::from_source(source.text.clone(), id).with_columns(self.columns))
+    }
+
+    /// The location at `byte_offset` of the source with `id`, with row and column.
+    pub fn location(&self, id: SourceId, byte_offset: usize) -> Option<Location> {
+        self.get(id).map(|source| {
+            let (row, column) = source.lines.position(&source.text, byte_offset, &self.columns);
+            Location::new(byte_offset, column, row).with_source(id)
+        })
+    }
+
+    /// Fills in row and column of a location that was parsed without tracking them.
+    pub fn resolve(&self, location: &Location) -> Location {
+        self.location(location.source(), location.byte_offset()).unwrap_or_else(|| location.clone())
+    }
+
+    /// Formats a location as `name:row:column`.
+    pub fn format_location(&self, location: &Location) -> String {
+        match self.get(location.source()) {
+            Some(source) => format!("{}:{}", source.name, self.resolve(location)),
+            None => location.to_string(),
+        }
+    }
+
+    /// Renders `error` with its location, the first line of its source range and carets under
+    /// that range.
+    pub fn render<T: Display>(&self, error: &Located<T>) -> String {
+        let range = error.source_range();
+        let mut rendered = format!("{}: {}", self.format_location(&range.start), error.target());
+        let source = match self.get(range.start.source()) {
+            Some(source) => source,
+            None => return rendered,
+        };
+        let row = source.lines.row(range.start.byte_offset());
+        let line_start = source.lines.line_start(row).expect("row of an offset exists");
+        let line = source.lines.line(&source.text, row).expect("row of an offset exists");
+        let start = (range.start.byte_offset() - line_start).min(line.len());
+        let end = range.end.byte_offset().saturating_sub(line_start).clamp(start, line.len());
+        // Tabs are kept so the carets line up however wide the terminal draws them.
+        let padding: String = line[..start].chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+        let width = line[start..end].chars().count().max(1);
+        rendered.push_str(&format!("\n{}\n{}{}", line, padding, "^".repeat(width)));
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::ParseError;
+    use crate::parser::Parser;
+    use crate::text::location::{ColumnMode, Columns, Location};
+    use crate::text::source_map::{LineTable, SourceMap};
+    use crate::text::text_parser::{integer, TextParser, TextState, token, whitespace};
+
+    const MODES: [ColumnMode; 4] = [ColumnMode::Scalar, ColumnMode::Utf16, ColumnMode::Utf8, ColumnMode::Grapheme];
+
+    fn position_of(text: &str, needle: char, columns: Columns) -> (usize, usize) {
+        let mut state = TextState::new(text).with_columns(columns);
+        while state.peek() != Some(needle) {
+            state.advance();
+        }
+        (state.location().row(), state.location().column())
+    }
+
+    #[test]
+    fn line_table_matches_tracked_locations() {
+        let text = "ab\r\nc\u{e4}d\r\u{1F600}\te\u{301}\n\nx";
+        let table = LineTable::new(text);
+        assert_eq!(5, table.line_count());
+        assert_eq!(Some(18), table.line_start(4));
+        assert_eq!(Some("c\u{e4}d"), table.line(text, 2));
+
+        for mode in MODES {
+            let columns = Columns::new().with_mode(mode).with_tab_width(4);
+            let mut state = TextState::new(text).with_columns(columns);
+            loop {
+                let location = state.location().clone();
+                assert_eq!((location.row(), location.column()), table.position(text, location.byte_offset(), &columns));
+                if state.next().is_none() {
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn column_modes() {
+        let text = "\u{1F600}e\u{301}x";
+        let columns = Columns::new();
+        assert_eq!((1, 4), position_of(text, 'x', columns));
+        assert_eq!((1, 5), position_of(text, 'x', columns.with_mode(ColumnMode::Utf16)));
+        assert_eq!((1, 8), position_of(text, 'x', columns.with_mode(ColumnMode::Utf8)));
+        assert_eq!((1, 3), position_of(text, 'x', columns.with_mode(ColumnMode::Grapheme)));
+
+        assert_eq!((1, 5), position_of("\tx", 'x', columns.with_tab_width(4)));
+        assert_eq!((1, 9), position_of("ab\tx", 'x', columns.with_tab_width(8)));
+        assert_eq!((2, 1), position_of("a\r\nx", 'x', columns));
+        assert_eq!((2, 1), position_of("a\rx", 'x', columns));
+        assert_eq!((3, 1), position_of("a\r\rx", 'x', columns));
+    }
+
+    #[test]
+    fn errors_name_their_source() {
+        let mut sources = SourceMap::new();
+        let first = sources.add("first.txt", "1 + 2");
+        let second = sources.add("second.txt", "1 +\n x");
+        let sum = integer().ignore(whitespace()).ignore(token("+")).ignore(whitespace()).map2(integer(), |lhs, rhs| lhs + rhs);
+
+        assert_eq!(Ok(3), sum.pars_with(sources.state(first).expect("first source")));
+        let error = sum.pars_with(sources.state(second).expect("second source")).unwrap_err();
+        assert_eq!(second, error.source_range().start.source());
+        assert_eq!("second.txt:2:2", sources.format_location(&error.source_range().start));
+        assert_eq!(ParseError::expected_rule("integer"), *error.target());
+
+        let error = sum.pars("1 +\n x").unwrap_err();
+        assert_eq!("2:2", sources.format_location(&error.source_range().start));
+    }
+
+    #[test]
+    fn untracked_locations_resolve_on_demand() {
+        let mut sources = SourceMap::new();
+        let id = sources.add("input", "12\n  x");
+        let state = sources.state(id).expect("source").with_position_tracking(false);
+        let error = integer().ignore(whitespace()).map2(integer(), |a, _| a).pars_with(state).unwrap_err();
+
+        let start = &error.source_range().start;
+        assert_eq!("@5", start.to_string());
+        assert_eq!(Location::new(5, 3, 2).with_source(id), sources.resolve(start));
+    }
+
+    #[test]
+    fn renders_errors_with_configured_columns() {
+        let mut sources = SourceMap::new().with_columns(Columns::new().with_mode(ColumnMode::Utf16).with_tab_width(4));
+        let id = sources.add("emoji.txt", "\u{1F600}\t= yes\r\n");
+        let assignment = token("\u{1F600}").ignore(whitespace()).ignore(token("=")).ignore(whitespace()).map2(integer(), |_, value| value);
+        let error = assignment.pars_with(sources.state(id).expect("source")).unwrap_err();
+        assert_eq!("emoji.txt:1:7: expected integer\n\u{1F600}\t= yes\n \t  ^", sources.render(&error));
+    }
+}
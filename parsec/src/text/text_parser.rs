@@ -1,23 +1,170 @@
+use std::convert::Infallible;
 use std::iter::FromIterator;
-use std::marker::PhantomData;
 use std::num::{ParseFloatError, ParseIntError};
 use std::rc::Rc;
 use std::str::FromStr;
 
+use polymap::PolyMap;
+
+use crate::context::ContextState;
+use crate::cst::{CstEvent, CstRecorder, CstState, SyntaxKind, SyntaxNode};
+use crate::error::{Expectation, ParseError};
+use crate::grammar::{Description, Grammar};
+use crate::limits::{Budget, LimitExceeded, Limits};
 use crate::parser::Parser;
-use crate::text::location::{Located, Location};
+use crate::text::completion::{self, Completion, Expectations};
+use crate::text::incremental::{Incremental, MemoStart, Relocate, Reuse, TextEdit};
+use crate::text::location::{Columns, Located, Location};
+use crate::text::source_map::SourceId;
+use crate::trace::{RuleHooks, Trace, TraceError};
+use crate::trampoline::{self, Continuation, StackParser, Step};
 
 #[derive(Clone, Debug)]
 pub struct TextState {
     input: Rc<String>,
     location: Location,
+    trace: Option<Trace>,
+    budget: Option<Rc<Budget>>,
+    depth: usize,
+    context: Rc<PolyMap>,
+    track_position: bool,
+    columns: Columns,
+    cst: Option<CstRecorder>,
+    reuse: Option<Reuse>,
+    expectations: Option<Expectations>,
 }
 
 impl TextState {
+    pub fn new(input: impl Into<String>) -> Self {
+        Self::from_source(Rc::new(input.into()), SourceId::default())
+    }
+
+    pub(crate) fn from_source(input: Rc<String>, source: SourceId) -> Self {
+        Self {
+            input,
+            location: Location::start().with_source(source),
+            trace: None,
+            budget: None,
+            depth: 0,
+            context: Rc::new(PolyMap::new()),
+            track_position: true,
+            columns: Columns::default(),
+            cst: None,
+            reuse: None,
+            expectations: None,
+        }
+    }
+
+    /// Records the nodes and tokens of `cst::node` and `cst::leaf` parsers, see `syntax_tree`.
+    pub fn with_cst(self) -> Self {
+        Self { cst: Some(CstRecorder::default()), ..self }
+    }
+
+    /// The lossless syntax tree of everything parsed up to this state, if it records one. The
+    /// tree covers the whole input, see `CstRecorder::build`.
+    pub fn syntax_tree(&self) -> Option<SyntaxNode> {
+        self.cst.as_ref().map(|cst| cst.build(&self.input, self.location.byte_offset()))
+    }
+
+    /// Takes `reusable` nodes from the previous parse of an incremental parse.
+    pub(crate) fn with_reuse(self, reuse: Reuse) -> Self {
+        Self { reuse: Some(reuse), ..self }
+    }
+
+    /// Continues after the `reusable` node of `kind` at the current offset if the previous parse
+    /// has one, recording its syntax tree events again and moving the locations in its value.
+    pub(crate) fn reuse_node<V: Clone + Relocate + 'static>(&mut self, kind: SyntaxKind) -> Option<V> {
+        let offset = self.location.byte_offset();
+        let (mut value, len, events, previous_start) = self.reuse.as_ref()?.reuse::<V>(kind, offset)?;
+        for event in events.iter() {
+            self.record(event.moved(0, offset));
+        }
+        let moved = previous_start != self.location;
+        let mut locations = vec![];
+        while self.location.byte_offset() < offset + len {
+            if moved {
+                locations.push(self.location.clone());
+            }
+            let next = self.input[self.location.byte_offset()..].char_indices().next();
+            self.location = self.location_after(next);
+        }
+        if moved {
+            locations.push(self.location.clone());
+            value.relocate(&mut |location| {
+                let relative = location.byte_offset().checked_sub(previous_start.byte_offset());
+                relative.and_then(|relative| locations.binary_search_by_key(&(offset + relative), Location::byte_offset).ok())
+                    .map_or_else(|| location.clone(), |index| locations[index].clone())
+            });
+        }
+        Some(value)
+    }
+
+    pub(crate) fn begin_memo(&self) -> Option<(Reuse, MemoStart)> {
+        let events = self.cst.as_ref().map_or(0, CstRecorder::len);
+        self.reuse.as_ref().map(|reuse| (reuse.clone(), reuse.begin(self.location.clone(), events)))
+    }
+
+    pub(crate) fn cst_events_since(&self, start: usize) -> Vec<CstEvent> {
+        self.cst.as_ref().map_or_else(Vec::new, |cst| cst.events_since(start))
+    }
+
+    /// Records what parsers expected when they ran into the end of the input, see
+    /// `TextParser::completions`.
+    pub(crate) fn with_expectations(self, expectations: Expectations) -> Self {
+        Self { expectations: Some(expectations), ..self }
+    }
+
+    /// Records `expectation` as a completion starting at `start` if the input ends here.
+    fn expected_at_end(&self, start: &Location, expectation: impl FnOnce() -> Expectation) {
+        if let Some(expectations) = &self.expectations {
+            if self.location.byte_offset() == self.input.len() {
+                expectations.record(expectation(), start.clone());
+            }
+        }
+    }
+
+    /// Counts columns of locations as configured by `columns`.
+    pub fn with_columns(self, columns: Columns) -> Self {
+        Self { columns, ..self }
+    }
+
+    /// Without tracking only the byte offset of locations is updated, which makes parsing
+    /// faster. Row and column can be computed later with `SourceMap::resolve`.
+    pub fn with_position_tracking(self, track_position: bool) -> Self {
+        let location = if track_position {
+            Location::start()
+        } else {
+            Location::new(0, 0, 0)
+        };
+        Self { location: location.with_source(self.location.source()), track_position, ..self }
+    }
+
+    /// Starts parsing with the values of `context`, see `context::get_ctx`.
+    pub fn with_context(self, context: PolyMap) -> Self {
+        Self { context: Rc::new(context), ..self }
+    }
+
+    /// Stops parsing once one of the `limits` is exceeded. Use `TextParser::pars_limited` or
+    /// `limit_exceeded` to find out whether that happened.
+    pub fn with_limits(self, limits: Limits) -> Self {
+        let budget = Budget::new(limits, self.input.len());
+        Self { budget: Some(Rc::new(budget)), ..self }
+    }
+
+    /// The limit that stopped parsing, if any. Shared by all clones of this state.
+    pub fn limit_exceeded(&self) -> Option<Located<LimitExceeded>> {
+        self.budget.as_ref().and_then(|budget| budget.exceeded())
+    }
+
+    /// Records every `named` rule that is tried while parsing into `trace`.
+    pub fn with_trace(self, trace: Trace) -> Self {
+        Self { trace: Some(trace), ..self }
+    }
+
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Option<char> {
         let next = self.peek_internal();
-        self.advance_internal(next);
-        next.map(|(_, c)| c)
+        self.advance_internal(next)
     }
 
     pub fn peek(&self) -> Option<char> {
@@ -25,8 +172,19 @@ impl TextState {
     }
 
     fn peek_internal(&self) -> Option<(usize, char)> {
+        if let Some(budget) = &self.budget {
+            if !budget.check(&self.location) {
+                return None;
+            }
+        }
         let next_str = &self.input[self.location.byte_offset()..];
-        next_str.char_indices().next()
+        let next = next_str.char_indices().next();
+        if let Some(reuse) = &self.reuse {
+            // Looking at the end of input counts as examining the byte after it, so appending
+            // text invalidates the nodes that stopped there.
+            reuse.examine(self.location.byte_offset() + next.map_or(1, |(_, c)| c.len_utf8()));
+        }
+        next
     }
 
     pub fn advance(&mut self) {
@@ -34,13 +192,29 @@ impl TextState {
         self.advance_internal(next);
     }
 
-    fn advance_internal(&mut self, next: Option<(usize, char)>) {
-        let new_location = match next {
-            None => self.location.clone(),
-            Some((index, '\n')) => self.location.new_line(index + 1),
-            Some((index, _)) => self.location.increment(index + 1)
-        };
+    fn advance_internal(&mut self, next: Option<(usize, char)>) -> Option<char> {
+        if let (Some(budget), Some(_)) = (&self.budget, next) {
+            if !budget.step(&self.location) {
+                return None;
+            }
+        }
+        let new_location = self.location_after(next);
         self.location = new_location;
+        next.map(|(_, c)| c)
+    }
+
+    fn location_after(&self, next: Option<(usize, char)>) -> Location {
+        match next {
+            None => self.location.clone(),
+            Some((index, c)) if !self.track_position => self.location.increment_offset(index + c.len_utf8()),
+            Some((index, c)) => {
+                let byte_offset = self.location.byte_offset() + index;
+                match self.columns.advance(&self.input, byte_offset, c, self.location.column()) {
+                    Some(column) => self.location.increment_to(index + c.len_utf8(), column),
+                    None => self.location.new_line(index + c.len_utf8()),
+                }
+            }
+        }
     }
 
     pub fn locate_at_exactly<T>(&self, target: T) -> Located<T> {
@@ -56,19 +230,139 @@ impl TextState {
     }
 }
 
-pub trait TextParser<E>: Parser<State=TextState, Error=Located<E>> {
+impl ContextState for TextState {
+    fn context(&self) -> &PolyMap {
+        &self.context
+    }
+
+    fn context_mut(&mut self) -> &mut PolyMap {
+        Rc::make_mut(&mut self.context)
+    }
+}
+
+impl CstState for TextState {
+    fn cst_offset(&self) -> usize {
+        self.location.byte_offset()
+    }
+
+    fn record(&mut self, event: CstEvent) {
+        if let Some(cst) = &mut self.cst {
+            cst.record(event);
+        }
+    }
+}
+
+impl<E: TraceError> RuleHooks<E> for TextState {
+    fn trace(&self) -> Option<&Trace> {
+        self.trace.as_ref()
+    }
+
+    fn trace_location(&self) -> Location {
+        self.location.clone()
+    }
+
+    fn trace_end(error: &E) -> Option<Location> {
+        error.trace_end()
+    }
+
+    fn enter_rule(&mut self) {
+        self.depth += 1;
+        if let Some(budget) = &self.budget {
+            budget.check_depth(self.depth, &self.location);
+        }
+    }
+
+    fn exit_rule(&mut self) {
+        self.depth -= 1;
+    }
+}
+
+pub trait TextParser<E = ParseError>: Parser<State=TextState, Error=Located<E>> {
     fn pars(&self, input: impl Into<String>) -> Result<Self::Value, Self::Error> {
-        let state = TextState {
-            input: Rc::new(input.into()),
-            location: Location::default(),
-        };
+        self.pars_with(TextState::new(input))
+    }
+
+    /// Parses starting from a configured state, e.g. one with a trace.
+    fn pars_with(&self, state: TextState) -> Result<Self::Value, Self::Error> {
         self.do_pars(state).map(|(_, value)| value)
     }
+
+    /// Parses untrusted input. If a limit is exceeded the result is a `LimitExceeded` error,
+    /// even if the parser recovered from the failures caused by the limit.
+    fn pars_limited(&self, input: impl Into<String>, limits: Limits) -> Result<Self::Value, Self::Error>
+        where E: From<LimitExceeded>
+    {
+        let state = TextState::new(input).with_limits(limits);
+        let budget = state.budget.clone();
+        let result = self.pars_with(state);
+        match budget.and_then(|budget| budget.exceeded()) {
+            Some(exceeded) => Err(exceeded.map(E::from)),
+            None => result,
+        }
+    }
+
+    /// Parses on the trampoline, so deeply nested input can't overflow the stack.
+    fn pars_stack_safe<'d>(&self, input: impl Into<String>) -> Result<Self::Value, Self::Error>
+        where Self: StackParser<'d>,
+              Self::Value: 'd,
+              E: 'd
+    {
+        trampoline::run(self, TextState::new(input)).map(|(_, value)| value)
+    }
+
+    /// Parses in CST mode and returns the value together with the lossless syntax tree.
+    fn pars_cst(&self, input: impl Into<String>) -> Result<(Self::Value, SyntaxNode), Self::Error> {
+        self.do_pars(TextState::new(input).with_cst()).map(|(state, value)| {
+            let tree = state.syntax_tree().expect("state records a syntax tree");
+            (value, tree)
+        })
+    }
+
+    /// What could come next at `cursor`: the tokens, keywords and named rules the parser would
+    /// accept there, ranked as described in `completion::complete`.
+    fn completions(&self, input: &str, cursor: &Location) -> Vec<Completion> {
+        completion::complete(self, input, cursor)
+    }
+
+    /// Parses in CST mode like `pars_cst` and keeps what `repars` needs to reuse the
+    /// `incremental::reusable` nodes of this parse after an edit.
+    fn pars_incremental(&self, input: impl Into<String>) -> Incremental<Self::Value, Self::Error> {
+        Incremental::parse(self, Rc::new(input.into()), Default::default())
+    }
+
+    /// Parses the text of `previous` with `edit` applied. Reusable nodes that didn't examine
+    /// the edited input are taken from `previous`, the result is the same as a fresh parse.
+    fn repars(&self, previous: &Incremental<Self::Value, Self::Error>, edit: &TextEdit) -> Incremental<Self::Value, Self::Error> {
+        let text = Rc::new(edit.apply(previous.text()));
+        Incremental::parse(self, text, previous.memo_after(edit))
+    }
 }
 
+/// Implements `StackParser` for parsers that don't run other parsers.
+macro_rules! leaf_stack_parser {
+    (impl<$($generic:ident),*> for $parser:ty $(where $($bound:tt)*)?) => {
+        impl<'d, $($generic),*> StackParser<'d> for $parser
+            where Self::Value: 'd,
+                  $($($bound)*)?
+        {
+            fn bounce<'a>(&'a self, state: Self::State, continuation: Continuation<'a, Self>) -> Step<'a>
+                where 'd: 'a
+            {
+                Step::more(move || continuation(self.do_pars(state)))
+            }
+        }
+    };
+}
+
+leaf_stack_parser!(impl<E> for Token<E> where E: Clone + 'd);
+leaf_stack_parser!(impl<F> for Chop<F> where F: Fn(char) -> bool + Clone);
+leaf_stack_parser!(impl<E> for End<E> where E: Clone + 'd);
+leaf_stack_parser!(impl<F, I, R, E> for Number<F, I, R, E>
+    where E: Clone + 'd, F: Fn(Result<f64, ParseFloatError>) -> Result<R, E>, I: Fn(Result<i64, ParseIntError>) -> Result<R, E>);
+
 impl<P: Parser<State=TextState, Error=Located<E>>, E> TextParser<E> for P {}
 
-pub struct Token<E: Clone> {
+pub struct Token<E: Clone = ParseError> {
     token: String,
     error: E,
 }
@@ -79,6 +373,10 @@ impl<E: Clone> Token<E> {
     }
 }
 
+pub fn token(token: &str) -> Token {
+    Token::new(String::from(token), ParseError::expected_token(token))
+}
+
 impl<E: Clone> Parser for Token<E> {
     type Value = String;
     type State = TextState;
@@ -88,7 +386,10 @@ impl<E: Clone> Parser for Token<E> {
         let start_location = state.location().clone();
         for expected_char in self.token.chars() {
             match state.next() {
-                None => return Err(state.locate(start_location, self.error.clone())),
+                None => {
+                    state.expected_at_end(&start_location, || Expectation::Token(self.token.clone()));
+                    return Err(state.locate(start_location, self.error.clone()));
+                }
                 Some(found_char) => {
                     if expected_char != found_char {
                         return Err(state.locate(start_location, self.error.clone()));
@@ -98,29 +399,38 @@ impl<E: Clone> Parser for Token<E> {
         }
         Ok((state, self.token.clone()))
     }
+
+    fn describe(&self, _grammar: &mut Grammar) -> Description {
+        Description::Token(self.token.clone())
+    }
 }
 
 #[derive(Debug, Clone)]
-pub struct Chop<F: Clone, E> {
+pub struct Chop<F: Clone> {
     f: F,
-    _error: PhantomData<E>,
+    description: Option<String>,
 }
 
-impl<F, E> Chop<F, E> where F: Fn(char) -> bool + Clone {
+impl<F> Chop<F> where F: Fn(char) -> bool + Clone {
     pub fn while_con(predicate: F) -> Self {
-        Self { f: predicate, _error: PhantomData::default() }
+        Self { f: predicate, description: None }
+    }
+
+    /// Names the class of characters matched by the predicate for grammar exports.
+    pub fn described_as(self, description: &str) -> Self {
+        Self { description: Some(String::from(description)), ..self }
     }
 }
 
-pub fn whitespace<E>() -> Chop<fn(char) -> bool, E> {
-    Chop::while_con(char::is_whitespace)
+pub fn whitespace() -> Chop<fn(char) -> bool> {
+    Chop::while_con(char::is_whitespace as fn(char) -> bool).described_as("whitespace")
 }
 
 
-impl<F, E> Parser for Chop<F, E> where F: Fn(char) -> bool + Clone {
+impl<F> Parser for Chop<F> where F: Fn(char) -> bool + Clone {
     type Value = String;
     type State = TextState;
-    type Error = E;
+    type Error = Infallible;
 
     fn do_pars(&self, mut state: Self::State) -> Result<(Self::State, Self::Value), Self::Error> {
         let predicate = &self.f;
@@ -135,23 +445,90 @@ impl<F, E> Parser for Chop<F, E> where F: Fn(char) -> bool + Clone {
             }
         }
     }
+
+    fn describe(&self, _grammar: &mut Grammar) -> Description {
+        let description = self.description.clone().unwrap_or_else(|| String::from("character"));
+        Description::repetition(Description::Terminal(description), 0, None)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct End<E: Clone = ParseError> {
+    error: E,
+}
+
+impl<E: Clone> End<E> {
+    pub fn new(error: E) -> Self {
+        Self { error }
+    }
+}
+
+/// Succeeds only if all input has been consumed.
+pub fn end() -> End {
+    End::new(ParseError::expected(Expectation::EndOfInput))
+}
+
+impl<E: Clone> Parser for End<E> {
+    type Value = ();
+    type State = TextState;
+    type Error = Located<E>;
+
+    fn do_pars(&self, state: Self::State) -> Result<(Self::State, Self::Value), Self::Error> {
+        match state.peek() {
+            None => Ok((state, ())),
+            Some(_) => Err(state.locate_at_exactly(self.error.clone())),
+        }
+    }
+
+    fn describe(&self, _grammar: &mut Grammar) -> Description {
+        Description::Empty
+    }
+}
+
+pub type FloatFn<R, E = ParseError> = fn(Result<f64, ParseFloatError>) -> Result<R, E>;
+
+pub type IntegerFn<R, E = ParseError> = fn(Result<i64, ParseIntError>) -> Result<R, E>;
+
+/// Parses an integer, rejecting floats.
+pub fn integer() -> Number<FloatFn<i64>, IntegerFn<i64>, i64> {
+    let number: Number<FloatFn<i64>, IntegerFn<i64>, i64> = Number::new(
+        |_| Err(ParseError::expected_rule("integer")),
+        |int_res| int_res.map_err(|e| ParseError::custom(e.to_string())),
+        ParseError::expected_rule("integer"),
+    );
+    number.described_as("integer")
+}
+
+/// Parses a float, accepting integers as well.
+pub fn float() -> Number<FloatFn<f64>, IntegerFn<f64>, f64> {
+    Number::new(
+        |float_res| float_res.map_err(|e| ParseError::custom(e.to_string())),
+        |int_res| int_res.map(|int| int as f64).map_err(|e| ParseError::custom(e.to_string())),
+        ParseError::expected_rule("number"),
+    )
 }
 
 #[derive(Debug, Clone)]
-pub struct Number<F, I, R, E: Clone>
+pub struct Number<F, I, R, E: Clone = ParseError>
     where F: Fn(Result<f64, ParseFloatError>) -> Result<R, E>,
           I: Fn(Result<i64, ParseIntError>) -> Result<R, E>,
 {
     float: F,
     integer: I,
     error: E,
+    description: String,
 }
 
 impl<F, I, R, E: Clone> Number<F, I, R, E>
     where F: Fn(Result<f64, ParseFloatError>) -> Result<R, E>,
           I: Fn(Result<i64, ParseIntError>) -> Result<R, E> {
     pub fn new(float: F, integer: I, error: E) -> Self {
-        Self { float, integer, error }
+        Self { float, integer, error, description: String::from("number") }
+    }
+
+    /// Names the kind of number for grammar exports.
+    pub fn described_as(self, description: &str) -> Self {
+        Self { description: String::from(description), ..self }
     }
 }
 
@@ -205,29 +582,36 @@ impl<F, I, R, E: Clone> Parser for Number<F, I, R, E>
         let made_progress = !consumed_chars.is_empty();
         let number_str = String::from_iter(consumed_chars);
         if !made_progress {
+            safe_state.expected_at_end(&start_location, || Expectation::Rule(self.description.clone()));
             return Err(safe_state.locate(start_location, self.error.clone()));
         }
 
         if is_float {
             let number = f64::from_str(number_str.as_str());
-            match (&self.float)(number) {
+            match (self.float)(number) {
                 Ok(r) => Ok((safe_state, r)),
                 Err(e) => Err(safe_state.locate(start_location, e))
             }
         } else {
             let number = i64::from_str(number_str.as_str());
-            match (&self.integer)(number) {
+            match (self.integer)(number) {
                 Ok(r) => Ok((safe_state, r)),
                 Err(e) => Err(safe_state.locate(start_location, e))
             }
         }
     }
+
+    fn describe(&self, _grammar: &mut Grammar) -> Description {
+        Description::Terminal(self.description.clone())
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use crate::error::ParseError;
+    use crate::parser::Parser;
     use crate::text::location::{Located, Location};
-    use crate::text::text_parser::{Number, TextParser};
+    use crate::text::text_parser::{end, float, integer, Number, TextParser, token, whitespace};
 
     fn str_err<T>(str: &str, start_location: Location, end_location: Location) -> Result<T, Located<String>> {
         Err(start_location.locate(end_location, String::from(str)))
@@ -268,4 +652,25 @@ mod test {
         assert_eq!(Ok(42f64), float.pars(String::from("42F")));
         assert_eq!(Ok(42.42f64), float.pars(String::from("42.42")));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn default_error_parsers() {
+        assert_eq!(Ok(42), integer().pars("42"));
+        assert_eq!(Ok(4.5), float().pars("4.5"));
+        assert_eq!(Ok(4.0), float().pars("4"));
+        assert_eq!(Err(Location::start().locate(Location::start(), ParseError::expected_rule("integer"))),
+                   integer().pars("x"));
+    }
+
+    #[test]
+    fn alternation_merges_expectations() {
+        let sign = token("+").or(token("-")).ignore(end());
+        assert_eq!(Ok(String::from("-")), sign.pars("-"));
+
+        let error = sign.pars("*").expect_err("neither token matches");
+        assert_eq!("1:1: expected `+` or `-`", error.to_string());
+
+        let error = integer().ignore(whitespace()).ignore(end()).pars("12 3").expect_err("trailing input");
+        assert_eq!("1:4: expected end of input", error.to_string());
+    }
+}
@@ -0,0 +1,241 @@
+use std::cell::RefCell;
+use std::convert::Infallible;
+use std::fmt::{Display, Formatter, Write};
+use std::rc::Rc;
+
+use crate::text::location::{Located, Location};
+
+/// Hooks `named` runs around every rule it parses, with `E` the error of the rule. States use
+/// them to trace rule attempts or to limit how deeply rules nest. Every hook does nothing by
+/// default, so a state without tracing or limits implements the trait with an empty body.
+pub trait RuleHooks<E> {
+    /// The trace recording the rules parsed from this state.
+    fn trace(&self) -> Option<&Trace> {
+        None
+    }
+
+    fn trace_location(&self) -> Location {
+        Location::start()
+    }
+
+    /// Where a traced rule stopped when it failed with `error`.
+    fn trace_end(_error: &E) -> Option<Location> {
+        None
+    }
+
+    fn enter_rule(&mut self) {}
+
+    fn exit_rule(&mut self) {}
+}
+
+impl<E> RuleHooks<E> for () {}
+
+/// Errors that know where a traced rule stopped when it failed.
+pub trait TraceError {
+    fn trace_end(&self) -> Option<Location>;
+}
+
+impl<T> TraceError for Located<T> {
+    fn trace_end(&self) -> Option<Location> {
+        Some(self.source_range().end.clone())
+    }
+}
+
+impl TraceError for Infallible {
+    fn trace_end(&self) -> Option<Location> {
+        match *self {}
+    }
+}
+
+impl TraceError for () {
+    fn trace_end(&self) -> Option<Location> {
+        None
+    }
+}
+
+/// One attempt to parse a named rule.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TraceNode {
+    pub rule: String,
+    pub start: Location,
+    pub end: Option<Location>,
+    pub success: bool,
+    /// The rule started before a location that had already been reached, so the parser went
+    /// back in the input to try it.
+    pub backtracked: bool,
+    pub children: Vec<TraceNode>,
+}
+
+#[derive(Debug, Default)]
+struct TraceData {
+    roots: Vec<TraceNode>,
+    open: Vec<TraceNode>,
+    furthest: Location,
+}
+
+/// A shared recorder of rule attempts. Clones record into the same trace, so one clone can be
+/// handed to the parse state and the other one inspected afterwards.
+#[derive(Debug, Clone, Default)]
+pub struct Trace {
+    data: Rc<RefCell<TraceData>>,
+}
+
+impl Trace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn enter(&self, rule: &str, start: Location) {
+        let mut data = self.data.borrow_mut();
+        let backtracked = start < data.furthest;
+        data.open.push(TraceNode {
+            rule: String::from(rule),
+            start,
+            end: None,
+            success: false,
+            backtracked,
+            children: vec![],
+        });
+    }
+
+    pub fn exit(&self, end: Option<Location>, success: bool) {
+        let mut data = self.data.borrow_mut();
+        let mut node = data.open.pop().expect("exit matches an enter");
+        if let Some(end) = &end {
+            if *end > data.furthest {
+                data.furthest = end.clone();
+            }
+        }
+        node.end = end;
+        node.success = success;
+        match data.open.last_mut() {
+            Some(parent) => parent.children.push(node),
+            None => data.roots.push(node),
+        }
+    }
+
+    /// The completed top level rule attempts.
+    pub fn roots(&self) -> Vec<TraceNode> {
+        self.data.borrow().roots.clone()
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        json.push('[');
+        for (index, node) in self.data.borrow().roots.iter().enumerate() {
+            if index > 0 {
+                json.push(',');
+            }
+            node_json(node, &mut json);
+        }
+        json.push(']');
+        json
+    }
+}
+
+/// Prints the trace as an indented tree, one rule attempt per line.
+impl Display for Trace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        fn write_node(node: &TraceNode, depth: usize, f: &mut Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:indent$}{} {}", "", node.rule, node.start, indent = depth * 2)?;
+            if let Some(end) = &node.end {
+                write!(f, "-{}", end)?;
+            }
+            write!(f, " {}", if node.success { "ok" } else { "failed" })?;
+            if node.backtracked {
+                write!(f, " (backtracked)")?;
+            }
+            writeln!(f)?;
+            node.children.iter().try_for_each(|child| write_node(child, depth + 1, f))
+        }
+
+        self.data.borrow().roots.iter().try_for_each(|node| write_node(node, 0, f))
+    }
+}
+
+fn node_json(node: &TraceNode, json: &mut String) {
+    json.push_str("{\"rule\":");
+    push_json_string(&node.rule, json);
+    json.push_str(",\"start\":");
+    location_json(&node.start, json);
+    json.push_str(",\"end\":");
+    match &node.end {
+        Some(end) => location_json(end, json),
+        None => json.push_str("null"),
+    }
+    write!(json, ",\"success\":{},\"backtracked\":{},\"children\":[", node.success, node.backtracked)
+        .expect("write to string");
+    for (index, child) in node.children.iter().enumerate() {
+        if index > 0 {
+            json.push(',');
+        }
+        node_json(child, json);
+    }
+    json.push_str("]}");
+}
+
+pub(crate) fn location_json(location: &Location, json: &mut String) {
+    write!(json, "{{\"offset\":{},\"line\":{},\"column\":{}}}",
+           location.byte_offset(), location.row(), location.column()).expect("write to string");
+}
+
+pub(crate) fn push_json_string(string: &str, json: &mut String) {
+    json.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(json, "\\u{:04x}", c as u32).expect("write to string"),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parser::{from_fn, named, Parser, Succeed};
+    use crate::text::text_parser::{integer, TextParser, TextState, token, whitespace};
+    use crate::trace::{RuleHooks, Trace};
+
+    #[test]
+    fn records_rule_tree() {
+        let sign = named("sign", token("+").or(token("-")));
+        let sum = named("sum", (named("lhs", integer()), whitespace(), sign, whitespace(), named("rhs", integer())));
+        let expression = named("expression", sum.map(|(lhs, _, _, _, rhs)| lhs + rhs).or(named("single", integer())));
+
+        let trace = Trace::new();
+        let result = expression.pars_with(TextState::new("4 x").with_trace(trace.clone()));
+        assert_eq!(Ok(4), result);
+        assert_eq!("expression 1:1-1:2 ok\n  sum 1:1-1:4 failed\n    lhs 1:1-1:2 ok\n    sign 1:3-1:4 failed\n  single 1:1-1:2 ok (backtracked)\n",
+                   trace.to_string());
+        assert!(trace.to_json().starts_with(
+            "[{\"rule\":\"expression\",\"start\":{\"offset\":0,\"line\":1,\"column\":1},\"end\":{\"offset\":1,\"line\":1,\"column\":2},\"success\":true"));
+    }
+
+    #[test]
+    fn only_traced_states_record() {
+        let parser = named("one", integer());
+        let trace = Trace::new();
+        assert_eq!(Ok(1), parser.pars_with(TextState::new("1").with_trace(trace.clone())));
+        assert_eq!(Ok(2), parser.pars("2"));
+        assert_eq!(Ok(3), parser.pars_with(TextState::new("3").with_trace(Trace::new())));
+        assert_eq!("one 1:1-1:2 ok\n", trace.to_string());
+    }
+
+    #[test]
+    fn named_works_without_hooks() {
+        #[derive(Debug, Eq, PartialEq)]
+        struct Count(usize);
+
+        impl RuleHooks<String> for Count {}
+
+        let parser = named("count", from_fn(|Count(n)| if n < 3 { Ok((Count(n + 1), n)) } else { Err(String::from("done")) }));
+        assert_eq!(Ok((Count(2), 1)), parser.do_pars(Count(1)));
+        assert_eq!(Err(String::from("done")), parser.do_pars(Count(3)));
+        assert_eq!(Ok(((), 1)), named("unit", Succeed::<(), _, String>::with(1)).do_pars(()));
+    }
+}
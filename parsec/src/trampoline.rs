@@ -0,0 +1,166 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::grammar::{Description, Grammar};
+use crate::parser::Parser;
+
+/// One bounce of the trampoline: either the parse is done or there is more work to do.
+///
+/// This is the idea of `recursion_driver` applied to parsers: instead of calling nested
+/// parsers on the native stack, every parser returns the next piece of work to `run` and
+/// passes its result on to a continuation. The continuations live on the heap, so the nesting
+/// of the input is limited by memory instead of the thread's stack size.
+pub enum Step<'a> {
+    Continue(Box<dyn FnOnce() -> Step<'a> + 'a>),
+    Done,
+}
+
+impl<'a> Step<'a> {
+    pub fn more<F: FnOnce() -> Step<'a> + 'a>(f: F) -> Self {
+        Step::Continue(Box::new(f))
+    }
+}
+
+pub type ParseResult<P> = Result<(<P as Parser>::State, <P as Parser>::Value), <P as Parser>::Error>;
+
+/// Receives the result of a parser run by the trampoline.
+pub type Continuation<'a, P> = Box<dyn FnOnce(ParseResult<P>) -> Step<'a> + 'a>;
+
+/// Hands `result` to `continuation` on the next bounce instead of calling it directly, so long
+/// chains of continuations don't grow the native stack.
+pub fn resume<'a, T: 'a>(continuation: Box<dyn FnOnce(T) -> Step<'a> + 'a>, result: T) -> Step<'a> {
+    Step::more(move || continuation(result))
+}
+
+/// Parsers that can run on the trampoline, see `run`.
+///
+/// `'d` bounds the states, values and errors the parser produces, since they are kept in
+/// continuations while nested parsers run. Recursion has to go through `lazy` to be stack
+/// safe. Parsers created at parse time by `flat_map` and recursion through plain functions still
+/// run on the native stack.
+pub trait StackParser<'d>: Parser {
+    fn bounce<'a>(&'a self, state: Self::State, continuation: Continuation<'a, Self>) -> Step<'a>
+        where 'd: 'a;
+
+    fn boxed_stack(self) -> BoxedStackParser<'d, Self::Value, Self::State, Self::Error>
+        where Self: Sized + 'd
+    {
+        BoxedStackParser { inner: Box::new(self) }
+    }
+}
+
+/// Runs `parser` on the trampoline.
+pub fn run<'d, P: StackParser<'d> + ?Sized>(parser: &P, state: P::State) -> ParseResult<P>
+    where P::State: 'd,
+          P::Value: 'd,
+          P::Error: 'd
+{
+    fn start<'d: 'a, 'a, P: StackParser<'d> + ?Sized>(parser: &'a P, state: P::State,
+                                                   slot: Rc<RefCell<Option<ParseResult<P>>>>) -> Step<'a>
+        where P::State: 'd,
+              P::Value: 'd,
+              P::Error: 'd
+    {
+        parser.bounce(state, Box::new(move |parsed| {
+            *slot.borrow_mut() = Some(parsed);
+            Step::Done
+        }))
+    }
+
+    let result = Rc::new(RefCell::new(None));
+    let mut step = start(parser, state, result.clone());
+    while let Step::Continue(next) = step {
+        step = next();
+    }
+    let parsed = result.borrow_mut().take();
+    parsed.expect("the last continuation stores the result")
+}
+
+/// A type-erased parser that can still run on the trampoline.
+pub struct BoxedStackParser<'d, V, S, E> {
+    inner: Box<dyn StackParser<'d, Value=V, State=S, Error=E> + 'd>,
+}
+
+impl<'d, V, S, E> Parser for BoxedStackParser<'d, V, S, E> {
+    type Value = V;
+    type State = S;
+    type Error = E;
+
+    fn do_pars(&self, state: Self::State) -> Result<(Self::State, Self::Value), Self::Error> {
+        self.inner.do_pars(state)
+    }
+
+    fn describe(&self, grammar: &mut Grammar) -> Description {
+        self.inner.describe(grammar)
+    }
+}
+
+/// A boxed parser that lives for `'e` runs just as well for any shorter `'d`, which e.g. lets
+/// `lazy` build `'static` parsers inside a parser that only lives for `'d`.
+impl<'d, 'e: 'd, V, S, E> StackParser<'d> for BoxedStackParser<'e, V, S, E> {
+    fn bounce<'a>(&'a self, state: Self::State, continuation: Continuation<'a, Self>) -> Step<'a>
+        where 'd: 'a
+    {
+        self.inner.bounce(state, continuation)
+    }
+}
+
+impl<'d, P: StackParser<'d> + ?Sized> StackParser<'d> for Box<P> {
+    fn bounce<'a>(&'a self, state: Self::State, continuation: Continuation<'a, Self>) -> Step<'a>
+        where 'd: 'a
+    {
+        (**self).bounce(state, continuation)
+    }
+}
+
+impl<'d, P: StackParser<'d> + ?Sized> StackParser<'d> for Rc<P> {
+    fn bounce<'a>(&'a self, state: Self::State, continuation: Continuation<'a, Self>) -> Step<'a>
+        where 'd: 'a
+    {
+        (**self).bounce(state, continuation)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use crate::error::ParseError;
+    use crate::parser::{lazy, named, Parser};
+    use crate::text::location::Located;
+    use crate::text::text_parser::{integer, TextParser, TextState, token, whitespace};
+    use crate::trampoline::{BoxedStackParser, StackParser};
+
+    fn nesting<'d>() -> BoxedStackParser<'d, usize, TextState, Located<ParseError>> {
+        named("nesting", token("(")
+            .map2(lazy(nesting).many(), |_, children| 1 + children.into_iter().max().unwrap_or(0))
+            .ignore(token(")")))
+            .boxed_stack()
+    }
+
+    #[test]
+    fn same_result_as_native_parsing() {
+        let parser = nesting();
+        assert_eq!(parser.pars("(()(()))"), parser.pars_stack_safe("(()(()))"));
+        assert_eq!(parser.pars("(()(()"), parser.pars_stack_safe("(()(()"));
+
+        let sum = (integer(), whitespace(), token("+").or(token("-")), whitespace(), integer())
+            .map(|(lhs, _, sign, _, rhs)| if sign == "+" { lhs + rhs } else { lhs - rhs })
+            .ignore(token(";"))
+            .many();
+        assert_eq!(Ok(vec![3, -1]), sum.pars_stack_safe("1 + 2;3 - 4;"));
+    }
+
+    #[test]
+    fn deep_nesting_on_small_stack() {
+        let depth = 100_000;
+        let input = format!("{}{}", "(".repeat(depth), ")".repeat(depth));
+        let result = thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(move || nesting().pars_stack_safe(input))
+            .expect("thread spawned")
+            .join()
+            .expect("no stack overflow");
+        assert_eq!(Ok(depth), result);
+    }
+}
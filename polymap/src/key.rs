@@ -5,7 +5,6 @@ use std::marker::PhantomData;
 #[derive(Debug, Clone)]
 pub struct NormalKey<T> where T: 'static + ?Sized {
     type_id: TypeId,
-    tag: u64,
     type_param: PhantomData<T>,
     key: PolyMapKey
 }
@@ -15,7 +14,6 @@ impl <T> NormalKey<T> {
         let type_id = TypeId::of::<T>();
         Self {
             type_id,
-            tag,
             type_param: PhantomData {},
             key: PolyMapKey {
                 element_type: type_id,
@@ -62,3 +60,17 @@ pub trait Key<T> where T : Any + ?Sized {
     fn new_key(&self) -> PolyMapKey;
     fn element_type(&self) -> TypeId;
 }
+
+impl<T, K> Key<T> for &K where T: Any + ?Sized, K: Key<T> + ?Sized {
+    fn key(&self) -> &PolyMapKey {
+        (**self).key()
+    }
+
+    fn new_key(&self) -> PolyMapKey {
+        (**self).new_key()
+    }
+
+    fn element_type(&self) -> TypeId {
+        (**self).element_type()
+    }
+}
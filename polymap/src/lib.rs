@@ -1,10 +1,9 @@
-#![feature(const_fn)]
-#![feature(const_type_id)]
 
 use std::any::{Any};
 use std::collections::HashMap;
+use std::rc::Rc;
 
-pub use crate::key::Key;
+pub use crate::key::{Key, NormalKey};
 use crate::key::PolyMapKey;
 
 mod key;
@@ -14,8 +13,10 @@ mod test;
 
 // inspired by https://lucumr.pocoo.org/2022/1/6/rust-extension-map/
 
+/// Values are shared between clones, so cloning the map only copies the pointers.
+#[derive(Debug, Clone, Default)]
 pub struct PolyMap {
-    backing_map: HashMap<PolyMapKey<>, Box<dyn Any>>,
+    backing_map: HashMap<PolyMapKey<>, Rc<dyn Any>>,
 }
 
 impl PolyMap {
@@ -28,8 +29,13 @@ impl PolyMap {
             .map(|v| (**v).downcast_ref::<T>().expect("Correct type"))
     }
 
-    pub fn insert<T: Any, K: Key<T>>(&mut self, key: &K, value: T) -> Option<Box<T>> {
-        self.backing_map.insert(key.new_key(), Box::new(value))
+    pub fn insert<T: Any, K: Key<T>>(&mut self, key: &K, value: T) -> Option<Rc<T>> {
+        self.backing_map.insert(key.new_key(), Rc::new(value))
+            .map(|v| v.downcast::<T>().expect("Correct type"))
+    }
+
+    pub fn remove<T: Any, K: Key<T>>(&mut self, key: &K) -> Option<Rc<T>> {
+        self.backing_map.remove(key.key())
             .map(|v| v.downcast::<T>().expect("Correct type"))
     }
 }
@@ -13,4 +13,16 @@ fn basic_insert_and_retrieve() {
 
     assert_eq!(map.get(INT_KEY).unwrap(), &1);
     assert_eq!(map.get(STRING_KEY).unwrap(), &hello_world);
-}
\ No newline at end of file
+}
+
+#[test]
+fn clones_are_independent() {
+    let mut map = PolyMap::new();
+    map.insert(INT_KEY, 1);
+    let snapshot = map.clone();
+    map.insert(INT_KEY, 2);
+    map.remove(STRING_KEY);
+
+    assert_eq!(map.get(INT_KEY).unwrap(), &2);
+    assert_eq!(snapshot.get(INT_KEY).unwrap(), &1);
+}